@@ -0,0 +1,212 @@
+//! Measures how much a slow request holds up everyone else.
+//!
+//! Concurrent clients query their profile through a personal access token while a few requests
+//! get stuck on a row this tool keeps locked, like they would behind a slow query. Run it
+//! against a server using the same database:
+//!
+//! ```sh
+//! DATABASE_URL=postgres://... cargo run --release --example load_test
+//! ```
+//!
+//! `LOAD_TEST_URL` (`http://localhost:8080/graphql`), `LOAD_TEST_CLIENTS` (20) and
+//! `LOAD_TEST_SECONDS` (5) change the target and the load.
+
+use std::time::{Duration, Instant};
+
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+
+/// How many requests get stuck on the locked token at once, fewer than the connection pool holds.
+const STUCK_REQUESTS: usize = 3;
+
+struct Config {
+    database_url: String,
+    url: String,
+    clients: usize,
+    duration: Duration,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn secret() -> String {
+    let random = rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect::<String>();
+
+    format!("gg_{random}")
+}
+
+fn hash(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Creates a throwaway user with a token for the clients and one to get stuck on.
+fn seed(conn: &mut PgConnection, user_id: &str, tokens: &[&str]) {
+    diesel::sql_query(
+        "INSERT INTO users (id, name, created_at, is_guest) VALUES ($1, 'load test', now(), true)",
+    )
+    .bind::<Text, _>(user_id)
+    .execute(conn)
+    .unwrap();
+
+    for token in tokens {
+        diesel::sql_query(
+            "INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, created_at) \
+            VALUES (gen_random_uuid(), $1, 'load test', $2, 'read', now())",
+        )
+        .bind::<Text, _>(user_id)
+        .bind::<Text, _>(hash(token))
+        .execute(conn)
+        .unwrap();
+    }
+}
+
+/// Keeps the row of the token locked, so requests using it wait for the lock.
+fn lock_token(
+    database_url: String,
+    token: String,
+    duration: Duration,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut conn = PgConnection::establish(&database_url).unwrap();
+
+        let _ = conn.transaction::<(), diesel::result::Error, _>(|conn| {
+            diesel::sql_query("SELECT 1 FROM api_tokens WHERE token_hash = $1 FOR UPDATE")
+                .bind::<Text, _>(hash(&token))
+                .execute(conn)?;
+
+            std::thread::sleep(duration);
+
+            Err(diesel::result::Error::RollbackTransaction)
+        });
+    })
+}
+
+async fn query(client: &reqwest::Client, url: &str, token: &str) -> bool {
+    client
+        .post(url)
+        .bearer_auth(token)
+        .header("content-type", "application/json")
+        .body(r#"{"query":"{ profile { id name } }"}"#)
+        .send()
+        .await
+        .map(|response| response.status().is_success())
+        .unwrap_or(false)
+}
+
+/// Runs the clients until the time is up and returns the latency of every request.
+async fn run_clients(config: &Config, token: &str) -> (Vec<Duration>, usize) {
+    let client = reqwest::Client::new();
+    let deadline = Instant::now() + config.duration;
+
+    let clients = (0..config.clients).map(|_| async {
+        let mut latencies = Vec::new();
+        let mut failures = 0;
+
+        while Instant::now() < deadline {
+            let start = Instant::now();
+
+            if query(&client, &config.url, token).await {
+                latencies.push(start.elapsed());
+            } else {
+                failures += 1;
+            }
+        }
+
+        (latencies, failures)
+    });
+
+    futures::future::join_all(clients).await.into_iter().fold(
+        (Vec::new(), 0),
+        |(mut all, total_failures), (latencies, failures)| {
+            all.extend(latencies);
+            (all, total_failures + failures)
+        },
+    )
+}
+
+fn report(name: &str, config: &Config, mut latencies: Vec<Duration>, failures: usize) {
+    latencies.sort();
+
+    let percentile = |p: f64| {
+        latencies
+            .get(((latencies.len() as f64 * p) as usize).min(latencies.len().saturating_sub(1)))
+            .map(|latency| format!("{:>8.1}ms", latency.as_secs_f64() * 1000.0))
+            .unwrap_or_else(|| "       -".to_string())
+    };
+
+    println!(
+        "{name:<22} {:>8.0} req/s  p50 {}  p95 {}  p99 {}  max {}  failed {failures}",
+        latencies.len() as f64 / config.duration.as_secs_f64(),
+        percentile(0.5),
+        percentile(0.95),
+        percentile(0.99),
+        percentile(1.0),
+    );
+}
+
+fn main() {
+    dotenvy::dotenv().ok();
+
+    let config = Config {
+        database_url: std::env::var("DATABASE_URL").expect("DATABASE_URL needs to be set"),
+        url: env_or("LOAD_TEST_URL", "http://localhost:8080/graphql".to_string()),
+        clients: env_or("LOAD_TEST_CLIENTS", 20),
+        duration: Duration::from_secs(env_or("LOAD_TEST_SECONDS", 5)),
+    };
+
+    let user_id = format!("load-test:{}", uuid::Uuid::new_v4());
+    let (token, stuck_token) = (secret(), secret());
+
+    let mut conn = PgConnection::establish(&config.database_url).unwrap();
+    seed(&mut conn, &user_id, &[&token, &stuck_token]);
+
+    println!(
+        "{} clients for {}s against {}",
+        config.clients,
+        config.duration.as_secs(),
+        config.url
+    );
+
+    actix_web::rt::System::new().block_on(async {
+        let (latencies, failures) = run_clients(&config, &token).await;
+        report("unhindered", &config, latencies, failures);
+
+        // a little longer than the clients run, so the stuck requests outlast them
+        let lock = lock_token(
+            config.database_url.clone(),
+            stuck_token.clone(),
+            config.duration + Duration::from_secs(1),
+        );
+        actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+
+        let stuck_client = reqwest::Client::new();
+        for _ in 0..STUCK_REQUESTS {
+            let (client, url, token) = (
+                stuck_client.clone(),
+                config.url.clone(),
+                stuck_token.clone(),
+            );
+            actix_web::rt::spawn(async move { query(&client, &url, &token).await });
+        }
+
+        let (latencies, failures) = run_clients(&config, &token).await;
+        report("with stuck requests", &config, latencies, failures);
+
+        lock.join().unwrap();
+    });
+
+    diesel::sql_query("DELETE FROM users WHERE id = $1")
+        .bind::<Text, _>(&user_id)
+        .execute(&mut conn)
+        .unwrap();
+}
@@ -0,0 +1,4 @@
+ALTER TABLE lobbies
+    DROP CONSTRAINT IF EXISTS "lobbies_rematch_id_fkey",
+    DROP COLUMN IF EXISTS "rematch_id",
+    DROP COLUMN IF EXISTS "finished_at";
@@ -0,0 +1,7 @@
+ALTER TABLE lobbies
+    ADD COLUMN "finished_at" TIMESTAMPTZ NULL DEFAULT NULL,
+    ADD COLUMN "rematch_id" CHAR(10) NULL DEFAULT NULL,
+    ADD CONSTRAINT "lobbies_rematch_id_fkey" FOREIGN KEY ("rematch_id") REFERENCES lobbies ("id")
+        ON DELETE SET NULL ON UPDATE CASCADE;
+
+COMMENT ON COLUMN lobbies.rematch_id IS 'lobby that players were carried into after this one finished';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS user_guess_targets;
+DROP TABLE IF EXISTS user_stats;
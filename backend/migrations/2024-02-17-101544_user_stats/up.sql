@@ -0,0 +1,31 @@
+CREATE TABLE user_stats
+(
+    "user_id" VARCHAR(100) NOT NULL,
+    "games_played" INTEGER NOT NULL DEFAULT 0,
+    "games_won" INTEGER NOT NULL DEFAULT 0,
+    "guesses_made" INTEGER NOT NULL DEFAULT 0,
+    "guesses_correct" INTEGER NOT NULL DEFAULT 0,
+    "songs_guessed" INTEGER NOT NULL DEFAULT 0,
+    "songs_recognised" INTEGER NOT NULL DEFAULT 0,
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "user_stats_pkey" PRIMARY KEY ("user_id"),
+    CONSTRAINT "user_stats_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+COMMENT ON COLUMN user_stats.songs_guessed IS 'guesses other players made on songs submitted by this user';
+COMMENT ON COLUMN user_stats.songs_recognised IS 'correct guesses other players made on songs submitted by this user';
+
+CREATE TABLE user_guess_targets
+(
+    "user_id" VARCHAR(100) NOT NULL,
+    "target_id" VARCHAR(100) NOT NULL,
+    "guesses" INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT "user_guess_targets_pkey" PRIMARY KEY ("user_id", "target_id"),
+    CONSTRAINT "user_guess_targets_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "user_guess_targets_target_id_fkey" FOREIGN KEY ("target_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
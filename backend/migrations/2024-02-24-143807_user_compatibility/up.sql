@@ -0,0 +1,16 @@
+CREATE TABLE user_compatibility
+(
+    "guesser_id" VARCHAR(100) NOT NULL,
+    "owner_id" VARCHAR(100) NOT NULL,
+    "rounds" INTEGER NOT NULL DEFAULT 0,
+    "correct" INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT "user_compatibility_pkey" PRIMARY KEY ("guesser_id", "owner_id"),
+    CONSTRAINT "user_compatibility_guesser_id_fkey" FOREIGN KEY ("guesser_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "user_compatibility_owner_id_fkey" FOREIGN KEY ("owner_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+COMMENT ON COLUMN user_compatibility.rounds IS 'rounds in which the guesser guessed on content submitted by the owner';
+COMMENT ON COLUMN user_compatibility.correct IS 'rounds in which the guesser correctly identified the owner';
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS game_rounds;
+DROP TABLE IF EXISTS game_players;
+DROP TABLE IF EXISTS games;
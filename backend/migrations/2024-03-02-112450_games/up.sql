@@ -0,0 +1,45 @@
+-- finished games are archived as snapshots without foreign keys to users,
+-- so renaming or deleting an account doesn't alter the record of a game
+CREATE TABLE games
+(
+    "id" CHAR(10) NOT NULL,
+    "host_id" VARCHAR(100) NOT NULL,
+    "host_name" VARCHAR(70) NOT NULL,
+    "guessing_time" SMALLINT NOT NULL,
+    "started_at" TIMESTAMPTZ NOT NULL,
+    "finished_at" TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT "games_pkey" PRIMARY KEY ("id")
+);
+
+COMMENT ON COLUMN games.id IS 'id of the lobby the game was played in';
+
+CREATE TABLE game_players
+(
+    "game_id" CHAR(10) NOT NULL,
+    "player_id" VARCHAR(100) NOT NULL,
+    "name" VARCHAR(70) NOT NULL,
+    "score" INTEGER NOT NULL,
+    "is_winner" BOOLEAN NOT NULL DEFAULT FALSE,
+    "guesses" TEXT NOT NULL DEFAULT '',
+
+    CONSTRAINT "game_players_pkey" PRIMARY KEY ("game_id", "player_id"),
+    CONSTRAINT "game_players_game_id_fkey" FOREIGN KEY ("game_id") REFERENCES games ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "game_players_player_id_idx" ON game_players ("player_id");
+
+CREATE TABLE game_rounds
+(
+    "game_id" CHAR(10) NOT NULL,
+    "round_index" SMALLINT NOT NULL,
+    "owner_id" VARCHAR(100) NOT NULL,
+    "owner_name" VARCHAR(70) NOT NULL,
+    "content_type" VARCHAR(70) NULL DEFAULT NULL,
+    "content_data" VARCHAR(255) NULL DEFAULT NULL,
+
+    CONSTRAINT "game_rounds_pkey" PRIMARY KEY ("game_id", "round_index"),
+    CONSTRAINT "game_rounds_game_id_fkey" FOREIGN KEY ("game_id") REFERENCES games ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,8 @@
+DELETE FROM users WHERE "is_guest";
+
+-- registered users can be without an email as well, they keep an address that can't be reached
+UPDATE users SET "email" = md5("id") || '@users.invalid' WHERE "email" IS NULL;
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS "is_guest",
+    ALTER COLUMN "email" SET NOT NULL;
@@ -0,0 +1,5 @@
+ALTER TABLE users
+    ALTER COLUMN "email" DROP NOT NULL,
+    ADD COLUMN "is_guest" BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN users.is_guest IS 'temporary user that logged in without an OIDC provider';
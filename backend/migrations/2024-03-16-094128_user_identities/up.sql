@@ -0,0 +1,17 @@
+CREATE TABLE user_identities
+(
+    "provider" VARCHAR(50) NOT NULL,
+    "subject" VARCHAR(255) NOT NULL,
+    "user_id" VARCHAR(100) NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "user_identities_pkey" PRIMARY KEY ("provider", "subject"),
+    CONSTRAINT "user_identities_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "user_identities_user_id_idx" ON user_identities ("user_id");
+
+-- accounts so far were keyed by the subject of the single configured provider
+INSERT INTO user_identities ("provider", "subject", "user_id")
+SELECT 'default', "id", "id" FROM users WHERE NOT "is_guest";
@@ -0,0 +1,4 @@
+ALTER TABLE users
+    ADD COLUMN "is_admin" BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN users.is_admin IS 'may revoke the sessions of other users';
@@ -0,0 +1,20 @@
+CREATE TABLE api_tokens
+(
+    "id" UUID NOT NULL,
+    "user_id" VARCHAR(100) NOT NULL,
+    "name" VARCHAR(70) NOT NULL,
+    "token_hash" CHAR(64) NOT NULL,
+    "scopes" TEXT NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "last_used_at" TIMESTAMPTZ NULL,
+
+    CONSTRAINT "api_tokens_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "api_tokens_token_hash_key" UNIQUE ("token_hash"),
+    CONSTRAINT "api_tokens_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES users ("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "api_tokens_user_id_idx" ON api_tokens ("user_id");
+
+COMMENT ON COLUMN api_tokens.token_hash IS 'hex encoded SHA-256 of the token, the token itself is only shown once';
+COMMENT ON COLUMN api_tokens.scopes IS 'comma separated list of scopes';
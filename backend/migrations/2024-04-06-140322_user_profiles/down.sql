@@ -0,0 +1,4 @@
+ALTER TABLE users
+    DROP COLUMN IF EXISTS "avatar",
+    DROP COLUMN IF EXISTS "bio",
+    DROP COLUMN IF EXISTS "genres";
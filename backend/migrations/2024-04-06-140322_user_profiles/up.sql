@@ -0,0 +1,7 @@
+ALTER TABLE users
+    ADD COLUMN "avatar" VARCHAR(100) NULL,
+    ADD COLUMN "bio" VARCHAR(280) NULL,
+    ADD COLUMN "genres" TEXT NOT NULL DEFAULT '';
+
+COMMENT ON COLUMN users.avatar IS 'storage key of the uploaded avatar';
+COMMENT ON COLUMN users.genres IS 'comma separated list of genre tags';
@@ -1,57 +1,231 @@
+use crate::models::api_token::TokenScope;
 use crate::models::user::User;
+use crate::services::blocking;
 use crate::services::user::generate_random_name;
 use crate::AppState;
 use actix_session::{Session, SessionInsertError};
+use actix_web::error::{ErrorInternalServerError, ErrorNotFound};
 use actix_web::http::header;
 use actix_web::web::Data;
 use actix_web::{web, HttpResponse, ResponseError};
 use openidconnect::core::{
-    CoreAuthenticationFlow, CoreClient, CoreGenderClaim, CoreProviderMetadata,
-    CoreRequestTokenError, CoreTokenResponse,
+    CoreAuthenticationFlow, CoreClient, CoreGenderClaim, CoreRequestTokenError, CoreTokenResponse,
 };
 use openidconnect::reqwest::{async_http_client, HttpClientError};
 use openidconnect::{
     AccessTokenHash, AuthorizationCode, ClaimsVerificationError, ClientId, ClientSecret, CsrfToken,
-    EmptyAdditionalClaims, IdTokenClaims, IssuerUrl, Nonce, OAuth2TokenResponse, PkceCodeChallenge,
-    PkceCodeVerifier, RedirectUrl, Scope, SigningError, TokenResponse,
+    EmptyAdditionalClaims, EndSessionUrl, IdTokenClaims, IssuerUrl, LocalizedClaim, LogoutRequest,
+    Nonce, OAuth2TokenResponse, PkceCodeChallenge, PkceCodeVerifier, PostLogoutRedirectUrl,
+    ProviderMetadataWithLogout, RedirectUrl, Scope, SigningError, TokenResponse,
 };
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
 
 pub struct OpenIDConnectConfig {
     pub issuer_url: String,
     pub client_id: String,
     pub client_secret: String,
     pub redirect_url: String,
+    /// where the provider sends users back to after logging them out
+    pub post_logout_redirect_url: Option<String>,
 }
 
-pub async fn create_client(config: OpenIDConnectConfig) -> Result<CoreClient, Box<dyn Error>> {
-    let provider_metadata = CoreProviderMetadata::discover_async(
-        IssuerUrl::new(config.issuer_url).unwrap(),
-        async_http_client,
-    )
-    .await?;
-
-    Ok(CoreClient::from_provider_metadata(
-        provider_metadata,
-        ClientId::new(config.client_id),
-        Some(ClientSecret::new(config.client_secret)),
-    )
-    .set_redirect_uri(RedirectUrl::new(config.redirect_url)?))
+/// A standard claim that may hold a user's display name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameClaim {
+    GivenName,
+    Name,
+    PreferredUsername,
+    Nickname,
+}
+
+impl FromStr for NameClaim {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "given_name" => Ok(NameClaim::GivenName),
+            "name" => Ok(NameClaim::Name),
+            "preferred_username" => Ok(NameClaim::PreferredUsername),
+            "nickname" => Ok(NameClaim::Nickname),
+            other => Err(format!("unsupported name claim: {other}")),
+        }
+    }
+}
+
+type Claims = IdTokenClaims<EmptyAdditionalClaims, CoreGenderClaim>;
+
+/// Prefers the claim without a language tag but settles for any localized one.
+fn localized<T: std::ops::Deref<Target = String>>(
+    claim: Option<&LocalizedClaim<T>>,
+) -> Option<&str> {
+    let claim = claim?;
+
+    claim
+        .get(None)
+        .or_else(|| claim.iter().map(|(_, value)| value).next())
+        .map(|value| value.as_str())
+}
+
+/// Which claims of a provider are used to fill in a new user.
+#[derive(Debug, Clone)]
+pub struct ClaimMapping {
+    /// tried in order, the first non-empty one becomes the user's name
+    pub name_claims: Vec<NameClaim>,
+    /// refuse logins of users the provider doesn't send an email for
+    pub require_email: bool,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            name_claims: vec![
+                NameClaim::GivenName,
+                NameClaim::Name,
+                NameClaim::PreferredUsername,
+                NameClaim::Nickname,
+            ],
+            require_email: false,
+        }
+    }
+}
+
+impl ClaimMapping {
+    pub fn name(&self, claims: &Claims) -> Option<String> {
+        self.name_claims
+            .iter()
+            .filter_map(|claim| match claim {
+                NameClaim::GivenName => localized(claims.given_name()),
+                NameClaim::Name => localized(claims.name()),
+                NameClaim::PreferredUsername => claims.preferred_username().map(|u| u.as_str()),
+                NameClaim::Nickname => localized(claims.nickname()),
+            })
+            .map(|name| name.trim())
+            .find(|name| !name.is_empty())
+            .map(|name| name.to_owned())
+    }
+
+    pub fn email(&self, claims: &Claims) -> Result<Option<String>, AuthCallbackError> {
+        match claims.email() {
+            Some(email) => Ok(Some(email.to_string())),
+            None if self.require_email => Err(AuthCallbackError::MissingClaim("email")),
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct OidcProvider {
+    pub name: String,
+    pub client: CoreClient,
+    pub client_id: ClientId,
+    pub claims: ClaimMapping,
+    /// the provider's end_session_endpoint, if it supports RP-initiated logout
+    pub end_session_endpoint: Option<EndSessionUrl>,
+    pub post_logout_redirect_url: Option<PostLogoutRedirectUrl>,
+}
+
+impl OidcProvider {
+    pub async fn discover(
+        name: String,
+        config: OpenIDConnectConfig,
+        claims: ClaimMapping,
+    ) -> Result<Self, Box<dyn Error>> {
+        let provider_metadata = ProviderMetadataWithLogout::discover_async(
+            IssuerUrl::new(config.issuer_url)?,
+            async_http_client,
+        )
+        .await?;
+
+        let end_session_endpoint = provider_metadata
+            .additional_metadata()
+            .end_session_endpoint
+            .clone();
+
+        let client_id = ClientId::new(config.client_id);
+        let client = CoreClient::from_provider_metadata(
+            provider_metadata,
+            client_id.clone(),
+            Some(ClientSecret::new(config.client_secret)),
+        )
+        .set_redirect_uri(RedirectUrl::new(config.redirect_url)?);
+
+        let post_logout_redirect_url = config
+            .post_logout_redirect_url
+            .map(PostLogoutRedirectUrl::new)
+            .transpose()?;
+
+        Ok(Self {
+            name,
+            client,
+            client_id,
+            claims,
+            end_session_endpoint,
+            post_logout_redirect_url,
+        })
+    }
+}
+
+/// The configured OIDC providers, the first one being the default for [`login`].
+#[derive(Clone)]
+pub struct OidcProviders(Arc<Vec<OidcProvider>>);
+
+impl OidcProviders {
+    pub fn new(providers: Vec<OidcProvider>) -> Self {
+        Self(Arc::new(providers))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OidcProvider> {
+        self.0.iter().find(|provider| provider.name == name)
+    }
+
+    pub fn default_name(&self) -> Option<&str> {
+        self.0.first().map(|provider| provider.name.as_str())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthState {
+    provider: String,
     csrf_token: CsrfToken,
     pkce_verifier: PkceCodeVerifier,
     nonce: Nonce,
 }
 
+/// Logs in through the default provider.
 pub async fn login(
     context: Data<AppState>,
     session: Session,
-) -> Result<HttpResponse, SessionInsertError> {
-    let client = context.oidc_client.clone();
+) -> Result<HttpResponse, actix_web::Error> {
+    let provider = context
+        .oidc_providers
+        .default_name()
+        .ok_or_else(|| ErrorNotFound("No OIDC provider configured"))?
+        .to_owned();
+
+    start_login(&context, &session, provider)
+}
+
+/// Logs in through the provider named in the path, e.g. `/login/gitlab`.
+pub async fn login_with_provider(
+    context: Data<AppState>,
+    session: Session,
+    provider: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    start_login(&context, &session, provider.into_inner())
+}
+
+fn start_login(
+    context: &AppState,
+    session: &Session,
+    provider: String,
+) -> Result<HttpResponse, actix_web::Error> {
+    let client = &context
+        .oidc_providers
+        .get(&provider)
+        .ok_or_else(|| ErrorNotFound("Unknown OIDC provider"))?
+        .client;
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
     let mut request = client
@@ -73,6 +247,7 @@ pub async fn login(
     session.insert(
         "oidc-request",
         AuthState {
+            provider,
             csrf_token,
             pkce_verifier,
             nonce,
@@ -84,12 +259,36 @@ pub async fn login(
         .finish())
 }
 
-pub async fn logout(session: Session) -> Result<HttpResponse, SessionInsertError> {
+/// Ends the local session and, if the provider supports it, the session at the provider too.
+pub async fn logout(context: Data<AppState>, session: Session) -> HttpResponse {
+    let user_info = session.get::<UserInfo>("user_info").ok().flatten();
+
     session.purge();
 
-    Ok(HttpResponse::Found()
-        .append_header((header::LOCATION, "/"))
-        .finish())
+    let location = user_info
+        .and_then(|user_info| end_session_url(&context.oidc_providers, &user_info))
+        .unwrap_or_else(|| "/".to_string());
+
+    HttpResponse::Found()
+        .append_header((header::LOCATION, location))
+        .finish()
+}
+
+/// The provider's logout page for the user, `None` if there is nothing to log out from.
+fn end_session_url(providers: &OidcProviders, user_info: &UserInfo) -> Option<String> {
+    let provider = providers.get(user_info.provider.as_deref()?)?;
+    let mut request = LogoutRequest::from(provider.end_session_endpoint.clone()?)
+        .set_client_id(provider.client_id.clone());
+
+    if let Some(id_token) = user_info.token.as_ref().and_then(|token| token.id_token()) {
+        request = request.set_id_token_hint(id_token);
+    }
+
+    if let Some(ref redirect_url) = provider.post_logout_redirect_url {
+        request = request.set_post_logout_redirect_uri(redirect_url.clone());
+    }
+
+    Some(request.http_get_url().to_string())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -100,20 +299,154 @@ pub struct AuthCallback {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserInfo {
-    pub claims: IdTokenClaims<EmptyAdditionalClaims, CoreGenderClaim>,
-    pub token: CoreTokenResponse,
+    /// `None` for guests that didn't log in through an OIDC provider
+    pub provider: Option<String>,
+    pub claims: Option<IdTokenClaims<EmptyAdditionalClaims, CoreGenderClaim>>,
+    pub token: Option<CoreTokenResponse>,
+    /// when the provider's access token expires, `None` if it doesn't
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// what a personal access token may do, `None` for logged in users who may do anything
+    #[serde(default)]
+    pub scopes: Option<Vec<TokenScope>>,
     pub user: User,
 }
 
+/// Tokens are renewed a little before they expire, so they don't run out mid-request.
+const REFRESH_MARGIN_SECONDS: i64 = 30;
+
+impl UserInfo {
+    pub fn guest(user: User) -> Self {
+        Self {
+            provider: None,
+            claims: None,
+            token: None,
+            expires_at: None,
+            scopes: None,
+            user,
+        }
+    }
+
+    /// A user authenticated through a personal access token instead of a session.
+    pub fn api_token(user: User, scopes: Vec<TokenScope>) -> Self {
+        Self {
+            provider: None,
+            claims: None,
+            token: None,
+            expires_at: None,
+            scopes: Some(scopes),
+            user,
+        }
+    }
+
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        match self.scopes {
+            Some(ref scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
+
+    pub fn needs_refresh(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at.timestamp() - REFRESH_MARGIN_SECONDS <= now.timestamp(),
+            None => false,
+        }
+    }
+}
+
+fn expires_at(token: &CoreTokenResponse) -> Option<chrono::DateTime<chrono::Utc>> {
+    let expires_in = chrono::Duration::from_std(token.expires_in()?).ok()?;
+
+    Some(chrono::Utc::now() + expires_in)
+}
+
+/// Renews the access token through the refresh token the provider handed out on login.
+pub async fn refresh(
+    providers: &OidcProviders,
+    user_info: UserInfo,
+) -> Result<UserInfo, RefreshError> {
+    let provider = user_info
+        .provider
+        .as_deref()
+        .and_then(|provider| providers.get(provider))
+        .ok_or(RefreshError::UnknownProvider)?;
+    let old_token = user_info
+        .token
+        .as_ref()
+        .ok_or(RefreshError::MissingRefreshToken)?;
+    let refresh_token = old_token
+        .refresh_token()
+        .ok_or(RefreshError::MissingRefreshToken)?;
+
+    let mut token = provider
+        .client
+        .exchange_refresh_token(refresh_token)
+        .request_async(async_http_client)
+        .await
+        .map_err(RefreshError::FailedRequestToken)?;
+
+    // providers may leave out what didn't change
+    if token.refresh_token().is_none() {
+        token.set_refresh_token(old_token.refresh_token().cloned());
+    }
+
+    if token.id_token().is_none() {
+        token.set_extra_fields(old_token.extra_fields().clone());
+    }
+
+    Ok(UserInfo {
+        expires_at: expires_at(&token),
+        token: Some(token),
+        ..user_info
+    })
+}
+
+/// Logs in as a temporary guest user, which can be upgraded later by logging in through [`login`].
+pub async fn guest_login(
+    context: Data<AppState>,
+    session: Session,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Ok(Some(_)) = session.get::<UserInfo>("user_info") {
+        return Ok(HttpResponse::Found()
+            .append_header((header::LOCATION, "/"))
+            .finish());
+    }
+
+    let guest = User {
+        id: format!("guest:{}", uuid::Uuid::new_v4()),
+        email: None,
+        name: generate_random_name(),
+        created_at: chrono::Utc::now().naive_utc(),
+        is_guest: true,
+        is_admin: false,
+        avatar: None,
+        bio: None,
+        genres: String::new(),
+    };
+
+    let user_service = context.user_service.clone();
+    let new_guest = guest.clone();
+
+    blocking(move || user_service.register(new_guest))
+        .await
+        .map_err(|_| ErrorInternalServerError("Failed to register guest"))?;
+
+    session.insert("user_info", UserInfo::guest(guest))?;
+
+    Ok(HttpResponse::Found()
+        .append_header((header::LOCATION, "/"))
+        .finish())
+}
+
 pub async fn auth_callback(
     context: Data<AppState>,
     session: Session,
     params: web::Query<AuthCallback>,
 ) -> Result<HttpResponse, AuthCallbackError> {
-    let client = context.oidc_client.clone();
     let AuthCallback { code, state } = params.into_inner();
 
     let AuthState {
+        provider,
         csrf_token,
         pkce_verifier,
         nonce,
@@ -122,6 +455,15 @@ pub async fn auth_callback(
         .ok_or(AuthCallbackError::MissingState)?
         .map_err(|_| AuthCallbackError::MissingState)?;
 
+    let OidcProvider {
+        client,
+        claims: claim_mapping,
+        ..
+    } = context
+        .oidc_providers
+        .get(&provider)
+        .ok_or(AuthCallbackError::UnknownProvider)?;
+
     if state.secret() != csrf_token.secret() {
         return Err(AuthCallbackError::InvalidState);
     }
@@ -152,30 +494,55 @@ pub async fn auth_callback(
         }
     }
 
-    let email = claims.email().unwrap().to_string();
-    let name = match claims.given_name() {
-        Some(name_claim) => name_claim.get(None).unwrap().to_string(),
-        None => generate_random_name(),
-    };
+    let email = claim_mapping.email(claims)?;
+    let name = claim_mapping
+        .name(claims)
+        .and_then(|name| context.user_service.name_policy().sanitize(&name))
+        .unwrap_or_else(generate_random_name);
 
     let new_user = User {
-        id: claims.subject().to_string(),
+        id: uuid::Uuid::new_v4().to_string(),
         email,
         name,
         created_at: chrono::Utc::now().naive_utc(),
+        is_guest: false,
+        is_admin: false,
+        avatar: None,
+        bio: None,
+        genres: String::new(),
+    };
+
+    // guests logging in keep everything they played so far
+    let guest = match session.get::<UserInfo>("user_info") {
+        Ok(Some(UserInfo { user: guest, .. })) if guest.is_guest => Some(guest),
+        _ => None,
     };
 
-    context
-        .user_service
-        .register(new_user.clone())
-        .map_err(|_| AuthCallbackError::UserServiceError)?;
+    let user_service = context.user_service.clone();
+    let identity = (provider.clone(), claims.subject().to_string());
+
+    let new_user = blocking(move || {
+        let (provider, subject) = identity;
+        let user = user_service.register_identity(&provider, &subject, new_user)?;
+
+        if let Some(guest) = guest {
+            user_service.upgrade_guest(&guest, &user)?;
+        }
+
+        Ok(user)
+    })
+    .await
+    .map_err(|_| AuthCallbackError::UserServiceError)?;
 
     session
         .insert(
             "user_info",
             UserInfo {
-                claims: claims.clone(),
-                token: token.clone(),
+                provider: Some(provider),
+                claims: Some(claims.clone()),
+                expires_at: expires_at(&token),
+                token: Some(token.clone()),
+                scopes: None,
                 user: new_user,
             },
         )
@@ -188,6 +555,9 @@ pub async fn auth_callback(
 
 #[derive(Debug)]
 pub enum AuthCallbackError {
+    /// The provider the login was started with isn't configured (anymore)
+    UnknownProvider,
+
     /// There is no `state` in the user's session
     /// Maybe he hasn't visited [`login`] yet?
     MissingState,
@@ -210,6 +580,9 @@ pub enum AuthCallbackError {
     /// The claims' access token doesn't match the oidc's
     InvalidAccessTokenHash,
 
+    /// The provider didn't send a claim the [`ClaimMapping`] requires
+    MissingClaim(&'static str),
+
     /// Error from [`Session::insert`]
     SessionInsert(SessionInsertError),
 
@@ -220,6 +593,7 @@ pub enum AuthCallbackError {
 impl std::fmt::Display for AuthCallbackError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            AuthCallbackError::UnknownProvider => write!(f, "Unknown OIDC provider"),
             AuthCallbackError::MissingState => write!(f, "State is missing from user session"),
             AuthCallbackError::InvalidState => write!(f, "State in user session is invalid"),
             AuthCallbackError::FailedRequestToken(err) => {
@@ -237,6 +611,9 @@ impl std::fmt::Display for AuthCallbackError {
             AuthCallbackError::InvalidAccessTokenHash => {
                 write!(f, "The access token's hash doesn't match")
             }
+            AuthCallbackError::MissingClaim(claim) => {
+                write!(f, "The provider didn't send the required claim {claim}")
+            }
             AuthCallbackError::SessionInsert(err) => {
                 write!(f, "Failed to set token in user session: {err}")
             }
@@ -250,6 +627,7 @@ impl std::fmt::Display for AuthCallbackError {
 impl Error for AuthCallbackError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
+            AuthCallbackError::UnknownProvider => None,
             AuthCallbackError::MissingState => None,
             AuthCallbackError::InvalidState => None,
             AuthCallbackError::FailedRequestToken(err) => Some(err),
@@ -257,9 +635,161 @@ impl Error for AuthCallbackError {
             AuthCallbackError::MissingIdToken => None,
             AuthCallbackError::CreateAccessTokenHash(err) => Some(err),
             AuthCallbackError::InvalidAccessTokenHash => None,
+            AuthCallbackError::MissingClaim(_) => None,
             AuthCallbackError::InvalidIdToken(err) => Some(err),
             AuthCallbackError::UserServiceError => None,
         }
     }
 }
 impl ResponseError for AuthCallbackError {}
+
+#[derive(Debug)]
+pub enum RefreshError {
+    /// The provider the user logged in with isn't configured (anymore)
+    UnknownProvider,
+
+    /// The provider didn't hand out a refresh token on login
+    MissingRefreshToken,
+
+    /// The provider refused to renew the token, e.g. because the user logged out there
+    FailedRequestToken(CoreRequestTokenError<HttpClientError>),
+}
+
+impl std::fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefreshError::UnknownProvider => write!(f, "Unknown OIDC provider"),
+            RefreshError::MissingRefreshToken => write!(f, "No refresh token in user session"),
+            RefreshError::FailedRequestToken(err) => {
+                write!(f, "Failed to refresh token: {err}")
+            }
+        }
+    }
+}
+
+impl Error for RefreshError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RefreshError::UnknownProvider => None,
+            RefreshError::MissingRefreshToken => None,
+            RefreshError::FailedRequestToken(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openidconnect::core::CoreGenderClaim;
+    use openidconnect::{
+        Audience, EmptyAdditionalClaims, EndUserGivenName, EndUserName, EndUserNickname,
+        EndUserUsername, IdTokenClaims, IssuerUrl, LanguageTag, LocalizedClaim, StandardClaims,
+        SubjectIdentifier,
+    };
+
+    use crate::models::user::User;
+
+    use super::{AuthCallbackError, ClaimMapping, Claims, NameClaim, UserInfo};
+
+    fn claims(standard_claims: StandardClaims<CoreGenderClaim>) -> Claims {
+        IdTokenClaims::new(
+            IssuerUrl::new("https://issuer.example.com".to_string()).unwrap(),
+            vec![Audience::new("grooveguessr".to_string())],
+            chrono::Utc::now(),
+            chrono::Utc::now(),
+            standard_claims,
+            EmptyAdditionalClaims {},
+        )
+    }
+
+    fn standard_claims() -> StandardClaims<CoreGenderClaim> {
+        StandardClaims::new(SubjectIdentifier::new("subject".to_string()))
+    }
+
+    #[test]
+    fn name_falls_back_through_claims() {
+        let claims = claims(
+            standard_claims()
+                .set_given_name(Some(EndUserGivenName::new(" ".to_string()).into()))
+                .set_preferred_username(Some(EndUserUsername::new("groovy".to_string())))
+                .set_nickname(Some(EndUserNickname::new("nick".to_string()).into())),
+        );
+
+        assert_eq!(
+            ClaimMapping::default().name(&claims),
+            Some("groovy".to_string())
+        );
+    }
+
+    #[test]
+    fn name_accepts_localized_claims_only() {
+        let name = [(
+            Some(LanguageTag::new("de".to_string())),
+            EndUserName::new("Hans".to_string()),
+        )]
+        .into_iter()
+        .collect::<LocalizedClaim<EndUserName>>();
+
+        let claims = claims(standard_claims().set_name(Some(name)));
+
+        assert_eq!(
+            ClaimMapping::default().name(&claims),
+            Some("Hans".to_string())
+        );
+    }
+
+    #[test]
+    fn name_uses_configured_claims_only() {
+        let claims = claims(
+            standard_claims().set_nickname(Some(EndUserNickname::new("nick".to_string()).into())),
+        );
+
+        let mapping = ClaimMapping {
+            name_claims: vec!["given_name".parse::<NameClaim>().unwrap()],
+            ..Default::default()
+        };
+
+        assert_eq!(mapping.name(&claims), None);
+        assert!("surname".parse::<NameClaim>().is_err());
+    }
+
+    #[test]
+    fn email_is_only_required_if_configured() {
+        let claims = claims(standard_claims());
+
+        assert_eq!(ClaimMapping::default().email(&claims).unwrap(), None);
+
+        let mapping = ClaimMapping {
+            require_email: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            mapping.email(&claims),
+            Err(AuthCallbackError::MissingClaim("email"))
+        ));
+    }
+
+    #[test]
+    fn tokens_are_refreshed_shortly_before_they_expire() {
+        let now = chrono::Utc::now();
+        let mut user_info = UserInfo::guest(User {
+            id: "guest:1".to_string(),
+            email: None,
+            name: "lazy dog".to_string(),
+            created_at: now.naive_utc(),
+            is_guest: true,
+            is_admin: false,
+            avatar: None,
+            bio: None,
+            genres: String::new(),
+        });
+
+        assert!(!user_info.needs_refresh(now));
+
+        user_info.expires_at = Some(now + chrono::Duration::try_minutes(5).unwrap());
+        assert!(!user_info.needs_refresh(now));
+
+        user_info.expires_at = Some(now + chrono::Duration::try_seconds(10).unwrap());
+        assert!(user_info.needs_refresh(now));
+    }
+}
@@ -1,20 +1,39 @@
 use std::future::{ready, Ready};
+use std::rc::Rc;
 
 use actix_session::SessionExt;
 use actix_web::{
     body::EitherBody,
     dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpResponse,
+    http::header,
+    web::Data,
+    Error, HttpMessage, HttpResponse,
 };
 use futures_util::future::LocalBoxFuture;
 
-use crate::auth::UserInfo;
+use crate::auth::{self, UserInfo};
+use crate::services::blocking;
+use crate::AppState;
+
+/// The secret of a personal access token sent as `Authorization: Bearer <secret>`.
+fn bearer_token(request: &ServiceRequest) -> Option<String> {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let (scheme, secret) = header.split_once(' ')?;
+
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| secret.trim().to_owned())
+}
 
 pub struct AuthRequired;
 
 impl<S, B> Transform<S, ServiceRequest> for AuthRequired
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -25,16 +44,18 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthRequiredMiddleware { service }))
+        ready(Ok(AuthRequiredMiddleware {
+            service: Rc::new(service),
+        }))
     }
 }
 pub struct AuthRequiredMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthRequiredMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -45,25 +66,75 @@ where
     dev::forward_ready!(service);
 
     fn call(&self, request: ServiceRequest) -> Self::Future {
-        let session = request.request().get_session();
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            if let Some(secret) = bearer_token(&request) {
+                let authenticated = match request.app_data::<Data<AppState>>() {
+                    Some(context) => {
+                        let api_token_service = context.api_token_service.clone();
+
+                        blocking(move || api_token_service.authenticate(&secret))
+                            .await
+                            .unwrap_or_else(|err| {
+                                warn!("Failed to check personal access token: {err}");
+                                None
+                            })
+                    }
+                    None => None,
+                };
+
+                let Some((token, user)) = authenticated else {
+                    let res = request.into_response(HttpResponse::Unauthorized().finish());
+
+                    return Ok(ServiceResponse::map_into_right_body(res));
+                };
+
+                // picked up by the handlers in place of the session's user info
+                request
+                    .extensions_mut()
+                    .insert(UserInfo::api_token(user, token.scopes()));
+
+                let res = service.call(request).await?;
+
+                return Ok(ServiceResponse::map_into_left_body(res));
+            }
+
+            let session = request.request().get_session();
+
+            let user_info = session.get::<UserInfo>("user_info").unwrap_or_default();
+
+            let is_logged_in = match user_info {
+                Some(user_info) if user_info.needs_refresh(chrono::Utc::now()) => {
+                    // without the app state there's no provider to ask, so the session just ends
+                    let refreshed = match request.app_data::<Data<AppState>>() {
+                        Some(context) => auth::refresh(&context.oidc_providers, user_info).await,
+                        None => Err(auth::RefreshError::UnknownProvider),
+                    };
+
+                    match refreshed {
+                        Ok(user_info) => session.insert("user_info", user_info).is_ok(),
+                        Err(err) => {
+                            info!("Ending session after failing to refresh the token: {err}");
+                            session.purge();
 
-        let is_logged_in = match session.get::<UserInfo>("user_info") {
-            Ok(x) => x.is_some(),
-            Err(_) => false,
-        };
+                            false
+                        }
+                    }
+                }
+                Some(_) => true,
+                None => false,
+            };
 
-        if !is_logged_in {
-            let res = request.into_response(HttpResponse::Unauthorized().finish());
+            if !is_logged_in {
+                let res = request.into_response(HttpResponse::Unauthorized().finish());
 
-            Box::pin(async { Ok(ServiceResponse::map_into_right_body(res)) })
-        } else {
-            let fut = self.service.call(request);
+                return Ok(ServiceResponse::map_into_right_body(res));
+            }
 
-            Box::pin(async move {
-                let res = fut.await?;
+            let res = service.call(request).await?;
 
-                Ok(ServiceResponse::map_into_left_body(res))
-            })
-        }
+            Ok(ServiceResponse::map_into_left_body(res))
+        })
     }
 }
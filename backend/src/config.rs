@@ -0,0 +1,583 @@
+//! Settings of the server, read from environment variables and an optional TOML file. Everything
+//! is validated at startup and all problems are reported at once, instead of failing on the first
+//! missing variable.
+//!
+//! Every setting has an environment variable and a key in the file, environment variables take
+//! precedence:
+//!
+//! ```toml
+//! bind_address = "0.0.0.0:8080"         # BIND_ADDRESS
+//! workers = 4                           # WORKERS, one per physical core by default
+//! secret_key = "..."                    # SECRET_KEY, at least 64 bytes
+//! avatar_dir = "avatars"                # AVATAR_DIR
+//! name_deny_list = "names.txt"          # NAME_DENY_LIST
+//! log_format = "text"                   # LOG_FORMAT, text or json
+//!
+//! [database]
+//! url = "postgres://..."                # DATABASE_URL
+//! pool_size = 10                        # DATABASE_POOL_SIZE
+//!
+//! [redis]
+//! url = "redis://127.0.0.1:6379"        # REDIS_URL
+//!
+//! [session]
+//! ttl_hours = 24                        # SESSION_TTL_HOURS
+//!
+//! [game]
+//! guessing_time = 80                    # GUESSING_TIME
+//! presence_ttl_seconds = 5              # PRESENCE_TTL_SECONDS
+//!
+//! [oidc.default]                        # OIDC_PROVIDERS lists the providers
+//! issuer_url = "https://..."            # OIDC_ISSUER_URL, OIDC_<NAME>_ISSUER_URL for others
+//! client_id = "..."                     # OIDC_CLIENT_ID
+//! client_secret = "..."                 # OIDC_CLIENT_SECRET
+//! redirect_url = "https://..."          # OIDC_REDIRECT_URL
+//! post_logout_redirect_url = "https://..." # OIDC_POST_LOGOUT_REDIRECT_URL
+//! name_claims = ["given_name", "name"]  # OIDC_NAME_CLAIMS
+//! require_email = false                 # OIDC_REQUIRE_EMAIL
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::auth::{ClaimMapping, NameClaim, OpenIDConnectConfig};
+use crate::telemetry::LogFormat;
+
+/// Read if `CONFIG_FILE` isn't set and it exists in the working directory.
+const DEFAULT_CONFIG_FILE: &str = "grooveguessr.toml";
+
+/// `Key::from` panics on anything shorter.
+const MIN_SECRET_KEY_BYTES: usize = 64;
+
+/// Everything that was wrong with the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration:")?;
+
+        for problem in &self.0 {
+            write!(f, "\n  - {problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Defaults of the games played on this server.
+#[derive(Debug, Clone)]
+pub struct GameConfig {
+    /// seconds new lobbies give players to guess
+    pub guessing_time: i16,
+    /// players count as present for this long after their last request to the lobby
+    pub presence_ttl_seconds: u64,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            guessing_time: 80,
+            presence_ttl_seconds: 5,
+        }
+    }
+}
+
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub connect: OpenIDConnectConfig,
+    pub claims: ClaimMapping,
+}
+
+pub struct Config {
+    pub bind_address: SocketAddr,
+    /// HTTP workers, actix picks one per physical core if `None`
+    pub workers: Option<usize>,
+    pub secret_key: String,
+    pub avatar_dir: String,
+    /// file with names players may not use in addition to the built-in ones
+    pub name_deny_list: Option<String>,
+    pub log_format: LogFormat,
+    pub database_url: String,
+    pub database_pool_size: u32,
+    pub redis_url: String,
+    pub session_ttl_hours: i64,
+    pub game: GameConfig,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+}
+
+impl Config {
+    /// Reads the configuration from the environment and the TOML file `CONFIG_FILE` points to,
+    /// `grooveguessr.toml` if that exists otherwise. Not configuring any OIDC provider is only
+    /// fine if `oidc_optional` is set, e.g. because dev auth is enabled.
+    pub fn load(oidc_optional: bool) -> Result<Self, ConfigError> {
+        let path = std::env::var("CONFIG_FILE").ok().or_else(|| {
+            Path::new(DEFAULT_CONFIG_FILE)
+                .exists()
+                .then(|| DEFAULT_CONFIG_FILE.to_owned())
+        });
+
+        let file = match path {
+            Some(path) => match std::fs::read_to_string(&path) {
+                Ok(contents) => Some((path, contents)),
+                Err(err) => return Err(ConfigError(vec![format!("Error reading {path}: {err}")])),
+            },
+            None => None,
+        };
+
+        Self::from_sources(|key| std::env::var(key).ok(), file, oidc_optional)
+    }
+
+    fn from_sources(
+        env: impl Fn(&str) -> Option<String>,
+        file: Option<(String, String)>,
+        oidc_optional: bool,
+    ) -> Result<Self, ConfigError> {
+        let mut sources = Sources {
+            env,
+            file_name: String::new(),
+            table: toml::Table::new(),
+            problems: Vec::new(),
+        };
+
+        if let Some((file_name, contents)) = file {
+            match contents.parse::<toml::Table>() {
+                Ok(table) => sources.table = table,
+                Err(err) => sources.problems.push(format!("{file_name}: {err}")),
+            }
+
+            sources.file_name = file_name;
+        }
+
+        let default_game = GameConfig::default();
+
+        let config = Config {
+            bind_address: sources
+                .optional("BIND_ADDRESS", "bind_address", |value| {
+                    value
+                        .parse::<SocketAddr>()
+                        .map_err(|_| "expected an address like 0.0.0.0:8080".to_owned())
+                })
+                .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 8080))),
+            workers: sources.optional("WORKERS", "workers", positive::<usize>),
+            secret_key: sources
+                .required("SECRET_KEY", "secret_key", |value| {
+                    if value.len() < MIN_SECRET_KEY_BYTES {
+                        return Err(format!(
+                            "needs to be at least {MIN_SECRET_KEY_BYTES} bytes long, got {}",
+                            value.len()
+                        ));
+                    }
+
+                    Ok(value.to_owned())
+                })
+                .unwrap_or_default(),
+            avatar_dir: sources
+                .optional("AVATAR_DIR", "avatar_dir", non_empty)
+                .unwrap_or_else(|| "avatars".to_owned()),
+            name_deny_list: sources.optional("NAME_DENY_LIST", "name_deny_list", |value| {
+                if !Path::new(value).is_file() {
+                    return Err(format!("{value} doesn't exist"));
+                }
+
+                Ok(value.to_owned())
+            }),
+            log_format: sources
+                .optional("LOG_FORMAT", "log_format", |value| {
+                    value.parse::<LogFormat>()
+                })
+                .unwrap_or(LogFormat::Text),
+            database_url: sources
+                .required("DATABASE_URL", "database.url", |value| {
+                    url_with_scheme(value, &["postgres", "postgresql"])
+                })
+                .unwrap_or_default(),
+            database_pool_size: sources
+                .optional("DATABASE_POOL_SIZE", "database.pool_size", positive::<u32>)
+                .unwrap_or(10),
+            redis_url: sources
+                .required("REDIS_URL", "redis.url", |value| {
+                    url_with_scheme(value, &["redis", "rediss", "redis+unix", "unix"])
+                })
+                .unwrap_or_default(),
+            session_ttl_hours: sources
+                .optional("SESSION_TTL_HOURS", "session.ttl_hours", positive::<i64>)
+                .unwrap_or(24),
+            game: GameConfig {
+                guessing_time: sources
+                    .optional("GUESSING_TIME", "game.guessing_time", positive::<i16>)
+                    .unwrap_or(default_game.guessing_time),
+                presence_ttl_seconds: sources
+                    .optional(
+                        "PRESENCE_TTL_SECONDS",
+                        "game.presence_ttl_seconds",
+                        positive::<u64>,
+                    )
+                    .unwrap_or(default_game.presence_ttl_seconds),
+            },
+            oidc_providers: sources.oidc_providers(oidc_optional),
+        };
+
+        if sources.problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(sources.problems))
+        }
+    }
+}
+
+/// Where settings are looked up, collecting the problems found along the way.
+struct Sources<E> {
+    env: E,
+    file_name: String,
+    table: toml::Table,
+    problems: Vec<String>,
+}
+
+impl<E: Fn(&str) -> Option<String>> Sources<E> {
+    /// The value of a setting along with where it came from, lists in the file are read like
+    /// comma separated variables.
+    fn raw(&self, env: &str, path: &str) -> Option<(String, String)> {
+        if let Some(value) = (self.env)(env) {
+            return Some((env.to_owned(), value));
+        }
+
+        let mut keys = path.split('.');
+        let mut value = self.table.get(keys.next()?)?;
+
+        for key in keys {
+            value = value.get(key)?;
+        }
+
+        let value = match value {
+            toml::Value::String(value) => value.clone(),
+            toml::Value::Array(values) => values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+            other => other.to_string(),
+        };
+
+        Some((format!("{path} in {}", self.file_name), value))
+    }
+
+    /// `None` if the setting isn't there or is invalid, which is recorded as a problem.
+    fn optional<T>(
+        &mut self,
+        env: &str,
+        path: &str,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        let (origin, value) = self.raw(env, path)?;
+
+        match parse(&value) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.problems.push(format!("{origin} is invalid: {err}"));
+
+                None
+            }
+        }
+    }
+
+    fn required<T>(
+        &mut self,
+        env: &str,
+        path: &str,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        if self.raw(env, path).is_none() {
+            self.problems.push(match self.file_name.as_str() {
+                "" => format!("{env} needs to be set"),
+                file_name => format!("{env} or {path} in {file_name} needs to be set"),
+            });
+
+            return None;
+        }
+
+        self.optional(env, path, parse)
+    }
+
+    /// The providers listed in `OIDC_PROVIDERS`, or the `oidc` tables of the file. Without either
+    /// there is a single provider called `default` configured through the plain `OIDC_*`
+    /// variables, unless providers are optional and none of those is set.
+    fn oidc_providers(&mut self, optional: bool) -> Vec<OidcProviderConfig> {
+        let names = match (self.env)("OIDC_PROVIDERS") {
+            Some(names) => names
+                .split(',')
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            None => match self.table.get("oidc").and_then(|oidc| oidc.as_table()) {
+                Some(providers) => providers.keys().cloned().collect(),
+                None if optional && (self.env)("OIDC_ISSUER_URL").is_none() => Vec::new(),
+                None => vec!["default".to_owned()],
+            },
+        };
+
+        names
+            .into_iter()
+            .map(|name| self.oidc_provider(name))
+            .collect()
+    }
+
+    fn oidc_provider(&mut self, name: String) -> OidcProviderConfig {
+        let prefix = match name.as_str() {
+            "default" => "OIDC_".to_owned(),
+            _ => format!("OIDC_{}_", name.to_uppercase().replace('-', "_")),
+        };
+        let env = |key: &str| format!("{prefix}{key}");
+        let path = |key: &str| format!("oidc.{name}.{}", key.to_lowercase());
+
+        let mut required = |key: &str, parse: fn(&str) -> Result<String, String>| {
+            self.required(&env(key), &path(key), parse)
+                .unwrap_or_default()
+        };
+
+        let connect = OpenIDConnectConfig {
+            issuer_url: required("ISSUER_URL", http_url),
+            client_id: required("CLIENT_ID", non_empty),
+            client_secret: required("CLIENT_SECRET", non_empty),
+            redirect_url: required("REDIRECT_URL", http_url),
+            post_logout_redirect_url: self.optional(
+                &env("POST_LOGOUT_REDIRECT_URL"),
+                &path("POST_LOGOUT_REDIRECT_URL"),
+                http_url,
+            ),
+        };
+
+        let mut claims = ClaimMapping::default();
+
+        if let Some(name_claims) =
+            self.optional(&env("NAME_CLAIMS"), &path("NAME_CLAIMS"), |value| {
+                value.split(',').map(NameClaim::from_str).collect()
+            })
+        {
+            claims.name_claims = name_claims;
+        }
+
+        if let Some(require_email) =
+            self.optional(&env("REQUIRE_EMAIL"), &path("REQUIRE_EMAIL"), parse::<bool>)
+        {
+            claims.require_email = require_email;
+        }
+
+        OidcProviderConfig {
+            name,
+            connect,
+            claims,
+        }
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value.trim().parse::<T>().map_err(|err| err.to_string())
+}
+
+fn positive<T: FromStr + PartialOrd + Default>(value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    let number = parse::<T>(value)?;
+
+    if number <= T::default() {
+        return Err("needs to be greater than 0".to_owned());
+    }
+
+    Ok(number)
+}
+
+fn non_empty(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        return Err("must not be empty".to_owned());
+    }
+
+    Ok(value.to_owned())
+}
+
+fn url_with_scheme(value: &str, schemes: &[&str]) -> Result<String, String> {
+    let url = url::Url::parse(value).map_err(|err| err.to_string())?;
+
+    if !schemes.contains(&url.scheme()) {
+        return Err(format!(
+            "expected a {} URL, got {}",
+            schemes.join(" or "),
+            url.scheme()
+        ));
+    }
+
+    Ok(value.to_owned())
+}
+
+fn http_url(value: &str) -> Result<String, String> {
+    url_with_scheme(value, &["http", "https"])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Config;
+    use crate::telemetry::LogFormat;
+
+    const SECRET_KEY: &str = "0123456789012345678901234567890123456789012345678901234567890123";
+
+    fn load(
+        env: &[(&str, &str)],
+        file: Option<&str>,
+        oidc_optional: bool,
+    ) -> Result<Config, Vec<String>> {
+        let env = env
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<String, String>>();
+
+        Config::from_sources(
+            |key| env.get(key).cloned(),
+            file.map(|contents| ("test.toml".to_owned(), contents.to_owned())),
+            oidc_optional,
+        )
+        .map_err(|err| err.0)
+    }
+
+    #[test]
+    fn defaults_fill_in_what_isnt_set() {
+        let config = load(
+            &[
+                ("SECRET_KEY", SECRET_KEY),
+                ("DATABASE_URL", "postgres://localhost/grooveguessr"),
+                ("REDIS_URL", "redis://127.0.0.1:6379"),
+            ],
+            None,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(config.bind_address.to_string(), "0.0.0.0:8080");
+        assert_eq!(config.workers, None);
+        assert_eq!(config.database_pool_size, 10);
+        assert_eq!(config.session_ttl_hours, 24);
+        assert_eq!(config.game.guessing_time, 80);
+        assert_eq!(config.avatar_dir, "avatars");
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert!(config.oidc_providers.is_empty());
+    }
+
+    #[test]
+    fn variables_take_precedence_over_the_file() {
+        let file = r#"
+            bind_address = "127.0.0.1:3000"
+            log_format = "json"
+            secret_key = "0123456789012345678901234567890123456789012345678901234567890123"
+
+            [database]
+            url = "postgres://file/grooveguessr"
+            pool_size = 4
+
+            [redis]
+            url = "redis://file:6379"
+
+            [game]
+            guessing_time = 30
+
+            [oidc.gitlab]
+            issuer_url = "https://gitlab.com"
+            client_id = "id"
+            client_secret = "secret"
+            redirect_url = "https://grooveguessr.example/auth_callback/gitlab"
+            name_claims = ["nickname", "name"]
+            require_email = true
+        "#;
+
+        let config = load(
+            &[
+                ("DATABASE_URL", "postgres://env/grooveguessr"),
+                ("OIDC_GITLAB_CLIENT_ID", "env-id"),
+            ],
+            Some(file),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(config.bind_address.to_string(), "127.0.0.1:3000");
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.database_url, "postgres://env/grooveguessr");
+        assert_eq!(config.database_pool_size, 4);
+        assert_eq!(config.redis_url, "redis://file:6379");
+        assert_eq!(config.game.guessing_time, 30);
+
+        let gitlab = &config.oidc_providers[0];
+        assert_eq!(gitlab.name, "gitlab");
+        assert_eq!(gitlab.connect.client_id, "env-id");
+        assert_eq!(gitlab.connect.client_secret, "secret");
+        assert_eq!(gitlab.claims.name_claims.len(), 2);
+        assert!(gitlab.claims.require_email);
+    }
+
+    #[test]
+    fn all_problems_are_reported_at_once() {
+        let problems = load(
+            &[
+                ("SECRET_KEY", "too-short"),
+                ("DATABASE_URL", "mysql://localhost/grooveguessr"),
+                ("DATABASE_POOL_SIZE", "0"),
+                ("BIND_ADDRESS", "everywhere"),
+                ("LOG_FORMAT", "yaml"),
+                ("OIDC_PROVIDERS", "google"),
+                ("OIDC_GOOGLE_ISSUER_URL", "accounts.google.com"),
+                ("OIDC_GOOGLE_NAME_CLAIMS", "name,email"),
+            ],
+            None,
+            false,
+        )
+        .err()
+        .expect("the configuration should be invalid");
+
+        assert_eq!(
+            problems,
+            vec![
+                "BIND_ADDRESS is invalid: expected an address like 0.0.0.0:8080",
+                "SECRET_KEY is invalid: needs to be at least 64 bytes long, got 9",
+                "LOG_FORMAT is invalid: expected text or json, got yaml",
+                "DATABASE_URL is invalid: expected a postgres or postgresql URL, got mysql",
+                "DATABASE_POOL_SIZE is invalid: needs to be greater than 0",
+                "REDIS_URL needs to be set",
+                "OIDC_GOOGLE_ISSUER_URL is invalid: relative URL without a base",
+                "OIDC_GOOGLE_CLIENT_ID needs to be set",
+                "OIDC_GOOGLE_CLIENT_SECRET needs to be set",
+                "OIDC_GOOGLE_REDIRECT_URL needs to be set",
+                "OIDC_GOOGLE_NAME_CLAIMS is invalid: unsupported name claim: email",
+            ]
+        );
+    }
+
+    #[test]
+    fn problems_in_the_file_point_to_the_key() {
+        let problems = load(
+            &[
+                ("SECRET_KEY", SECRET_KEY),
+                ("REDIS_URL", "redis://localhost"),
+            ],
+            Some("[database]\nurl = \"postgres://localhost\"\npool_size = \"many\""),
+            true,
+        )
+        .err()
+        .expect("the configuration should be invalid");
+
+        assert_eq!(
+            problems,
+            vec!["database.pool_size in test.toml is invalid: invalid digit found in string"]
+        );
+    }
+}
@@ -1,5 +1,19 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_tokens (id) {
+        id -> Uuid,
+        #[max_length = 100]
+        user_id -> Varchar,
+        #[max_length = 70]
+        name -> Varchar,
+        #[max_length = 64]
+        token_hash -> Bpchar,
+        scopes -> Text,
+        created_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+    }
+}
 diesel::table! {
     contents (lobby_id, user_id) {
         #[max_length = 10]
@@ -15,6 +29,50 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    game_players (game_id, player_id) {
+        #[max_length = 10]
+        game_id -> Bpchar,
+        #[max_length = 100]
+        player_id -> Varchar,
+        #[max_length = 70]
+        name -> Varchar,
+        score -> Int4,
+        is_winner -> Bool,
+        guesses -> Text,
+    }
+}
+
+diesel::table! {
+    game_rounds (game_id, round_index) {
+        #[max_length = 10]
+        game_id -> Bpchar,
+        round_index -> Int2,
+        #[max_length = 100]
+        owner_id -> Varchar,
+        #[max_length = 70]
+        owner_name -> Varchar,
+        #[max_length = 70]
+        content_type -> Nullable<Varchar>,
+        #[max_length = 255]
+        content_data -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    games (id) {
+        #[max_length = 10]
+        id -> Bpchar,
+        #[max_length = 100]
+        host_id -> Varchar,
+        #[max_length = 70]
+        host_name -> Varchar,
+        guessing_time -> Int2,
+        started_at -> Timestamptz,
+        finished_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     lobbies (id) {
         #[max_length = 10]
@@ -27,6 +85,9 @@ diesel::table! {
         #[max_length = 100]
         current_user_id -> Nullable<Varchar>,
         created_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+        #[max_length = 10]
+        rematch_id -> Nullable<Bpchar>,
     }
 }
 
@@ -42,27 +103,94 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_compatibility (guesser_id, owner_id) {
+        #[max_length = 100]
+        guesser_id -> Varchar,
+        #[max_length = 100]
+        owner_id -> Varchar,
+        rounds -> Int4,
+        correct -> Int4,
+    }
+}
+
+diesel::table! {
+    user_guess_targets (user_id, target_id) {
+        #[max_length = 100]
+        user_id -> Varchar,
+        #[max_length = 100]
+        target_id -> Varchar,
+        guesses -> Int4,
+    }
+}
+
+diesel::table! {
+    user_identities (provider, subject) {
+        #[max_length = 50]
+        provider -> Varchar,
+        #[max_length = 255]
+        subject -> Varchar,
+        #[max_length = 100]
+        user_id -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_stats (user_id) {
+        #[max_length = 100]
+        user_id -> Varchar,
+        games_played -> Int4,
+        games_won -> Int4,
+        guesses_made -> Int4,
+        guesses_correct -> Int4,
+        songs_guessed -> Int4,
+        songs_recognised -> Int4,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     users (id) {
         #[max_length = 100]
         id -> Varchar,
         #[max_length = 70]
-        email -> Varchar,
+        email -> Nullable<Varchar>,
         #[max_length = 70]
         name -> Varchar,
         created_at -> Timestamptz,
+        is_guest -> Bool,
+        is_admin -> Bool,
+        #[max_length = 100]
+        avatar -> Nullable<Varchar>,
+        #[max_length = 280]
+        bio -> Nullable<Varchar>,
+        genres -> Text,
     }
 }
 
+diesel::joinable!(api_tokens -> users (user_id));
 diesel::joinable!(contents -> lobbies (lobby_id));
 diesel::joinable!(contents -> users (user_id));
+diesel::joinable!(game_players -> games (game_id));
+diesel::joinable!(game_rounds -> games (game_id));
 diesel::joinable!(lobbies -> users (host_id));
 diesel::joinable!(lobbies_players -> lobbies (lobby_id));
 diesel::joinable!(lobbies_players -> users (player_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(user_stats -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
     contents,
+    game_players,
+    game_rounds,
+    games,
     lobbies,
     lobbies_players,
+    user_compatibility,
+    user_guess_targets,
+    user_identities,
+    user_stats,
     users,
 );
@@ -0,0 +1,258 @@
+//! Logging in without an OIDC provider for local development, only compiled with the
+//! `dev-auth` feature. Anyone can log in as anyone, so it's never meant to be deployed.
+
+use actix_session::Session;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header;
+use actix_web::web::Data;
+use actix_web::{guard, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::auth::UserInfo;
+use crate::models::user::User;
+use crate::services::blocking;
+use crate::services::user::generate_random_name;
+use crate::AppState;
+
+const PROVIDER: &str = "dev";
+
+/// Panics unless `DEV_AUTH=true` is set, so that a build with the `dev-auth` feature can't be
+/// started by accident.
+pub fn ensure_enabled() {
+    match std::env::var("DEV_AUTH").as_deref() {
+        Ok("true") => warn!("dev auth is enabled, anyone can log in as anyone via /dev_login"),
+        _ => panic!(
+            "Built with the dev-auth feature which lets anyone log in as anyone, set DEV_AUTH=true to start anyway"
+        ),
+    }
+}
+
+pub fn is_enabled() -> bool {
+    matches!(std::env::var("DEV_AUTH").as_deref(), Ok("true"))
+}
+
+/// Adds `/dev_login`, which only answers while `DEV_AUTH=true` is set.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/dev_login")
+            .guard(guard::fn_guard(|_| is_enabled()))
+            .to(dev_login),
+    );
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DevLogin {
+    user: String,
+    name: Option<String>,
+}
+
+/// Logs in as the given user, e.g. `/dev_login?user=alice`, registering it on first use.
+pub async fn dev_login(
+    context: Data<AppState>,
+    session: Session,
+    params: web::Query<DevLogin>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DevLogin { user, name } = params.into_inner();
+
+    let new_user = User {
+        id: format!("{PROVIDER}:{user}"),
+        email: None,
+        name: context
+            .user_service
+            .name_policy()
+            .sanitize(name.as_deref().unwrap_or(&user))
+            .unwrap_or_else(generate_random_name),
+        created_at: chrono::Utc::now().naive_utc(),
+        is_guest: false,
+        is_admin: false,
+        avatar: None,
+        bio: None,
+        genres: String::new(),
+    };
+
+    let user_service = context.user_service.clone();
+
+    let user = blocking(move || user_service.register_identity(PROVIDER, &user, new_user))
+        .await
+        .map_err(|_| ErrorInternalServerError("Failed to register user"))?;
+
+    session.insert(
+        "user_info",
+        UserInfo {
+            provider: Some(PROVIDER.to_owned()),
+            claims: None,
+            token: None,
+            expires_at: None,
+            scopes: None,
+            user,
+        },
+    )?;
+
+    Ok(HttpResponse::Found()
+        .append_header((header::LOCATION, "/"))
+        .finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    use actix_session::storage::CookieSessionStore;
+    use actix_session::{Session, SessionMiddleware};
+    use actix_web::cookie::Key;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::web::Data;
+    use actix_web::{web, App, HttpResponse};
+    use diesel::r2d2::{ConnectionManager, Pool};
+
+    use super::{ensure_enabled, routes, PROVIDER};
+    use crate::auth::{OidcProviders, UserInfo};
+    use crate::config::GameConfig;
+    use crate::metrics::Metrics;
+    use crate::repositories::Repositories;
+    use crate::services::api_token::ApiTokenService;
+    use crate::services::avatar::AvatarService;
+    use crate::services::health::HealthService;
+    use crate::services::Services;
+    use crate::storage::LocalStorage;
+    use crate::{schema_builder, AppState, NamePolicy};
+
+    /// The tests switch `DEV_AUTH` on and off, so they take turns. Each one runs on a runtime of
+    /// its own thread, holding the lock across its requests blocks no one but the other tests.
+    fn lock_env() -> MutexGuard<'static, ()> {
+        static ENV: Mutex<()> = Mutex::new(());
+
+        ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The app state on the in-memory repositories. Postgres and Redis are never connected to,
+    /// logging in doesn't need them.
+    fn app_state(repositories: &Repositories) -> AppState {
+        let metrics = Metrics::new();
+        let db_pool =
+            Pool::builder().build_unchecked(ConnectionManager::new("postgres://localhost/unused"));
+        let redis = redis::Client::open("redis://localhost/").unwrap();
+        let services = Services::new(
+            repositories,
+            Arc::new(NamePolicy::default()),
+            &GameConfig::default(),
+            metrics.clone(),
+        );
+        let storage =
+            LocalStorage::new(std::env::temp_dir().join("grooveguessr-dev-auth-test")).unwrap();
+
+        AppState {
+            schema: schema_builder(
+                repositories,
+                &services,
+                GameConfig::default(),
+                metrics.clone(),
+            )
+            .finish(),
+            oidc_providers: OidcProviders::new(Vec::new()),
+            user_service: services.users,
+            lobby_service: services.lobbies,
+            game_service: services.games,
+            content_service: services.contents,
+            api_token_service: ApiTokenService::new(db_pool.clone()),
+            avatar_service: AvatarService::new(Arc::new(storage)),
+            health_service: HealthService::new(db_pool.clone(), redis.clone()),
+            presence_service: services.presence,
+            db_pool,
+            redis,
+            metrics,
+        }
+    }
+
+    /// Answers with the user of the session.
+    async fn whoami(session: Session) -> HttpResponse {
+        match session.get::<UserInfo>("user_info").unwrap() {
+            Some(user_info) => HttpResponse::Ok().body(format!(
+                "{} {} {}",
+                user_info.provider.unwrap_or_default(),
+                user_info.user.id,
+                user_info.user.name
+            )),
+            None => HttpResponse::Unauthorized().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn logs_in_as_the_same_user_every_time() {
+        let _env = lock_env();
+        std::env::set_var("DEV_AUTH", "true");
+
+        let repositories = Repositories::in_memory();
+        let app = init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                ))
+                .app_data(Data::new(app_state(&repositories)))
+                .configure(routes)
+                .service(web::resource("/whoami").to(whoami)),
+        )
+        .await;
+
+        let mut logged_in_as = Vec::new();
+
+        // the name only counts when the user is registered
+        for uri in ["/dev_login?user=alice", "/dev_login?user=alice&name=bob"] {
+            let response = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::FOUND);
+            assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/");
+
+            let cookie = response.response().cookies().next().unwrap().into_owned();
+            let response = call_service(
+                &app,
+                TestRequest::get()
+                    .uri("/whoami")
+                    .cookie(cookie)
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            logged_in_as.push(String::from_utf8(read_body(response).await.to_vec()).unwrap());
+        }
+
+        std::env::remove_var("DEV_AUTH");
+
+        let user = repositories
+            .users
+            .find_by_identity(PROVIDER, "alice")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(user.name, "alice");
+        assert_eq!(logged_in_as, vec![format!("dev {} alice", user.id); 2]);
+    }
+
+    #[actix_web::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn refuses_to_log_in_when_disabled() {
+        let _env = lock_env();
+        std::env::remove_var("DEV_AUTH");
+
+        let app = init_service(App::new().configure(routes)).await;
+        let response = call_service(
+            &app,
+            TestRequest::get().uri("/dev_login?user=alice").to_request(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    #[should_panic(expected = "set DEV_AUTH=true")]
+    fn refuses_to_start_when_disabled() {
+        let _env = lock_env();
+        std::env::remove_var("DEV_AUTH");
+
+        ensure_enabled();
+    }
+}
@@ -0,0 +1,32 @@
+use actix_web::http::header;
+use actix_web::web::Data;
+use actix_web::{web, HttpResponse};
+
+use crate::services::blocking;
+use crate::storage::is_valid_key;
+use crate::AppState;
+
+/// Serves an uploaded avatar. Every upload gets a new key, so they can be cached forever.
+pub async fn serve_avatar(context: Data<AppState>, key: web::Path<String>) -> HttpResponse {
+    let key = key.into_inner();
+
+    if !is_valid_key(&key) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let avatar_service = context.avatar_service.clone();
+    let avatar_key = key.clone();
+
+    match blocking(move || avatar_service.load(&avatar_key)).await {
+        Ok(Some(avatar)) => HttpResponse::Ok()
+            .content_type("image/png")
+            .append_header((header::CACHE_CONTROL, "public, max-age=31536000, immutable"))
+            .body(avatar),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            error!("Failed to load avatar {key}: {err}");
+
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,53 +1,191 @@
-use async_graphql::{Context, EmptySubscription, ErrorExtensions, FieldResult, Object, Schema};
+use std::io::Read;
+
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{
+    Context, EmptySubscription, ErrorExtensions, FieldResult, Json, Object, ResultExt, Schema,
+    SchemaBuilder,
+};
 
 use crate::auth::UserInfo;
+use crate::config::GameConfig;
+use crate::loaders::{ContentLoader, LobbyPlayersLoader, UserLoader};
+use crate::metrics::{GraphQLMetrics, Metrics};
+use crate::models::api_token::{ApiToken, CreatedApiToken, TokenScope};
+use crate::models::export::UserDataExport;
+use crate::models::game::{Game, GamePage};
 use crate::models::lobby::Lobby;
-use crate::models::user::User;
+use crate::models::stats::Compatibility;
+use crate::models::user::{ProfileInput, User};
+use crate::repositories::Repositories;
+use crate::services::api_token::ApiTokenService;
+use crate::services::avatar::{AvatarService, MAX_AVATAR_BYTES};
+use crate::services::game::GameService;
 use crate::services::lobby::LobbyService;
+use crate::services::session::SessionService;
+use crate::services::stats::StatsService;
 use crate::services::user::UserService;
-use crate::services::Error;
+use crate::services::{blocking, Error, Resource, Services};
+use crate::telemetry::GraphQLTracing;
+
+use super::guards::{ScopeGuard, SessionGuard};
 
 pub struct Query;
 pub struct Mutation;
 
 #[Object]
 impl Query {
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Read)")]
     async fn profile(&self, ctx: &Context<'_>) -> FieldResult<User> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let user_service = ctx.data::<UserService>().unwrap();
+        let user_service = ctx.data::<UserService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
 
-        let user = user_service
-            .find(&user_info.user.id)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+        let user = blocking(move || user_service.find(&user_id))
+            .await
+            .extend()?;
 
         Ok(user)
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Read)")]
     async fn lobby(&self, ctx: &Context<'_>, id: String) -> FieldResult<Lobby> {
-        let service = ctx.data::<LobbyService>().unwrap();
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = ctx.data::<UserInfo>().unwrap().user.clone();
+
+        blocking(move || service.find(id, &user)).await.extend()
+    }
+
+    /// The games you played in, most recent first.
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Read)")]
+    async fn my_games(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 0)] offset: i64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 50))] limit: i64,
+    ) -> FieldResult<GamePage> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let game_service = ctx.data::<GameService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        let (games, total) =
+            blocking(move || game_service.find_by_player(&user_id, offset.max(0), limit))
+                .await
+                .extend()?;
+
+        Ok(GamePage {
+            has_more: offset.max(0) + (games.len() as i64) < total,
+            games,
+            total,
+        })
+    }
+
+    /// The full record of a finished game you played in.
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Read)")]
+    async fn game(&self, ctx: &Context<'_>, id: String) -> FieldResult<Game> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let game_service = ctx.data::<GameService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        blocking(move || {
+            let game = game_service.find(&id)?;
+
+            if !game_service.is_player(&game, &user_id)? {
+                return Err(Error::NotFound(Resource::Game));
+            }
+
+            Ok(game)
+        })
+        .await
+        .extend()
+    }
+
+    /// How well players predict each other's taste, either within a finished lobby
+    /// or for a user (defaults to yourself) across all their games.
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Read)")]
+    async fn compatibility(
+        &self,
+        ctx: &Context<'_>,
+        lobby_id: Option<String>,
+        user_id: Option<String>,
+    ) -> FieldResult<Vec<Compatibility>> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let stats_service = ctx.data::<StatsService>().unwrap().clone();
+        let game_service = ctx.data::<GameService>().unwrap().clone();
+        let viewer_id = user_info.user.id.clone();
+        let user_id = user_id.unwrap_or(viewer_id.clone());
+
+        let compatibility = blocking(move || match lobby_id {
+            Some(lobby_id) => {
+                let game = game_service.find(&lobby_id)?;
 
-        service
-            .find(id, &ctx.data::<UserInfo>().unwrap().user)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))
+                if !game_service.is_player(&game, &viewer_id)? {
+                    return Err(Error::NotFound(Resource::Game));
+                }
+
+                let scoreboard = game_service.scoreboard(&game)?;
+
+                stats_service.game_compatibility(&scoreboard)
+            }
+            None => stats_service.compatibility(&user_id),
+        })
+        .await
+        .extend()?;
+
+        Ok(compatibility)
+    }
+
+    /// Everything stored about you.
+    #[graphql(guard = "SessionGuard")]
+    async fn export_my_data(&self, ctx: &Context<'_>) -> FieldResult<Json<UserDataExport>> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let user_service = ctx.data::<UserService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        let export = blocking(move || user_service.export_data(&user_id))
+            .await
+            .extend()?;
+
+        Ok(Json(export))
+    }
+
+    /// Your personal access tokens, without their secrets.
+    #[graphql(guard = "SessionGuard")]
+    async fn api_tokens(&self, ctx: &Context<'_>) -> FieldResult<Vec<ApiToken>> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let api_token_service = ctx.data::<ApiTokenService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        let tokens = blocking(move || api_token_service.find_by_user(&user_id))
+            .await
+            .extend()?;
+
+        Ok(tokens)
     }
 }
 
 #[Object]
 impl Mutation {
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn create_lobby(&self, ctx: &Context<'_>) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<LobbyService>().unwrap();
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let game_config = ctx.data::<GameConfig>().unwrap();
+        let user = user_info.user.clone();
 
         let mut new_lobby = Lobby {
             host_id: user_info.user.id.clone(),
+            guessing_time: game_config.guessing_time,
             ..Default::default()
         };
 
-        new_lobby = service.create(new_lobby, &user_info.user)?;
+        new_lobby = blocking(move || service.create(new_lobby, &user))
+            .await
+            .extend()?;
 
         Ok(new_lobby)
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn configure_lobby(
         &self,
         ctx: &Context<'_>,
@@ -55,62 +193,243 @@ impl Mutation {
         guessing_time: i16,
     ) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<LobbyService>().unwrap();
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
 
-        let mut lobby = service.find(id, &user_info.user)?;
-        lobby.guessing_time = guessing_time;
-        lobby = service.configure(lobby, &user_info.user)?;
+        blocking(move || {
+            let mut lobby = service.find(id, &user)?;
+            lobby.guessing_time = guessing_time;
 
-        Ok(lobby)
+            service.configure(lobby, &user)
+        })
+        .await
+        .extend()
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn join_lobby(&self, ctx: &Context<'_>, id: String) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<LobbyService>().unwrap();
-        let lobby = service.find(id, &user_info.user)?;
-        let lobby = service.join(&lobby, &user_info.user)?;
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
 
-        Ok(lobby)
+        blocking(move || {
+            let lobby = service.find(id, &user)?;
+
+            service.join(&lobby, &user)
+        })
+        .await
+        .extend()
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn set_ready(&self, ctx: &Context<'_>, id: String, ready: bool) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<LobbyService>().unwrap();
-        let lobby = service.find(id, &user_info.user)?;
-        let lobby = service.set_ready(lobby, &user_info.user, ready)?;
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
+
+        blocking(move || {
+            let lobby = service.find(id, &user)?;
 
-        Ok(lobby)
+            service.set_ready(lobby, &user, ready)
+        })
+        .await
+        .extend()
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn set_content(&self, ctx: &Context<'_>, id: String, url: String) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<LobbyService>().unwrap();
-        let lobby = service.find(id, &user_info.user)?;
-        let lobby = service.set_content(lobby, &user_info.user, url)?;
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
 
-        Ok(lobby)
+        blocking(move || {
+            let lobby = service.find(id, &user)?;
+
+            service.set_content(lobby, &user, url)
+        })
+        .await
+        .extend()
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn start_game(&self, ctx: &Context<'_>, id: String) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<LobbyService>().unwrap();
-        let mut lobby = service.find(id, &user_info.user)?;
-        lobby = service.start_game(lobby, &user_info.user)?;
+        let service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
+
+        blocking(move || {
+            let lobby = service.find(id, &user)?;
 
-        Ok(lobby)
+            service.start_game(lobby, &user)
+        })
+        .await
+        .extend()
     }
 
-    async fn set_name(&self, ctx: &Context<'_>, name: String) -> FieldResult<User> {
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Profile)")]
+    async fn update_profile(&self, ctx: &Context<'_>, input: ProfileInput) -> FieldResult<User> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let service = ctx.data::<UserService>().unwrap();
-        let mut user = service.find(&user_info.user.id)?;
-        user.name = name;
+        let user_service = ctx.data::<UserService>().unwrap().clone();
+        let avatar_service = ctx.data::<AvatarService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        // read before leaving for the blocking pool, the upload belongs to the request
+        let avatar = match input.avatar {
+            Some(avatar) => {
+                let avatar = avatar.value(ctx)?;
+
+                let size = avatar.size().map_err(|err| Error::Storage(err).extend())?;
+
+                if size > MAX_AVATAR_BYTES {
+                    return Err(Error::InvalidInput(format!(
+                        "avatar must be at most {} MB",
+                        MAX_AVATAR_BYTES / 1024 / 1024
+                    ))
+                    .extend());
+                }
+
+                let mut data = Vec::new();
+                avatar
+                    .into_read()
+                    .read_to_end(&mut data)
+                    .map_err(|err| Error::Storage(err).extend())?;
+
+                Some(data)
+            }
+            None => None,
+        };
 
-        user = service.save(user)?;
+        blocking(move || {
+            let mut user = user_service.find(&user_id)?;
+            let previous_avatar = user.avatar.clone();
+
+            user.update_profile(
+                user_service.name_policy(),
+                input.name,
+                input.bio,
+                input.genres,
+            )?;
+
+            if let Some(data) = avatar {
+                user.avatar = Some(avatar_service.store(&data)?);
+            } else if input.remove_avatar {
+                user.avatar = None;
+            }
+
+            let user = user_service.save(user)?;
+
+            if let Some(previous_avatar) =
+                previous_avatar.filter(|key| user.avatar.as_ref() != Some(key))
+            {
+                if let Err(err) = avatar_service.delete(&previous_avatar) {
+                    warn!("Failed to delete replaced avatar {previous_avatar}: {err}");
+                }
+            }
+
+            Ok(user)
+        })
+        .await
+        .extend()
+    }
 
-        Ok(user)
+    /// Deletes your account and ends all your sessions. Games you took part in are kept for the
+    /// other players, with you showing up as a deleted player.
+    #[graphql(guard = "SessionGuard")]
+    async fn delete_account(&self, ctx: &Context<'_>) -> FieldResult<bool> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let user_service = ctx.data::<UserService>().unwrap().clone();
+        let avatar_service = ctx.data::<AvatarService>().unwrap().clone();
+        let session_service = ctx.data::<SessionService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        blocking(move || {
+            let user = user_service.find(&user_id)?;
+
+            user_service.delete_account(&user.id)?;
+
+            if let Some(avatar) = user.avatar {
+                if let Err(err) = avatar_service.delete(&avatar) {
+                    warn!("Failed to delete avatar {avatar} of deleted user: {err}");
+                }
+            }
+
+            session_service.revoke_all(&user.id)
+        })
+        .await
+        .extend()?;
+
+        Ok(true)
+    }
+
+    /// Ends all your sessions, including the current one. Returns how many were ended.
+    #[graphql(guard = "SessionGuard")]
+    async fn logout_all_devices(&self, ctx: &Context<'_>) -> FieldResult<usize> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let session_service = ctx.data::<SessionService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        let revoked = blocking(move || session_service.revoke_all(&user_id))
+            .await
+            .extend()?;
+
+        Ok(revoked)
+    }
+
+    /// Ends all sessions of another user, only allowed for admins. Returns how many were ended.
+    #[graphql(guard = "SessionGuard")]
+    async fn revoke_sessions(&self, ctx: &Context<'_>, user_id: String) -> FieldResult<usize> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let user_service = ctx.data::<UserService>().unwrap().clone();
+        let session_service = ctx.data::<SessionService>().unwrap().clone();
+        let admin_id = user_info.user.id.clone();
+
+        let revoked = blocking(move || {
+            // the session may predate the promotion, so the database decides
+            if !user_service.is_admin(&admin_id)? {
+                return Err(Error::Unauthorized);
+            }
+
+            session_service.revoke_all(&user_id)
+        })
+        .await
+        .extend()?;
+
+        Ok(revoked)
+    }
+
+    /// Creates a personal access token for bots and scripts, its secret is only returned once.
+    #[graphql(guard = "SessionGuard")]
+    async fn create_api_token(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        scopes: Vec<TokenScope>,
+    ) -> FieldResult<CreatedApiToken> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let api_token_service = ctx.data::<ApiTokenService>().unwrap().clone();
+        let user = user_info.user.clone();
+
+        let token = blocking(move || api_token_service.create(&user, name, &scopes))
+            .await
+            .extend()?;
+
+        Ok(token)
+    }
+
+    #[graphql(guard = "SessionGuard")]
+    async fn revoke_api_token(&self, ctx: &Context<'_>, id: uuid::Uuid) -> FieldResult<bool> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+        let api_token_service = ctx.data::<ApiTokenService>().unwrap().clone();
+        let user_id = user_info.user.id.clone();
+
+        blocking(move || api_token_service.revoke(&user_id, id))
+            .await
+            .extend()?;
+
+        Ok(true)
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn guess(
         &self,
         ctx: &Context<'_>,
@@ -119,25 +438,89 @@ impl Mutation {
         guessed_user_id: String,
     ) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
-        let user_service = ctx.data::<UserService>().unwrap();
-        let lobby_service = ctx.data::<LobbyService>().unwrap();
-        let guessed_user = user_service.find(&guessed_user_id)?;
-        let lobby = lobby_service.find(id, &user_info.user)?;
+        let user_service = ctx.data::<UserService>().unwrap().clone();
+        let lobby_service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
+
+        blocking(move || {
+            let guessed_user = user_service.find(&guessed_user_id)?;
+            let lobby = lobby_service.find(id, &user)?;
 
-        lobby_service.guess(&lobby, round_index, &user_info.user, &guessed_user)?;
+            lobby_service.guess(&lobby, round_index, &user, &guessed_user)?;
 
-        Ok(lobby)
+            Ok(lobby)
+        })
+        .await
+        .extend()
     }
 
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
     async fn forward(&self, ctx: &Context<'_>, id: String) -> FieldResult<Lobby> {
         let user_info = ctx.data::<UserInfo>().unwrap();
 
-        let lobby_service = ctx.data::<LobbyService>().unwrap();
-        let lobby = lobby_service.find(id, &user_info.user)?;
-        let lobby = lobby_service.forward(lobby, &user_info.user)?;
+        let lobby_service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
+
+        blocking(move || {
+            let lobby = lobby_service.find(id, &user)?;
+
+            lobby_service.forward(lobby, &user)
+        })
+        .await
+        .extend()
+    }
+
+    #[graphql(guard = "ScopeGuard::new(TokenScope::Play)")]
+    async fn rematch(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        #[graphql(default)] keep_content: bool,
+    ) -> FieldResult<Lobby> {
+        let user_info = ctx.data::<UserInfo>().unwrap();
+
+        let lobby_service = ctx.data::<LobbyService>().unwrap().clone();
+        let user = user_info.user.clone();
+
+        blocking(move || {
+            let lobby = lobby_service.find(id, &user)?;
 
-        Ok(lobby)
+            lobby_service.rematch(lobby, &user, keep_content)
+        })
+        .await
+        .extend()
     }
 }
 
 pub type ProjectSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// Starts a schema with the game services and loaders on top of the given repositories. Sessions,
+/// personal access tokens and avatars are left to the caller.
+pub fn schema_builder(
+    repositories: &Repositories,
+    services: &Services,
+    game_config: GameConfig,
+    metrics: Metrics,
+) -> SchemaBuilder<Query, Mutation, EmptySubscription> {
+    Schema::build(Query, Mutation, EmptySubscription)
+        .extension(GraphQLTracing)
+        .extension(GraphQLMetrics(metrics))
+        .data(game_config)
+        .data(services.lobbies.clone())
+        .data(services.users.clone())
+        .data(services.contents.clone())
+        .data(services.stats.clone())
+        .data(services.games.clone())
+        .data(DataLoader::new(
+            UserLoader::new(repositories.users.clone()),
+            actix_web::rt::spawn,
+        ))
+        .data(DataLoader::new(
+            LobbyPlayersLoader::new(repositories.lobbies.clone()),
+            actix_web::rt::spawn,
+        ))
+        .data(DataLoader::new(
+            ContentLoader::new(repositories.lobbies.clone()),
+            actix_web::rt::spawn,
+        ))
+}
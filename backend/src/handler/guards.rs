@@ -0,0 +1,43 @@
+use async_graphql::{Context, ErrorExtensions, Guard, Result};
+
+use crate::auth::UserInfo;
+use crate::models::api_token::TokenScope;
+use crate::services::Error;
+
+/// Lets logged in users through, as well as personal access tokens with the scope.
+pub struct ScopeGuard(TokenScope);
+
+impl ScopeGuard {
+    pub fn new(scope: TokenScope) -> Self {
+        Self(scope)
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for ScopeGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let user_info = ctx.data::<UserInfo>()?;
+
+        if user_info.has_scope(self.0) {
+            Ok(())
+        } else {
+            Err(Error::MissingScope(self.0).extend())
+        }
+    }
+}
+
+/// Only lets logged in users through, e.g. to keep tokens from creating more tokens.
+pub struct SessionGuard;
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for SessionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let user_info = ctx.data::<UserInfo>()?;
+
+        if user_info.scopes.is_none() {
+            Ok(())
+        } else {
+            Err(Error::SessionRequired.extend())
+        }
+    }
+}
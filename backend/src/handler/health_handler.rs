@@ -0,0 +1,26 @@
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+
+use crate::services::blocking;
+use crate::AppState;
+
+/// Liveness: the server is up and handling requests.
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness: Postgres and Redis answer and the migrations are up to date, see
+/// `HealthService::readiness` for the JSON returned. Answers 503 if anything is wrong.
+pub async fn readyz(context: Data<AppState>) -> HttpResponse {
+    let health_service = context.health_service.clone();
+
+    match blocking(move || Ok(health_service.readiness())).await {
+        Ok(readiness) if readiness.ready => HttpResponse::Ok().json(readiness),
+        Ok(readiness) => HttpResponse::ServiceUnavailable().json(readiness),
+        Err(err) => {
+            error!("Failed to check readiness: {}", err);
+
+            HttpResponse::ServiceUnavailable().finish()
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+
+use crate::services::blocking;
+use crate::AppState;
+
+/// Prometheus metrics in the text format, see `metrics` for what is collected. The pool and
+/// presence gauges are updated first, a failing presence lookup leaves them at their last value.
+pub async fn serve_metrics(context: Data<AppState>) -> HttpResponse {
+    let metrics = context.metrics.clone();
+    let db_pool = context.db_pool.clone();
+    let presence_service = context.presence_service.clone();
+
+    metrics.record_db_pool(&db_pool);
+
+    if let Err(err) = blocking(move || presence_service.record_online()).await {
+        warn!("Failed to count players online: {}", err);
+    }
+
+    HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics.encode())
+}
@@ -1 +1,6 @@
+pub mod avatar_handler;
 pub mod graphql_handler;
+mod guards;
+pub mod health_handler;
+pub mod metrics_handler;
+pub mod playlist_handler;
@@ -0,0 +1,87 @@
+use actix_session::Session;
+use actix_web::http::header;
+use actix_web::web::Data;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+
+use crate::auth::UserInfo;
+use crate::models::playlist::PlaylistFormat;
+use crate::services::{blocking, Error};
+use crate::AppState;
+
+/// Exports the content of a finished lobby to its players, e.g. `/lobbies/<id>/playlist.m3u`.
+pub async fn export_playlist(
+    context: Data<AppState>,
+    session: Session,
+    http_request: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (lobby_id, format) = path.into_inner();
+
+    let Ok(format) = format.parse::<PlaylistFormat>() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    // requests with a personal access token don't have a session
+    let user_info = match http_request.extensions_mut().remove::<UserInfo>() {
+        Some(user_info) => Some(user_info),
+        None => session.get::<UserInfo>("user_info").unwrap_or_default(),
+    };
+    let Some(user_id) = user_info.map(|user_info| user_info.user.id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+
+    let (lobby_service, content_service) = (
+        context.lobby_service.clone(),
+        context.content_service.clone(),
+    );
+    let id = lobby_id.clone();
+
+    let playlist = match blocking(move || {
+        let lobby = lobby_service.find_by_id(&id)?;
+
+        content_service.playlist(&lobby, &user_id)
+    })
+    .await
+    {
+        Ok(playlist) => playlist,
+        // the same as for the game itself, only the people who played it get to see it
+        Err(Error::NotFound(_))
+        | Err(Error::NotAPlayer)
+        | Err(Error::Db(diesel::result::Error::NotFound)) => {
+            return HttpResponse::NotFound().finish()
+        }
+        Err(Error::GameNotFinished) => {
+            return HttpResponse::Conflict().body("Game not finished yet")
+        }
+        Err(err) => {
+            error!("Failed to export playlist of lobby {}: {}", lobby_id, err);
+
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let attachment = |extension: &str| {
+        format!(
+            "attachment; filename=\"grooveguessr-{}.{}\"",
+            lobby_id.trim(),
+            extension
+        )
+    };
+
+    match format {
+        PlaylistFormat::M3u => HttpResponse::Ok()
+            .content_type("audio/x-mpegurl; charset=utf-8")
+            .append_header((header::CONTENT_DISPOSITION, attachment("m3u")))
+            .body(playlist.to_m3u()),
+        PlaylistFormat::Jspf => HttpResponse::Ok()
+            .content_type("application/xspf+json")
+            .append_header((header::CONTENT_DISPOSITION, attachment("jspf")))
+            .body(playlist.to_jspf()),
+        PlaylistFormat::Youtube => match playlist.to_youtube_url() {
+            Some(url) => HttpResponse::Found()
+                .append_header((header::LOCATION, url))
+                .finish(),
+            None => HttpResponse::NotFound().body("No YouTube videos in this game"),
+        },
+    }
+}
@@ -1,38 +1,61 @@
-use std::sync::Arc;
-
+use auth::OidcProviders;
 use diesel::PgConnection;
-use openidconnect::core::CoreClient;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+use metrics::Metrics;
 use redis::Client as RedisClient;
+use services::api_token::ApiTokenService;
+use services::avatar::AvatarService;
+use services::content::ContentService;
+use services::game::GameService;
+use services::health::HealthService;
+use services::lobby::LobbyService;
+use services::presence::PresenceService;
 use services::user::UserService;
 
+#[macro_use]
+extern crate log;
+
 pub mod auth;
 pub mod auth_middleware;
+pub mod config;
 mod db_schema;
+#[cfg(feature = "dev-auth")]
+pub mod dev_auth;
 mod handler;
+pub mod loaders;
+pub mod metrics;
 mod models;
+pub mod repositories;
 pub mod services;
+pub mod session_store;
+pub mod storage;
+pub mod telemetry;
+
+pub use crate::handler::avatar_handler::serve_avatar;
+pub use crate::handler::graphql_handler::{schema_builder, Mutation, ProjectSchema, Query};
+pub use crate::handler::health_handler::{healthz, readyz};
+pub use crate::handler::metrics_handler::serve_metrics;
+pub use crate::handler::playlist_handler::export_playlist;
+pub use crate::models::name::NamePolicy;
+pub use crate::models::user::User;
 
-pub use crate::handler::graphql_handler::{Mutation, ProjectSchema, Query};
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
 pub type DbPool = diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>;
-pub type OidcClient = Arc<CoreClient>;
 
+#[derive(Clone)]
 pub struct AppState {
     pub db_pool: DbPool,
     pub redis: RedisClient,
     pub schema: ProjectSchema,
-    pub oidc_client: OidcClient,
-    pub user_service: Arc<UserService>,
-}
-
-impl Clone for AppState {
-    fn clone(&self) -> Self {
-        Self {
-            db_pool: self.db_pool.clone(),
-            redis: self.redis.clone(),
-            schema: self.schema.clone(),
-            oidc_client: self.oidc_client.clone(),
-            user_service: self.user_service.clone(),
-        }
-    }
+    pub oidc_providers: OidcProviders,
+    pub user_service: UserService,
+    pub lobby_service: LobbyService,
+    pub game_service: GameService,
+    pub content_service: ContentService,
+    pub api_token_service: ApiTokenService,
+    pub avatar_service: AvatarService,
+    pub health_service: HealthService,
+    pub presence_service: PresenceService,
+    pub metrics: Metrics,
 }
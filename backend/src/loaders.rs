@@ -0,0 +1,332 @@
+//! Batched lookups for nested GraphQL fields, so a lobby takes the same few queries no matter
+//! how many players it has. The queries run on the blocking thread pool like those of the
+//! services.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_graphql::dataloader::Loader;
+use async_graphql::ErrorExtensions;
+
+use crate::{
+    models::{content::Contents, lobby::LobbyPlayers, user::User},
+    repositories::{LobbyRepository, UserRepository},
+    services::blocking,
+};
+
+/// Loads users by id.
+pub struct UserLoader {
+    users: Arc<dyn UserRepository>,
+}
+
+impl UserLoader {
+    pub fn new(users: Arc<dyn UserRepository>) -> Self {
+        Self { users }
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<String> for UserLoader {
+    type Value = User;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, User>, Self::Error> {
+        let users = self.users.clone();
+        let keys = keys.to_vec();
+
+        let users = blocking(move || users.find_many(&keys))
+            .await
+            .map_err(|err| err.extend())?;
+
+        Ok(users
+            .into_iter()
+            .map(|user| (user.id.clone(), user))
+            .collect())
+    }
+}
+
+/// Loads the players of lobbies by lobby id, in the order they joined.
+pub struct LobbyPlayersLoader {
+    lobbies: Arc<dyn LobbyRepository>,
+}
+
+impl LobbyPlayersLoader {
+    pub fn new(lobbies: Arc<dyn LobbyRepository>) -> Self {
+        Self { lobbies }
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<String> for LobbyPlayersLoader {
+    type Value = Vec<LobbyPlayers>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[String],
+    ) -> Result<HashMap<String, Vec<LobbyPlayers>>, Self::Error> {
+        let lobbies = self.lobbies.clone();
+        let lobby_ids = keys.to_vec();
+
+        let players = blocking(move || lobbies.players(&lobby_ids))
+            .await
+            .map_err(|err| err.extend())?;
+
+        // lobbies nobody joined yet still get their empty list
+        let mut by_lobby = keys
+            .iter()
+            .map(|key| (key.clone(), Vec::new()))
+            .collect::<HashMap<String, Vec<LobbyPlayers>>>();
+
+        for player in players {
+            by_lobby
+                .entry(player.lobby_id.clone())
+                .or_default()
+                .push(player);
+        }
+
+        Ok(by_lobby)
+    }
+}
+
+/// Loads submitted content by lobby and user id.
+pub struct ContentLoader {
+    lobbies: Arc<dyn LobbyRepository>,
+}
+
+impl ContentLoader {
+    pub fn new(lobbies: Arc<dyn LobbyRepository>) -> Self {
+        Self { lobbies }
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<(String, String)> for ContentLoader {
+    type Value = Contents;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[(String, String)],
+    ) -> Result<HashMap<(String, String), Contents>, Self::Error> {
+        let lobbies = self.lobbies.clone();
+        let lobby_ids = keys
+            .iter()
+            .map(|(lobby_id, _)| lobby_id.clone())
+            .collect::<Vec<String>>();
+        let user_ids = keys
+            .iter()
+            .map(|(_, user_id)| user_id.clone())
+            .collect::<Vec<String>>();
+
+        // may return more than asked for when several lobbies are loaded at once
+        let contents = blocking(move || lobbies.contents(&lobby_ids, &user_ids))
+            .await
+            .map_err(|err| err.extend())?;
+
+        Ok(contents
+            .into_iter()
+            .map(|content| ((content.lobby_id.clone(), content.user_id.clone()), content))
+            .filter(|(key, _)| keys.contains(key))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_graphql::dataloader::DataLoader;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+    use diesel::prelude::*;
+    use diesel::r2d2::{event::CheckoutEvent, ConnectionManager, HandleEvent, Pool};
+
+    use crate::auth::UserInfo;
+    use crate::db_schema::{lobbies, users};
+    use crate::models::{content::Contents, lobby::Lobby, lobby::LobbyPlayers, user::User};
+    use crate::repositories::{LobbyRepository, MemoryRepository, PgRepository, UserRepository};
+
+    use super::{ContentLoader, LobbyPlayersLoader, UserLoader};
+
+    /// Every query of the loaders takes a connection from the pool, so these are counted.
+    #[derive(Debug, Default)]
+    struct Checkouts(Arc<AtomicUsize>);
+
+    impl HandleEvent for Checkouts {
+        fn handle_checkout(&self, _event: CheckoutEvent) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct LobbyQuery(Lobby);
+
+    #[Object]
+    impl LobbyQuery {
+        async fn lobby(&self) -> Lobby {
+            self.0.clone()
+        }
+    }
+
+    fn user(id: String) -> User {
+        User {
+            name: id.clone(),
+            id,
+            email: None,
+            created_at: chrono::Utc::now().naive_utc(),
+            is_guest: true,
+            is_admin: false,
+            avatar: None,
+            bio: None,
+            genres: String::new(),
+        }
+    }
+
+    /// Creates a started lobby where everyone submitted content.
+    fn seed<R: UserRepository + LobbyRepository>(
+        repository: &R,
+        players: usize,
+    ) -> (Lobby, Vec<User>) {
+        let prefix = format!("loader-test:{}", uuid::Uuid::new_v4());
+        let users = (0..players)
+            .map(|i| user(format!("{prefix}:{i}")))
+            .collect::<Vec<User>>();
+        let ids = users.iter().map(|u| u.id.clone()).collect::<Vec<String>>();
+
+        let lobby = Lobby {
+            host_id: ids[0].clone(),
+            started_at: Some(chrono::Utc::now().naive_utc()),
+            sequence: Some(ids.join(",")),
+            current_user_id: Some(ids[0].clone()),
+            ..Default::default()
+        };
+
+        for user in &users {
+            repository.register(user).unwrap();
+        }
+        repository.insert(&lobby).unwrap();
+
+        for id in &ids {
+            repository
+                .add_player(&LobbyPlayers {
+                    lobby_id: lobby.id.clone(),
+                    player_id: id.clone(),
+                    is_ready: true,
+                    guesses: String::new(),
+                    created_at: chrono::Utc::now(),
+                })
+                .unwrap();
+            repository
+                .save_content(&Contents {
+                    lobby_id: lobby.id.clone(),
+                    user_id: id.clone(),
+                    type_: "url".to_string(),
+                    data: format!("https://example.com/{id}"),
+                    created_at: chrono::Utc::now().naive_utc(),
+                })
+                .unwrap();
+        }
+
+        (lobby, users)
+    }
+
+    /// Seeds a lobby and counts the queries it takes to load it, as told by `queries`.
+    async fn queries_for_lobby_of<R>(
+        repository: Arc<R>,
+        queries: impl Fn() -> usize,
+        players: usize,
+    ) -> (usize, Lobby, Vec<User>)
+    where
+        R: UserRepository + LobbyRepository + 'static,
+    {
+        let (lobby, users) = seed(repository.as_ref(), players);
+
+        let schema = Schema::build(LobbyQuery(lobby.clone()), EmptyMutation, EmptySubscription)
+            .data(UserInfo::guest(users[1].clone()))
+            .data(DataLoader::new(
+                UserLoader::new(repository.clone()),
+                actix_web::rt::spawn,
+            ))
+            .data(DataLoader::new(
+                LobbyPlayersLoader::new(repository.clone()),
+                actix_web::rt::spawn,
+            ))
+            .data(DataLoader::new(
+                ContentLoader::new(repository.clone()),
+                actix_web::rt::spawn,
+            ))
+            .finish();
+
+        let before = queries();
+
+        let response = schema
+            .execute(
+                "{ lobby { host { name } content { data } currentContent { data } \
+                guesses players { name isReady } } }",
+            )
+            .await;
+
+        let count = queries() - before;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["lobby"]["players"].as_array().unwrap().len(), players);
+        assert_eq!(data["lobby"]["host"]["name"], users[0].name.as_str());
+
+        (count, lobby, users)
+    }
+
+    fn assert_fixed_number_of_queries(few: usize, many: usize, backend: &str) {
+        // host, players and their users, content; whether the host shares a batch with the
+        // other users depends on timing
+        assert!(few <= 4, "{few} queries for 2 players on {backend}");
+        assert!(many <= 4, "{many} queries for 8 players on {backend}");
+    }
+
+    #[actix_web::test]
+    async fn lobbies_take_a_fixed_number_of_queries_in_memory() {
+        let repository = Arc::new(MemoryRepository::default());
+        let queries = || repository.lookups();
+
+        let (few, _, _) = queries_for_lobby_of(repository.clone(), queries, 2).await;
+        let (many, _, _) = queries_for_lobby_of(repository.clone(), queries, 8).await;
+
+        assert_fixed_number_of_queries(few, many, "memory");
+    }
+
+    /// Needs a migrated database in `TEST_DATABASE_URL`, skipped without one.
+    #[actix_web::test]
+    async fn lobbies_take_a_fixed_number_of_queries_on_postgres() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return;
+        };
+
+        let checkouts = Arc::new(AtomicUsize::new(0));
+        let db_pool = Pool::builder()
+            .max_size(2)
+            .event_handler(Box::new(Checkouts(checkouts.clone())))
+            .build(ConnectionManager::<PgConnection>::new(database_url))
+            .unwrap();
+        let repository = Arc::new(PgRepository::new(db_pool.clone()));
+        let queries = || checkouts.load(Ordering::SeqCst);
+
+        let mut counts = Vec::new();
+
+        for players in [2, 8] {
+            let (count, lobby, users) =
+                queries_for_lobby_of(repository.clone(), queries, players).await;
+            counts.push(count);
+
+            let mut conn = db_pool.get().unwrap();
+            diesel::delete(lobbies::table.filter(lobbies::id.eq(&lobby.id)))
+                .execute(&mut conn)
+                .unwrap();
+            diesel::delete(users::table.filter(users::id.eq_any(users.iter().map(|u| &u.id))))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        assert_fixed_number_of_queries(counts[0], counts[1], "postgres");
+    }
+}
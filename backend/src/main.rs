@@ -5,43 +5,55 @@ use std::sync::Arc;
 
 use actix_files::Files;
 use actix_session::config::PersistentSession;
-use actix_session::storage::CookieSessionStore;
 use actix_session::{Session, SessionMiddleware};
 use actix_web::cookie::time::Duration;
 use actix_web::cookie::Key;
 use actix_web::web::Data;
-use actix_web::{guard, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{guard, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder};
 use async_graphql::http::GraphiQLSource;
-use async_graphql::{EmptySubscription, Schema};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use diesel::prelude::*;
 use diesel::r2d2;
 use dotenvy::dotenv;
+use tracing_actix_web::TracingLogger;
 
-use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use grooveguessr_backend::services::content::ContentService;
-use grooveguessr_backend::services::lobby::LobbyService;
-use grooveguessr_backend::services::presence::PresenceService;
-use grooveguessr_backend::services::user::UserService;
+use diesel_migrations::MigrationHarness;
+use grooveguessr_backend::config::{Config, OidcProviderConfig};
+use grooveguessr_backend::metrics::{Metrics, RequestMetrics};
+use grooveguessr_backend::repositories::Repositories;
+use grooveguessr_backend::services::api_token::ApiTokenService;
+use grooveguessr_backend::services::avatar::AvatarService;
+use grooveguessr_backend::services::health::HealthService;
+use grooveguessr_backend::services::session::SessionService;
+use grooveguessr_backend::services::Services;
+use grooveguessr_backend::session_store::RedisSessionStore;
+use grooveguessr_backend::storage::LocalStorage;
+use grooveguessr_backend::telemetry;
 use grooveguessr_backend::{
-    auth, auth::create_client, auth::OpenIDConnectConfig, auth::UserInfo,
-    auth_middleware::AuthRequired, OidcClient,
+    auth, auth::OidcProvider, auth::OidcProviders, auth::UserInfo, auth_middleware::AuthRequired,
+};
+use grooveguessr_backend::{
+    export_playlist, healthz, readyz, schema_builder, serve_avatar, serve_metrics, AppState,
+    DbPool, NamePolicy, MIGRATIONS,
 };
-use grooveguessr_backend::{AppState, DbPool, Mutation, Query};
-
-pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
 async fn graphql(
     context: Data<AppState>,
     session: Session,
+    http_request: HttpRequest,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
     let mut request = req.into_inner();
 
-    let user = session
-        .get::<UserInfo>("user_info")
-        .expect("Could not fetch user info - not logged in?")
-        .unwrap();
+    // requests with a personal access token don't have a session
+    let token_user = http_request.extensions_mut().remove::<UserInfo>();
+    let user = match token_user {
+        Some(user) => user,
+        None => session
+            .get::<UserInfo>("user_info")
+            .expect("Could not fetch user info - not logged in?")
+            .unwrap(),
+    };
 
     request = request.data(user);
 
@@ -54,96 +66,193 @@ async fn index_graphiql() -> impl Responder {
         .body(GraphiQLSource::build().endpoint("/graphql").finish())
 }
 
-/// Initialize database connection pool based on `DATABASE_URL` environment variable.
+/// Initialize database connection pool with the configured size, reporting waits to `metrics`.
 ///
 /// See more: <https://docs.rs/diesel/latest/diesel/r2d2/index.html>.
-fn initialize_db_pool() -> DbPool {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL should be set");
-    let manager = r2d2::ConnectionManager::<PgConnection>::new(database_url);
+fn initialize_db_pool(config: &Config, metrics: &Metrics) -> DbPool {
+    let manager = r2d2::ConnectionManager::<PgConnection>::new(&config.database_url);
 
     r2d2::Pool::builder()
+        .max_size(config.database_pool_size)
+        .event_handler(Box::new(metrics.db_pool_events()))
         .build(manager)
         .expect("Error building r2d2 pool")
 }
 
-fn initialize_redis() -> redis::Client {
-    redis::Client::open(std::env::var("REDIS_URL").expect("REDIS_URL needs to be set"))
-        .expect("Error initializing redis client")
+fn initialize_redis(config: &Config) -> redis::Client {
+    redis::Client::open(config.redis_url.as_str()).expect("Error initializing redis client")
 }
 
-async fn initialize_oidc_client() -> OidcClient {
-    Arc::new(
-        create_client(OpenIDConnectConfig {
-            issuer_url: std::env::var("OIDC_ISSUER_URL").expect("OIDC_ISSUER_URL needs to be set"),
-            client_id: std::env::var("OIDC_CLIENT_ID").expect("OIDC_CLIENT_ID needs to be set"),
-            client_secret: std::env::var("OIDC_CLIENT_SECRET")
-                .expect("OIDC_CLIENT_SECRET needs to be set"),
-            redirect_url: std::env::var("OIDC_REDIRECT_URL")
-                .expect("OIDC_REDIRECT_URL needs to be set"),
-        })
-        .await
-        .expect("Error initializing OIDC client"),
-    )
+fn initialize_avatar_storage(config: &Config) -> LocalStorage {
+    LocalStorage::new(&config.avatar_dir).unwrap_or_else(|err| {
+        panic!(
+            "Error creating avatar directory {}: {err}",
+            config.avatar_dir
+        )
+    })
+}
+
+/// The built-in deny-list, extended by the configured file.
+fn initialize_name_policy(config: &Config) -> NamePolicy {
+    match config.name_deny_list {
+        Some(ref path) => NamePolicy::default()
+            .with_deny_list_file(path)
+            .unwrap_or_else(|err| panic!("Error reading name deny-list {path}: {err}")),
+        None => NamePolicy::default(),
+    }
+}
+
+#[cfg(feature = "dev-auth")]
+fn dev_auth_enabled() -> bool {
+    grooveguessr_backend::dev_auth::is_enabled()
+}
+
+#[cfg(not(feature = "dev-auth"))]
+fn dev_auth_enabled() -> bool {
+    false
+}
+
+/// Routes that only exist in builds with the `dev-auth` feature.
+fn dev_routes(_cfg: &mut web::ServiceConfig) {
+    #[cfg(feature = "dev-auth")]
+    grooveguessr_backend::dev_auth::routes(_cfg);
+}
+
+/// Discovers the endpoints of the configured OIDC providers. Logging out sends users to the
+/// provider's logout page, which redirects to their post logout redirect URL.
+async fn initialize_oidc_providers(config: Vec<OidcProviderConfig>) -> OidcProviders {
+    let mut providers = Vec::new();
+
+    for OidcProviderConfig {
+        name,
+        connect,
+        claims,
+    } in config
+    {
+        let provider = OidcProvider::discover(name.clone(), connect, claims)
+            .await
+            .unwrap_or_else(|err| panic!("Error initializing OIDC client {name}: {err}"));
+
+        providers.push(provider);
+    }
+
+    OidcProviders::new(providers)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    env_logger::init();
+
+    // dev auth works offline, so there doesn't have to be a provider at all
+    let config = Config::load(dev_auth_enabled()).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    telemetry::init(config.log_format);
+
+    #[cfg(feature = "dev-auth")]
+    grooveguessr_backend::dev_auth::ensure_enabled();
 
     // initialize outside of `HttpServer::new` so that it is shared across all workers
-    let db_pool = initialize_db_pool();
+    let metrics = Metrics::new();
+    let db_pool = initialize_db_pool(&config, &metrics);
     db_pool
         .get()
         .unwrap()
         .run_pending_migrations(MIGRATIONS)
         .expect("Error running pending migrations");
 
-    let redis = initialize_redis();
+    let redis = initialize_redis(&config);
+
+    let secret_key = Key::from(config.secret_key.as_bytes());
+    let session_ttl = Duration::hours(config.session_ttl_hours);
 
-    let secret_key = Key::from(
-        std::env::var("SECRET_KEY")
-            .expect("OIDC_CLIENT_SECRET needs to be set")
-            .as_bytes(),
-    );
+    let name_policy = Arc::new(initialize_name_policy(&config));
+    let avatar_service = AvatarService::new(Arc::new(initialize_avatar_storage(&config)));
+    let oidc_providers = initialize_oidc_providers(config.oidc_providers).await;
 
-    let oidc_client = initialize_oidc_client().await;
+    let repositories = Repositories::postgres(db_pool.clone(), redis.clone());
+    let session_service = SessionService::new(redis.clone());
 
-    let presence_service = PresenceService::new(redis.clone());
-    let lobby_service = LobbyService::new(db_pool.clone(), presence_service.clone());
-    let user_service = UserService::new(db_pool.clone());
-    let content_service = ContentService::new(db_pool.clone());
+    let services = Services::new(&repositories, name_policy, &config.game, metrics.clone());
+    let api_token_service = ApiTokenService::new(db_pool.clone());
+
+    let schema = schema_builder(
+        &repositories,
+        &services,
+        config.game.clone(),
+        metrics.clone(),
+    )
+    .data(db_pool.clone())
+    .data(redis.clone())
+    .data(session_service.clone())
+    .data(api_token_service.clone())
+    .data(avatar_service.clone())
+    .finish();
 
-    let schema = Schema::build(Query, Mutation, EmptySubscription)
-        .data(db_pool.clone())
-        .data(redis.clone())
-        .data(lobby_service)
-        .data(user_service)
-        .data(content_service)
-        .finish();
+    let health_service = HealthService::new(db_pool.clone(), redis.clone());
 
     let app_state = AppState {
         db_pool: db_pool.clone(),
         redis,
         schema,
-        oidc_client,
-        user_service: Arc::new(UserService::new(db_pool.clone())),
+        oidc_providers,
+        user_service: services.users,
+        lobby_service: services.lobbies,
+        game_service: services.games,
+        content_service: services.contents,
+        api_token_service,
+        avatar_service,
+        health_service,
+        presence_service: services.presence,
+        metrics: metrics.clone(),
     };
     let app_data = Data::new(app_state);
 
-    info!("GraphiQL IDE: http://localhost:8080/graphql");
+    info!("GraphiQL IDE: http://{}/graphql", config.bind_address);
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(
-                SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
-                    .session_lifecycle(PersistentSession::default().session_ttl(Duration::days(1)))
-                    .build(),
+                SessionMiddleware::builder(
+                    RedisSessionStore::new(session_service.clone()),
+                    secret_key.clone(),
+                )
+                .session_lifecycle(PersistentSession::default().session_ttl(session_ttl))
+                .build(),
             )
+            .wrap(RequestMetrics(metrics.clone()))
+            // outermost, so everything logged while handling a request carries its id
+            .wrap(TracingLogger::default())
             .app_data(app_data.clone())
+            .service(web::resource("/healthz").guard(guard::Get()).to(healthz))
+            .service(web::resource("/readyz").guard(guard::Get()).to(readyz))
+            .service(
+                web::resource("/metrics")
+                    .guard(guard::Get())
+                    .to(serve_metrics),
+            )
             .service(web::resource("/login").to(auth::login))
-            .service(web::resource("/auth_callback").to(auth::auth_callback))
+            .service(web::resource("/login/{provider}").to(auth::login_with_provider))
+            .service(web::resource("/guest_login").to(auth::guest_login))
+            .configure(dev_routes)
+            .service(
+                web::resource(["/auth_callback", "/auth_callback/{provider}"])
+                    .to(auth::auth_callback),
+            )
             .service(web::resource("/logout").to(auth::logout))
+            .service(
+                web::resource("/avatars/{key}")
+                    .guard(guard::Get())
+                    .to(serve_avatar),
+            )
+            .service(
+                web::resource("/lobbies/{id}/playlist.{format}")
+                    .guard(guard::Get())
+                    .wrap(AuthRequired)
+                    .to(export_playlist),
+            )
             .service(
                 web::resource("/graphql")
                     .guard(guard::Get())
@@ -157,8 +266,13 @@ async fn main() -> std::io::Result<()> {
                     .to(graphql),
             )
             .service(Files::new("/", "public").index_file("index.html"))
-    })
-    .bind("0.0.0.0:8080")?
+    });
+
+    match config.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    }
+    .bind(config.bind_address)?
     .run()
     .await
 }
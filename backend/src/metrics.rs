@@ -0,0 +1,352 @@
+//! Prometheus metrics, served as text at `/metrics`:
+//!
+//! - `grooveguessr_http_request_duration_seconds` by `method`, `route` and `status`
+//! - `grooveguessr_graphql_operation_duration_seconds` by `operation` and `status` (`ok`, `error`)
+//! - `grooveguessr_db_pool_connections` by `state` (`in_use`, `idle`)
+//! - `grooveguessr_db_pool_max_connections`
+//! - `grooveguessr_db_pool_wait_seconds`, `grooveguessr_db_pool_timeouts_total`
+//! - `grooveguessr_redis_duration_seconds` by presence `operation`
+//! - `grooveguessr_active_lobbies`, `grooveguessr_players_online`
+//! - `grooveguessr_games_started_total`, `grooveguessr_games_finished_total`
+//! - `grooveguessr_guesses_submitted_total`
+//!
+//! Routes are labelled with their pattern (`/lobbies/{id}/playlist.{format}`), so ids don't end
+//! up in label values. Gauges about the pool and presence are updated when they are scraped.
+
+use std::future::{ready, Ready};
+use std::sync::Arc;
+use std::time::Instant;
+
+use actix_web::{
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use async_graphql::async_trait::async_trait;
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextExecute};
+use async_graphql::Response;
+use futures_util::future::LocalBoxFuture;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+use crate::DbPool;
+
+/// All metrics of the server. Clones share the same values, every `Metrics::new` has its own
+/// registry, so tests don't count each other's games.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub http_request_duration: HistogramVec,
+    pub graphql_operation_duration: HistogramVec,
+    pub db_pool_connections: IntGaugeVec,
+    pub db_pool_max_connections: IntGauge,
+    pub db_pool_wait: Histogram,
+    pub db_pool_timeouts: IntCounter,
+    pub redis_duration: HistogramVec,
+    pub active_lobbies: IntGauge,
+    pub players_online: IntGauge,
+    pub games_started: IntCounter,
+    pub games_finished: IntCounter,
+    pub guesses_submitted: IntCounter,
+}
+
+/// Buckets for Redis and the pool, which should answer well below the default's 5ms.
+const FAST_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("grooveguessr".to_owned()), None)
+            .expect("Error creating metrics registry");
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to answer HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let graphql_operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "graphql_operation_duration_seconds",
+                "Time taken to execute GraphQL operations",
+            ),
+            &["operation", "status"],
+        )
+        .unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Database connections by state"),
+            &["state"],
+        )
+        .unwrap();
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum number of database connections",
+        )
+        .unwrap();
+        let db_pool_wait = Histogram::with_opts(
+            HistogramOpts::new(
+                "db_pool_wait_seconds",
+                "Time spent waiting for a database connection",
+            )
+            .buckets(FAST_BUCKETS.to_vec()),
+        )
+        .unwrap();
+        let db_pool_timeouts = IntCounter::new(
+            "db_pool_timeouts_total",
+            "Times no database connection became available in time",
+        )
+        .unwrap();
+        let redis_duration = HistogramVec::new(
+            HistogramOpts::new("redis_duration_seconds", "Time taken by presence lookups")
+                .buckets(FAST_BUCKETS.to_vec()),
+            &["operation"],
+        )
+        .unwrap();
+        let active_lobbies =
+            IntGauge::new("active_lobbies", "Lobbies with at least one player present").unwrap();
+        let players_online =
+            IntGauge::new("players_online", "Players present in any lobby").unwrap();
+        let games_started = IntCounter::new("games_started_total", "Games started").unwrap();
+        let games_finished = IntCounter::new("games_finished_total", "Games finished").unwrap();
+        let guesses_submitted =
+            IntCounter::new("guesses_submitted_total", "Guesses submitted").unwrap();
+
+        let metrics = Self {
+            registry,
+            http_request_duration,
+            graphql_operation_duration,
+            db_pool_connections,
+            db_pool_max_connections,
+            db_pool_wait,
+            db_pool_timeouts,
+            redis_duration,
+            active_lobbies,
+            players_online,
+            games_started,
+            games_finished,
+            guesses_submitted,
+        };
+
+        for collector in metrics.collectors() {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Error registering metric");
+        }
+
+        metrics
+    }
+
+    fn collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![
+            Box::new(self.http_request_duration.clone()),
+            Box::new(self.graphql_operation_duration.clone()),
+            Box::new(self.db_pool_connections.clone()),
+            Box::new(self.db_pool_max_connections.clone()),
+            Box::new(self.db_pool_wait.clone()),
+            Box::new(self.db_pool_timeouts.clone()),
+            Box::new(self.redis_duration.clone()),
+            Box::new(self.active_lobbies.clone()),
+            Box::new(self.players_online.clone()),
+            Box::new(self.games_started.clone()),
+            Box::new(self.games_finished.clone()),
+            Box::new(self.guesses_submitted.clone()),
+        ]
+    }
+
+    /// Updates the pool gauges with the current state of the pool.
+    pub fn record_db_pool(&self, db_pool: &DbPool) {
+        let state = db_pool.state();
+
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set((state.connections - state.idle_connections) as i64);
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(state.idle_connections as i64);
+        self.db_pool_max_connections.set(db_pool.max_size() as i64);
+    }
+
+    /// Records how long waiting for a connection took, attach it with `Builder::event_handler`.
+    pub fn db_pool_events(&self) -> DbPoolEvents {
+        DbPoolEvents {
+            wait: self.db_pool_wait.clone(),
+            timeouts: self.db_pool_timeouts.clone(),
+        }
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Error encoding metrics");
+
+        String::from_utf8(buffer).expect("Metrics are not valid UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct DbPoolEvents {
+    wait: Histogram,
+    timeouts: IntCounter,
+}
+
+impl r2d2::HandleEvent for DbPoolEvents {
+    fn handle_checkout(&self, event: r2d2::event::CheckoutEvent) {
+        self.wait.observe(event.duration().as_secs_f64());
+    }
+
+    fn handle_timeout(&self, _event: r2d2::event::TimeoutEvent) {
+        self.timeouts.inc();
+    }
+}
+
+/// Times every request by method, route pattern and status.
+pub struct RequestMetrics(pub Metrics);
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service,
+            metrics: self.0.clone(),
+        }))
+    }
+}
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let method = request.method().to_string();
+        let metrics = self.metrics.clone();
+        let response = self.service.call(request);
+
+        Box::pin(async move {
+            let response = response.await;
+
+            // the route is only known once the request has been routed, services mounted at `/`
+            // like the static files have an empty pattern
+            let (route, status) = match response {
+                Ok(ref response) => (
+                    match response.request().match_pattern() {
+                        Some(pattern) if pattern.is_empty() => "/*".to_owned(),
+                        Some(pattern) => pattern,
+                        None => "unmatched".to_owned(),
+                    },
+                    response.status(),
+                ),
+                Err(ref err) => (
+                    "unmatched".to_owned(),
+                    err.as_response_error().status_code(),
+                ),
+            };
+
+            metrics
+                .http_request_duration
+                .with_label_values(&[&method, &route, status.as_str()])
+                .observe(started.elapsed().as_secs_f64());
+
+            response
+        })
+    }
+}
+
+/// Times GraphQL operations by name, operations that return any error count as `error`.
+pub struct GraphQLMetrics(pub Metrics);
+
+impl ExtensionFactory for GraphQLMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLMetricsExtension(self.0.clone()))
+    }
+}
+
+struct GraphQLMetricsExtension(Metrics);
+
+#[async_trait]
+impl Extension for GraphQLMetricsExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let started = Instant::now();
+        let response = next.run(ctx, operation_name).await;
+        let status = if response.is_ok() { "ok" } else { "error" };
+
+        self.0
+            .graphql_operation_duration
+            .with_label_values(&[operation_name.unwrap_or("anonymous"), status])
+            .observe(started.elapsed().as_secs_f64());
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use diesel::PgConnection;
+
+    use super::Metrics;
+
+    #[test]
+    fn encodes_the_pool_state() {
+        let metrics = Metrics::new();
+        let db_pool =
+            Pool::builder()
+                .max_size(3)
+                .build_unchecked(ConnectionManager::<PgConnection>::new(
+                    "postgres://127.0.0.1:1/grooveguessr",
+                ));
+
+        metrics.record_db_pool(&db_pool);
+        metrics.games_started.inc();
+
+        let encoded = metrics.encode();
+
+        assert!(
+            encoded.contains("grooveguessr_db_pool_max_connections 3"),
+            "{encoded}"
+        );
+        assert!(encoded.contains("grooveguessr_db_pool_connections{state=\"in_use\"} 0"));
+        assert!(encoded.contains("grooveguessr_games_started_total 1"));
+    }
+}
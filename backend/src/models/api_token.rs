@@ -0,0 +1,117 @@
+use std::str::FromStr;
+
+use async_graphql::*;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db_schema::api_tokens;
+
+/// What a personal access token may be used for. Managing tokens and sessions always
+/// requires logging in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Enum)]
+pub enum TokenScope {
+    /// read your profile, lobbies, games and stats
+    Read,
+    /// create, configure, join and play lobbies
+    Play,
+    /// change your profile
+    Profile,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Play => "play",
+            TokenScope::Profile => "profile",
+        }
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "play" => Ok(TokenScope::Play),
+            "profile" => Ok(TokenScope::Profile),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, Queryable, Selectable, Insertable)]
+#[diesel(table_name = api_tokens)]
+#[graphql(complex)]
+pub struct ApiToken {
+    pub id: uuid::Uuid,
+    #[graphql(skip)]
+    pub user_id: String,
+    pub name: String,
+    #[graphql(skip)]
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    #[graphql(skip)]
+    pub scopes: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ApiToken {
+    /// The scopes of the token, unknown ones are dropped.
+    pub fn scopes(&self) -> Vec<TokenScope> {
+        self.scopes
+            .split(',')
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+
+    pub fn join_scopes(scopes: &[TokenScope]) -> String {
+        scopes
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<&str>>()
+            .join(",")
+    }
+}
+
+#[ComplexObject]
+impl ApiToken {
+    #[graphql(name = "scopes")]
+    async fn graphql_scopes(&self) -> Vec<TokenScope> {
+        self.scopes()
+    }
+}
+
+/// A newly created token, the only time its secret is shown.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct CreatedApiToken {
+    pub token: ApiToken,
+    /// send as `Authorization: Bearer <secret>`
+    pub secret: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiToken, TokenScope};
+
+    #[test]
+    fn scopes_round_trip_through_their_string_form() {
+        let scopes = ApiToken::join_scopes(&[TokenScope::Read, TokenScope::Play]);
+
+        assert_eq!(scopes, "read,play");
+
+        let token = ApiToken {
+            id: uuid::Uuid::new_v4(),
+            user_id: "1".to_string(),
+            name: "bot".to_string(),
+            token_hash: String::new(),
+            scopes: format!("{scopes},admin"),
+            created_at: chrono::Utc::now(),
+            last_used_at: None,
+        };
+
+        assert_eq!(token.scopes(), vec![TokenScope::Read, TokenScope::Play]);
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+use super::{
+    api_token::ApiToken,
+    content::Contents,
+    game::GamePlayer,
+    lobby::LobbyPlayers,
+    stats::{UserCompatibility, UserGuessTarget, UserStats},
+    user::{User, UserIdentity},
+};
+
+/// Everything stored about a user, as handed out by `exportMyData`.
+#[derive(Debug, Serialize)]
+pub struct UserDataExport {
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub user: User,
+    pub identities: Vec<UserIdentity>,
+    pub api_tokens: Vec<ApiToken>,
+    pub lobbies: Vec<LobbyPlayers>,
+    pub contents: Vec<Contents>,
+    pub games: Vec<GamePlayer>,
+    pub stats: Option<UserStats>,
+    pub guess_targets: Vec<UserGuessTarget>,
+    pub compatibility: Vec<UserCompatibility>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::api_token::ApiToken;
+
+    #[test]
+    fn token_hashes_are_not_exported() {
+        let token = ApiToken {
+            id: uuid::Uuid::new_v4(),
+            user_id: "alice".to_string(),
+            name: "bot".to_string(),
+            token_hash: "0123456789abcdef".to_string(),
+            scopes: "read".to_string(),
+            created_at: chrono::Utc::now(),
+            last_used_at: None,
+        };
+
+        let json = serde_json::to_value(&token).unwrap();
+
+        assert_eq!(json["name"], "bot");
+        assert!(json.get("token_hash").is_none());
+    }
+}
@@ -0,0 +1,165 @@
+use async_graphql::*;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db_schema::{game_players, game_rounds, games},
+    services::{blocking, game::GameService},
+};
+
+use super::{
+    scoreboard::Scoreboard,
+    stats::{UserCompatibility, UserGuessTarget, UserStats},
+};
+
+/// The immutable record of a finished game.
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    SimpleObject,
+    Queryable,
+    Identifiable,
+    Selectable,
+    Insertable,
+)]
+#[diesel(table_name = games)]
+#[graphql(complex)]
+pub struct Game {
+    pub id: String,
+    pub host_id: String,
+    pub host_name: String,
+    pub guessing_time: i16,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[ComplexObject]
+impl Game {
+    async fn players(&self, ctx: &Context<'_>) -> FieldResult<Vec<GamePlayer>> {
+        let game_service = ctx.data::<GameService>().unwrap().clone();
+        let game = self.clone();
+
+        let players = blocking(move || game_service.players(&game))
+            .await
+            .extend()?;
+
+        Ok(players)
+    }
+
+    async fn winners(&self, ctx: &Context<'_>) -> FieldResult<Vec<GamePlayer>> {
+        let game_service = ctx.data::<GameService>().unwrap().clone();
+        let game = self.clone();
+
+        let players = blocking(move || game_service.players(&game))
+            .await
+            .extend()?;
+
+        Ok(players.into_iter().filter(|p| p.is_winner).collect())
+    }
+
+    async fn rounds(&self, ctx: &Context<'_>) -> FieldResult<Vec<GameRound>> {
+        let game_service = ctx.data::<GameService>().unwrap().clone();
+        let game = self.clone();
+
+        let rounds = blocking(move || game_service.rounds(&game))
+            .await
+            .extend()?;
+
+        Ok(rounds)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    SimpleObject,
+    Queryable,
+    Identifiable,
+    Selectable,
+    Insertable,
+    Associations,
+)]
+#[diesel(belongs_to(Game))]
+#[diesel(table_name = game_players)]
+#[diesel(primary_key(game_id, player_id))]
+#[graphql(complex)]
+pub struct GamePlayer {
+    #[graphql(skip)]
+    pub game_id: String,
+    pub player_id: String,
+    /// the name of the player at the time the game was played
+    pub name: String,
+    pub score: i32,
+    pub is_winner: bool,
+    #[graphql(skip)]
+    pub guesses: String,
+}
+
+#[ComplexObject]
+impl GamePlayer {
+    /// the guessed user id per round
+    async fn guesses(&self) -> Vec<String> {
+        if self.guesses.is_empty() {
+            return Vec::new();
+        }
+
+        self.guesses.split(',').map(|s| s.to_owned()).collect()
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    SimpleObject,
+    Queryable,
+    Identifiable,
+    Selectable,
+    Insertable,
+    Associations,
+)]
+#[diesel(belongs_to(Game))]
+#[diesel(table_name = game_rounds)]
+#[diesel(primary_key(game_id, round_index))]
+pub struct GameRound {
+    #[graphql(skip)]
+    pub game_id: String,
+    pub round_index: i16,
+    pub owner_id: String,
+    /// the name of the owner at the time the game was played
+    pub owner_name: String,
+    pub content_type: Option<String>,
+    pub content_data: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct GamePage {
+    pub games: Vec<Game>,
+    pub total: i64,
+    pub has_more: bool,
+}
+
+/// A lobby that just finished, archived along with what it adds to the stats of its players.
+#[derive(Debug, Clone)]
+pub struct FinishedGame {
+    pub game: Game,
+    pub players: Vec<GamePlayer>,
+    pub rounds: Vec<GameRound>,
+    pub stats: Vec<UserStats>,
+    pub guess_targets: Vec<UserGuessTarget>,
+    pub compatibility: Vec<UserCompatibility>,
+}
+
+pub fn scoreboard(rounds: &[GameRound], players: &[GamePlayer]) -> Scoreboard {
+    Scoreboard::build(
+        rounds.iter().map(|r| r.owner_id.as_str()),
+        players
+            .iter()
+            .map(|p| (p.player_id.as_str(), p.guesses.as_str())),
+    )
+}
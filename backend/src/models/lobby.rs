@@ -1,3 +1,4 @@
+use async_graphql::dataloader::DataLoader;
 use async_graphql::*;
 use diesel::prelude::*;
 use rand::Rng;
@@ -7,10 +8,16 @@ use crate::{
     auth::UserInfo,
     db_schema::lobbies::{self},
     db_schema::lobbies_players,
-    services::{content::ContentService, lobby::LobbyService, user::UserService, Error},
+    loaders::{ContentLoader, LobbyPlayersLoader, UserLoader},
+    services::{avatar::avatar_url, blocking, content::ContentService, Error, Resource},
 };
 
-use super::{content::Contents, user::User};
+use super::{
+    content::Contents,
+    name::disambiguate_names,
+    playlist::Playlist,
+    user::{PublicUser, User},
+};
 
 #[derive(
     Debug,
@@ -35,6 +42,8 @@ pub struct Lobby {
     pub sequence: Option<String>,
     pub current_user_id: Option<String>,
     pub created_at: chrono::NaiveDateTime,
+    pub finished_at: Option<chrono::NaiveDateTime>,
+    pub rematch_id: Option<String>,
 }
 
 fn generate_random_string(length: usize) -> String {
@@ -55,6 +64,8 @@ impl Default for Lobby {
             current_user_id: None,
             created_at: chrono::Utc::now().naive_utc(),
             host_id: "".to_string(),
+            finished_at: None,
+            rematch_id: None,
         }
     }
 }
@@ -63,6 +74,7 @@ impl Default for Lobby {
 struct Player {
     id: String,
     name: String,
+    avatar_url: Option<String>,
     is_ready: bool,
 }
 
@@ -93,85 +105,161 @@ impl Lobby {
 
         Ok(self)
     }
+
+    /// Marks the game as finished, which is only possible once the last round has been reached.
+    pub fn finish(mut self) -> Result<Self, Error> {
+        if self.finished_at.is_some() {
+            return Err(Error::GameAlreadyFinished);
+        }
+
+        let current_user_index = self.current_user_index().ok_or(Error::GameNotStarted)?;
+        let rounds = self
+            .sequence
+            .as_ref()
+            .map(|sequence| sequence.split(',').count())
+            .unwrap_or_default();
+
+        if current_user_index != rounds - 1 {
+            return Err(Error::GameNotFinished);
+        }
+
+        self.finished_at = Some(chrono::Utc::now().naive_utc());
+
+        Ok(self)
+    }
+
+    /// Creates a fresh lobby with the same host and settings to play another round in.
+    pub fn rematch(&self) -> Self {
+        Self {
+            host_id: self.host_id.clone(),
+            guessing_time: self.guessing_time,
+            ..Default::default()
+        }
+    }
 }
 
 #[ComplexObject]
 impl Lobby {
-    async fn host(&self, ctx: &Context<'_>) -> FieldResult<User> {
-        let user_service = ctx.data::<UserService>().unwrap();
+    async fn host(&self, ctx: &Context<'_>) -> FieldResult<PublicUser> {
+        let user_loader = ctx.data::<DataLoader<UserLoader>>().unwrap();
 
-        let user = user_service
-            .find(&self.host_id)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+        let user = user_loader
+            .load_one(self.host_id.clone())
+            .await?
+            .ok_or_else(|| Error::NotFound(Resource::User).extend())?;
 
-        Ok(user)
+        Ok(user.into())
     }
 
     async fn content(&self, ctx: &Context<'_>) -> FieldResult<Option<Contents>> {
-        let content_service = ctx.data::<ContentService>().unwrap();
+        let content_loader = ctx.data::<DataLoader<ContentLoader>>().unwrap();
+        let user = &ctx.data::<UserInfo>().unwrap().user;
 
-        let content = content_service
-            .find(self, &ctx.data::<UserInfo>().unwrap().user)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+        let content = content_loader
+            .load_one((self.id.clone(), user.id.clone()))
+            .await?;
 
         Ok(content)
     }
 
     async fn current_content(&self, ctx: &Context<'_>) -> FieldResult<Option<Contents>> {
-        let content_service = ctx.data::<ContentService>().unwrap();
+        let content_loader = ctx.data::<DataLoader<ContentLoader>>().unwrap();
+
+        let Some(ref current_user_id) = self.current_user_id else {
+            return Ok(None);
+        };
 
-        let content = content_service
-            .current(self)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+        let content = content_loader
+            .load_one((self.id.clone(), current_user_id.clone()))
+            .await?;
 
         Ok(content)
     }
 
     async fn guesses(&self, ctx: &Context<'_>) -> FieldResult<Vec<String>> {
-        let user = ctx.data::<UserInfo>().unwrap().user.clone();
+        let user = &ctx.data::<UserInfo>().unwrap().user;
+        let players_loader = ctx.data::<DataLoader<LobbyPlayersLoader>>().unwrap();
 
-        let lobby_service = ctx.data::<LobbyService>().unwrap();
+        let players = players_loader
+            .load_one(self.id.clone())
+            .await?
+            .unwrap_or_default();
 
-        let guesses = lobby_service
-            .guesses(self, &user)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+        let guesses = players
+            .into_iter()
+            .find(|player| player.player_id == user.id)
+            .filter(|player| !player.guesses.is_empty())
+            .map(|player| player.guesses.split(',').map(|s| s.to_owned()).collect())
+            .unwrap_or_default();
 
         Ok(guesses)
     }
 
+    /// the content of a finished game in the order it was played, for its players only
+    async fn playlist(&self, ctx: &Context<'_>) -> FieldResult<Playlist> {
+        let user_id = ctx.data::<UserInfo>().unwrap().user.id.clone();
+        let content_service = ctx.data::<ContentService>().unwrap().clone();
+        let lobby = self.clone();
+
+        let playlist = blocking(move || content_service.playlist(&lobby, &user_id))
+            .await
+            .extend()?;
+
+        Ok(playlist)
+    }
+
     async fn round_index(&self) -> FieldResult<Option<usize>> {
         Ok(self.current_user_index())
     }
 
+    /// in the order they joined, players sharing a name are numbered
     async fn players(&self, ctx: &Context<'_>) -> FieldResult<Vec<Player>> {
-        let user_service = ctx.data::<UserService>().unwrap();
-        let lobby_service = ctx.data::<LobbyService>().unwrap();
+        let players_loader = ctx.data::<DataLoader<LobbyPlayersLoader>>().unwrap();
+        let user_loader = ctx.data::<DataLoader<UserLoader>>().unwrap();
 
-        let players = lobby_service
-            .find_players(self)
-            .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+        let players = players_loader
+            .load_one(self.id.clone())
+            .await?
+            .unwrap_or_default();
 
-        let mut users: Vec<Player> = Vec::new();
+        let users = user_loader
+            .load_many(players.iter().map(|player| player.player_id.clone()))
+            .await?;
+
+        let mut lobby_players: Vec<Player> = Vec::new();
 
         for player in players {
-            let user = user_service
-                .find(&player.player_id)
-                .map_err(|err: Error| err.extend_with(|_, e| e.set("code", 404)))?;
+            let user = users
+                .get(&player.player_id)
+                .ok_or_else(|| Error::NotFound(Resource::User).extend())?;
 
             let new_player = Player {
                 id: user.id.clone(),
                 name: user.name.clone(),
+                avatar_url: user.avatar.as_deref().map(avatar_url),
                 is_ready: player.is_ready,
             };
 
-            users.push(new_player);
+            lobby_players.push(new_player);
+        }
+
+        let mut names = lobby_players
+            .iter()
+            .map(|player| player.name.clone())
+            .collect::<Vec<String>>();
+        disambiguate_names(&mut names);
+
+        for (player, name) in lobby_players.iter_mut().zip(names) {
+            player.name = name;
         }
 
-        Ok(users)
+        Ok(lobby_players)
     }
 }
 
-#[derive(Identifiable, Selectable, Queryable, Associations, Insertable, Debug)]
+#[derive(
+    Identifiable, Selectable, Queryable, Associations, Insertable, Debug, Clone, Serialize,
+)]
 #[diesel(belongs_to(Lobby))]
 #[diesel(belongs_to(User, foreign_key = player_id))]
 #[diesel(table_name = lobbies_players)]
@@ -249,6 +337,50 @@ mod tests {
         assert!(lobby.forward().is_err());
     }
 
+    #[test]
+    fn will_finish_in_the_last_round() {
+        let lobby = lobby::Lobby {
+            sequence: Some("1,2,3".to_string()),
+            current_user_id: Some("3".to_string()),
+            ..Default::default()
+        };
+
+        let finished_lobby = lobby.finish().unwrap();
+
+        assert!(finished_lobby.finished_at.is_some());
+        assert!(finished_lobby.finish().is_err());
+    }
+
+    #[test]
+    fn will_return_error_for_finish_if_there_are_rounds_left() {
+        let lobby = lobby::Lobby {
+            sequence: Some("1,2,3".to_string()),
+            current_user_id: Some("2".to_string()),
+            ..Default::default()
+        };
+
+        assert!(lobby.finish().is_err());
+    }
+
+    #[test]
+    fn rematch_keeps_host_and_settings() {
+        let lobby = lobby::Lobby {
+            host_id: "1".to_string(),
+            guessing_time: 42,
+            sequence: Some("1,2,3".to_string()),
+            current_user_id: Some("3".to_string()),
+            ..Default::default()
+        };
+
+        let rematch = lobby.rematch();
+
+        assert_ne!(rematch.id, lobby.id);
+        assert_eq!(rematch.host_id, "1");
+        assert_eq!(rematch.guessing_time, 42);
+        assert_eq!(rematch.sequence, None);
+        assert_eq!(rematch.current_user_id, None);
+    }
+
     #[test]
     fn will_return_current_user_index() {
         let lobby = lobby::Lobby {
@@ -1,3 +1,10 @@
 pub mod lobby;
 pub mod user;
 pub mod content;
+pub mod scoreboard;
+pub mod stats;
+pub mod game;
+pub mod playlist;
+pub mod api_token;
+pub mod name;
+pub mod export;
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::services::Error;
+
+pub const MIN_NAME_LENGTH: usize = 2;
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Names nobody should be able to pose as, always part of the deny-list.
+const RESERVED_NAMES: [&str; 5] = [
+    "admin",
+    "administrator",
+    "moderator",
+    "grooveguessr",
+    "system",
+];
+
+/// Decides which display names are acceptable.
+///
+/// Entries of the deny-list match whole words, also when they are spelled out across
+/// several words (`s l u r`), while entries starting with `*` match anywhere in a name.
+/// Both are compared case-insensitively and with common digit substitutions undone.
+#[derive(Debug, Clone)]
+pub struct NamePolicy {
+    words: Vec<String>,
+    fragments: Vec<String>,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        Self {
+            words: Vec::new(),
+            fragments: Vec::new(),
+        }
+        .with_deny_list(RESERVED_NAMES)
+    }
+}
+
+/// Lowercases the text, undoes digit substitutions like `3` for `e` and drops everything
+/// that isn't a letter or digit.
+fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' => 'i',
+            '3' => 'e',
+            '4' => 'a',
+            '5' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+fn is_allowed_char(c: char) -> bool {
+    c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' || c == '.' || c == '\''
+}
+
+impl NamePolicy {
+    pub fn with_deny_list<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for entry in entries {
+            let entry = entry.as_ref().trim();
+
+            let (list, entry) = match entry.strip_prefix('*') {
+                Some(fragment) => (&mut self.fragments, fold(fragment)),
+                None => (&mut self.words, fold(entry)),
+            };
+
+            if !entry.is_empty() && !list.contains(&entry) {
+                list.push(entry);
+            }
+        }
+
+        self
+    }
+
+    /// Adds the entries of a file with one entry per line, `#` starts a comment.
+    pub fn with_deny_list_file(self, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        Ok(self.with_deny_list(
+            contents
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#')),
+        ))
+    }
+
+    fn is_denied(&self, name: &str) -> bool {
+        let folded = fold(name);
+
+        if self
+            .fragments
+            .iter()
+            .any(|fragment| folded.contains(fragment.as_str()))
+        {
+            return true;
+        }
+
+        let words = name
+            .split(|c: char| !c.is_alphanumeric())
+            .map(fold)
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<String>>();
+
+        // every run of consecutive words, so spelling a word out doesn't get it past the list
+        (0..words.len()).any(|start| {
+            (start + 1..=words.len()).any(|end| self.words.contains(&words[start..end].concat()))
+        })
+    }
+
+    /// Trims the name and collapses whitespace, then checks its length, characters and the
+    /// deny-list. Returns the name as it should be stored.
+    pub fn validate(&self, name: &str) -> Result<String, Error> {
+        let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let length = name.chars().count();
+
+        if !(MIN_NAME_LENGTH..=MAX_NAME_LENGTH).contains(&length) {
+            return Err(Error::InvalidInput(format!(
+                "name must be between {MIN_NAME_LENGTH} and {MAX_NAME_LENGTH} characters"
+            )));
+        }
+
+        if !name.chars().all(is_allowed_char) || !name.chars().any(char::is_alphanumeric) {
+            return Err(Error::InvalidInput(
+                "name may only contain letters, digits, spaces, -, _, . and '".to_string(),
+            ));
+        }
+
+        if self.is_denied(&name) {
+            return Err(Error::InvalidInput("this name is not allowed".to_string()));
+        }
+
+        Ok(name)
+    }
+
+    /// For names taken from elsewhere, e.g. a login provider, which are dropped rather than
+    /// refused when they don't pass.
+    pub fn sanitize(&self, name: &str) -> Option<String> {
+        self.validate(name).ok()
+    }
+}
+
+/// Appends ` (2)`, ` (3)` and so on to names that already appeared earlier in the list,
+/// ignoring case, so players sharing a name can still be told apart.
+pub fn disambiguate_names(names: &mut [String]) {
+    let mut taken = names
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect::<HashSet<String>>();
+    let mut seen = HashSet::new();
+
+    for name in names.iter_mut() {
+        if seen.insert(name.to_lowercase()) {
+            continue;
+        }
+
+        let unique = (2..)
+            .map(|n| format!("{name} ({n})"))
+            .find(|candidate| !taken.contains(&candidate.to_lowercase()))
+            .unwrap();
+
+        taken.insert(unique.to_lowercase());
+        seen.insert(unique.to_lowercase());
+        *name = unique;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disambiguate_names, NamePolicy};
+
+    #[test]
+    fn names_are_validated() {
+        let policy = NamePolicy::default().with_deny_list(["badword", "*worse"]);
+
+        assert_eq!(policy.validate("  Mary   Jane ").unwrap(), "Mary Jane");
+        assert_eq!(policy.validate("O'Neil-2").unwrap(), "O'Neil-2");
+        assert_eq!(policy.validate("Zoë").unwrap(), "Zoë");
+        assert_eq!(policy.validate("badminton").unwrap(), "badminton");
+
+        assert!(policy.validate(" ").is_err());
+        assert!(policy.validate("a").is_err());
+        assert!(policy.validate(&"a".repeat(33)).is_err());
+        assert!(policy.validate("<script>").is_err());
+        assert!(policy.validate("--").is_err());
+
+        assert!(policy.validate("Admin").is_err());
+        assert!(policy.validate("the BADWORD").is_err());
+        assert!(policy.validate("b a d w o r d").is_err());
+        assert!(policy.validate("b4dw0rd").is_err());
+        assert!(policy.validate("badwording").is_ok());
+        assert!(policy.validate("superw0rse").is_err());
+    }
+
+    #[test]
+    fn duplicate_names_are_numbered() {
+        let mut names = ["bob", "Alice", "Bob", "bob (2)", "bob"].map(String::from);
+
+        disambiguate_names(&mut names);
+
+        assert_eq!(names, ["bob", "Alice", "Bob (3)", "bob (2)", "bob (4)"]);
+    }
+}
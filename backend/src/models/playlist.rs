@@ -0,0 +1,238 @@
+use std::str::FromStr;
+
+use async_graphql::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+/// The content every player submitted to a finished lobby, in the order it was played.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct Playlist {
+    pub title: String,
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct PlaylistTrack {
+    pub url: String,
+    pub submitter_id: String,
+    pub submitter_name: String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PlaylistFormat {
+    M3u,
+    Jspf,
+    Youtube,
+}
+
+impl FromStr for PlaylistFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "m3u" => Ok(PlaylistFormat::M3u),
+            "jspf" => Ok(PlaylistFormat::Jspf),
+            "youtube" => Ok(PlaylistFormat::Youtube),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Extracts the video id from the common forms of YouTube links.
+fn youtube_video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url
+        .host_str()?
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+
+    let id = match host {
+        "youtu.be" => url.path_segments()?.next().map(|s| s.to_owned()),
+        "youtube.com" | "music.youtube.com" => {
+            let mut segments = url.path_segments()?;
+
+            match segments.next() {
+                Some("watch") => url
+                    .query_pairs()
+                    .find(|(key, _)| key == "v")
+                    .map(|(_, value)| value.into_owned()),
+                Some("embed") | Some("shorts") | Some("live") | Some("v") => {
+                    segments.next().map(|s| s.to_owned())
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    id.filter(|id| !id.is_empty())
+}
+
+/// M3U is line based, so a line break in a name or url would start an entry of its own.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+impl Playlist {
+    fn annotation(track: &PlaylistTrack) -> String {
+        format!("Submitted by {}", track.submitter_name)
+    }
+
+    pub fn to_m3u(&self) -> String {
+        let mut m3u = format!("#EXTM3U\n#PLAYLIST:{}\n", single_line(&self.title));
+
+        for track in &self.tracks {
+            m3u.push_str(&format!(
+                "#EXTINF:-1,{}\n{}\n",
+                single_line(&Self::annotation(track)),
+                single_line(&track.url)
+            ));
+        }
+
+        m3u
+    }
+
+    pub fn to_jspf(&self) -> String {
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| {
+                json!({
+                    "location": [track.url],
+                    "annotation": Self::annotation(track),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "playlist": {
+                "title": self.title,
+                "track": tracks,
+            }
+        })
+        .to_string()
+    }
+
+    /// A link that plays all YouTube videos of the playlist in a row, if there are any.
+    pub fn to_youtube_url(&self) -> Option<String> {
+        let video_ids = self
+            .tracks
+            .iter()
+            .filter_map(|track| youtube_video_id(&track.url))
+            .collect::<Vec<String>>();
+
+        if video_ids.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "https://www.youtube.com/watch_videos?video_ids={}",
+            video_ids.join(",")
+        ))
+    }
+}
+
+#[ComplexObject]
+impl Playlist {
+    async fn m3u(&self) -> String {
+        self.to_m3u()
+    }
+
+    async fn jspf(&self) -> String {
+        self.to_jspf()
+    }
+
+    async fn youtube_url(&self) -> Option<String> {
+        self.to_youtube_url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{youtube_video_id, Playlist, PlaylistTrack};
+
+    fn playlist() -> Playlist {
+        Playlist {
+            title: "grooveguessr".to_string(),
+            tracks: vec![
+                PlaylistTrack {
+                    url: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string(),
+                    submitter_id: "1".to_string(),
+                    submitter_name: "grumpy cat".to_string(),
+                },
+                PlaylistTrack {
+                    url: "https://youtu.be/9bZkp7q19f0?t=42".to_string(),
+                    submitter_id: "2".to_string(),
+                    submitter_name: "lazy dog".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn extracts_youtube_video_ids() {
+        assert_eq!(
+            youtube_video_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1"),
+            Some("dQw4w9WgXcQ".to_string())
+        );
+        assert_eq!(
+            youtube_video_id("https://youtu.be/dQw4w9WgXcQ"),
+            Some("dQw4w9WgXcQ".to_string())
+        );
+        assert_eq!(
+            youtube_video_id("https://m.youtube.com/shorts/dQw4w9WgXcQ"),
+            Some("dQw4w9WgXcQ".to_string())
+        );
+        assert_eq!(youtube_video_id("https://vimeo.com/76979871"), None);
+        assert_eq!(youtube_video_id("not a url"), None);
+    }
+
+    #[test]
+    fn renders_m3u_with_submitters() {
+        assert_eq!(
+            playlist().to_m3u(),
+            "#EXTM3U\n#PLAYLIST:grooveguessr\n\
+             #EXTINF:-1,Submitted by grumpy cat\nhttps://www.youtube.com/watch?v=dQw4w9WgXcQ\n\
+             #EXTINF:-1,Submitted by lazy dog\nhttps://youtu.be/9bZkp7q19f0?t=42\n"
+        );
+    }
+
+    #[test]
+    fn keeps_m3u_entries_on_their_lines() {
+        let mut playlist = playlist();
+        playlist.tracks[0].submitter_name = "grumpy\r\n#EXTINF:-1,cat".to_string();
+        playlist.tracks[1].url = "https://youtu.be/9bZkp7q19f0\nfile:///etc/passwd".to_string();
+
+        let m3u = playlist.to_m3u();
+
+        assert_eq!(m3u.lines().count(), 6);
+        assert!(m3u.contains("#EXTINF:-1,Submitted by grumpy  #EXTINF:-1,cat\n"));
+        assert!(m3u.contains("\nhttps://youtu.be/9bZkp7q19f0 file:///etc/passwd\n"));
+    }
+
+    #[test]
+    fn renders_jspf_in_round_order() {
+        let jspf: serde_json::Value = serde_json::from_str(&playlist().to_jspf()).unwrap();
+        let tracks = jspf["playlist"]["track"].as_array().unwrap();
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[1]["annotation"], "Submitted by lazy dog");
+        assert_eq!(
+            tracks[1]["location"][0],
+            "https://youtu.be/9bZkp7q19f0?t=42"
+        );
+    }
+
+    #[test]
+    fn renders_youtube_watch_list() {
+        assert_eq!(
+            playlist().to_youtube_url(),
+            Some(
+                "https://www.youtube.com/watch_videos?video_ids=dQw4w9WgXcQ,9bZkp7q19f0"
+                    .to_string()
+            )
+        );
+    }
+}
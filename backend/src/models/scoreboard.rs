@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::services::Error;
+
+use super::{
+    lobby::{Lobby, LobbyPlayers},
+    stats::{UserCompatibility, UserGuessTarget, UserStats},
+};
+
+/// A single round of a game: whose content was played and whom every player guessed.
+#[derive(Debug, Clone)]
+pub struct Round {
+    pub owner_id: String,
+    /// pairs of (guessing player id, guessed user id)
+    pub guesses: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scoreboard {
+    pub player_ids: Vec<String>,
+    pub rounds: Vec<Round>,
+}
+
+impl Scoreboard {
+    pub fn new(lobby: &Lobby, players: &[LobbyPlayers]) -> Result<Self, Error> {
+        let sequence = lobby.sequence.as_ref().ok_or(Error::GameNotStarted)?;
+
+        Ok(Self::build(
+            sequence.split(','),
+            players
+                .iter()
+                .map(|p| (p.player_id.as_str(), p.guesses.as_str())),
+        ))
+    }
+
+    /// Builds a scoreboard from the round owners in order and every player's comma separated guesses.
+    pub fn build<'a>(
+        owner_ids: impl Iterator<Item = &'a str>,
+        players: impl Iterator<Item = (&'a str, &'a str)> + Clone,
+    ) -> Self {
+        let mut rounds: Vec<Round> = owner_ids
+            .map(|owner_id| Round {
+                owner_id: owner_id.to_owned(),
+                guesses: Vec::new(),
+            })
+            .collect();
+
+        let mut player_ids: Vec<String> = players
+            .clone()
+            .map(|(player_id, _)| player_id.to_owned())
+            .collect();
+
+        // players that left the lobby still played if their content was part of the game
+        for round in &rounds {
+            if !player_ids.contains(&round.owner_id) {
+                player_ids.push(round.owner_id.clone());
+            }
+        }
+
+        for (player_id, guesses) in players {
+            if guesses.is_empty() {
+                continue;
+            }
+
+            for (round, guessed_id) in rounds.iter_mut().zip(guesses.split(',')) {
+                if !guessed_id.is_empty() {
+                    round
+                        .guesses
+                        .push((player_id.to_owned(), guessed_id.to_owned()));
+                }
+            }
+        }
+
+        Self { player_ids, rounds }
+    }
+
+    /// All guesses a player made on other players' content as (round owner id, guessed user id).
+    fn guesses_by<'a>(&'a self, player_id: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.rounds
+            .iter()
+            .filter(move |round| round.owner_id != player_id)
+            .flat_map(move |round| {
+                round
+                    .guesses
+                    .iter()
+                    .filter(move |(guesser_id, _)| guesser_id == player_id)
+                    .map(move |(_, guessed_id)| (round.owner_id.as_str(), guessed_id.as_str()))
+            })
+    }
+
+    /// All guesses other players made on the content of the given player.
+    fn guesses_on<'a>(&'a self, player_id: &'a str) -> impl Iterator<Item = &'a str> {
+        self.rounds
+            .iter()
+            .filter(move |round| round.owner_id == player_id)
+            .flat_map(move |round| {
+                round
+                    .guesses
+                    .iter()
+                    .filter(move |(guesser_id, _)| guesser_id != player_id)
+                    .map(|(_, guessed_id)| guessed_id.as_str())
+            })
+    }
+
+    /// The user a player guessed in every round, empty if they didn't guess.
+    pub fn guesses(&self, player_id: &str) -> Vec<&str> {
+        self.rounds
+            .iter()
+            .map(|round| {
+                round
+                    .guesses
+                    .iter()
+                    .find(|(guesser_id, _)| guesser_id == player_id)
+                    .map(|(_, guessed_id)| guessed_id.as_str())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    pub fn score(&self, player_id: &str) -> usize {
+        self.guesses_by(player_id)
+            .filter(|(owner_id, guessed_id)| owner_id == guessed_id)
+            .count()
+    }
+
+    /// Every player with the highest score, ties are shared.
+    pub fn winner_ids(&self) -> Vec<String> {
+        let best = match self.player_ids.iter().map(|p| self.score(p)).max() {
+            Some(best) if best > 0 => best,
+            _ => return Vec::new(),
+        };
+
+        self.player_ids
+            .iter()
+            .filter(|p| self.score(p) == best)
+            .cloned()
+            .collect()
+    }
+
+    /// The increments this game adds to each player's lifetime stats.
+    pub fn user_stats(&self) -> Vec<UserStats> {
+        let winner_ids = self.winner_ids();
+
+        self.player_ids
+            .iter()
+            .map(|player_id| {
+                let songs_guessed = self.guesses_on(player_id).count();
+                let songs_recognised = self
+                    .guesses_on(player_id)
+                    .filter(|guessed_id| guessed_id == player_id)
+                    .count();
+
+                UserStats {
+                    user_id: player_id.clone(),
+                    games_played: 1,
+                    games_won: winner_ids.contains(player_id) as i32,
+                    guesses_made: self.guesses_by(player_id).count() as i32,
+                    guesses_correct: self.score(player_id) as i32,
+                    songs_guessed: songs_guessed as i32,
+                    songs_recognised: songs_recognised as i32,
+                    updated_at: chrono::Utc::now(),
+                }
+            })
+            .collect()
+    }
+
+    /// How often each player guessed each other user in this game.
+    pub fn guess_targets(&self) -> Vec<UserGuessTarget> {
+        let mut targets: HashMap<(&str, &str), i32> = HashMap::new();
+
+        for player_id in &self.player_ids {
+            for (_, guessed_id) in self.guesses_by(player_id) {
+                *targets.entry((player_id, guessed_id)).or_default() += 1;
+            }
+        }
+
+        targets
+            .into_iter()
+            .map(|((user_id, target_id), guesses)| UserGuessTarget {
+                user_id: user_id.to_owned(),
+                target_id: target_id.to_owned(),
+                guesses,
+            })
+            .collect()
+    }
+
+    /// How often each player guessed on each other player's content and how often they were right.
+    pub fn compatibility(&self) -> Vec<UserCompatibility> {
+        let mut pairs: BTreeMap<(&str, &str), (i32, i32)> = BTreeMap::new();
+
+        for round in &self.rounds {
+            for (guesser_id, guessed_id) in &round.guesses {
+                if *guesser_id == round.owner_id {
+                    continue;
+                }
+
+                let (rounds, correct) = pairs.entry((guesser_id, &round.owner_id)).or_default();
+
+                *rounds += 1;
+                *correct += (*guessed_id == round.owner_id) as i32;
+            }
+        }
+
+        pairs
+            .into_iter()
+            .map(
+                |((guesser_id, owner_id), (rounds, correct))| UserCompatibility {
+                    guesser_id: guesser_id.to_owned(),
+                    owner_id: owner_id.to_owned(),
+                    rounds,
+                    correct,
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::lobby::{Lobby, LobbyPlayers};
+
+    use super::Scoreboard;
+
+    fn player(id: &str, guesses: &str) -> LobbyPlayers {
+        LobbyPlayers {
+            lobby_id: "lobby".to_string(),
+            player_id: id.to_string(),
+            is_ready: true,
+            guesses: guesses.to_string(),
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn scoreboard() -> Scoreboard {
+        let lobby = Lobby {
+            sequence: Some("a,b,c".to_string()),
+            current_user_id: Some("c".to_string()),
+            ..Default::default()
+        };
+
+        Scoreboard::new(
+            &lobby,
+            &[
+                player("a", "a,b,c"),
+                player("b", "c,b,a"),
+                player("c", "b,a,c"),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn scores_ignore_guesses_on_own_content() {
+        let scoreboard = scoreboard();
+
+        assert_eq!(scoreboard.score("a"), 2);
+        assert_eq!(scoreboard.score("b"), 0);
+        assert_eq!(scoreboard.score("c"), 0);
+        assert_eq!(scoreboard.winner_ids(), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn user_stats_count_guesses_and_recognitions() {
+        let stats = scoreboard().user_stats();
+        let a = stats.iter().find(|s| s.user_id == "a").unwrap();
+        let b = stats.iter().find(|s| s.user_id == "b").unwrap();
+
+        assert_eq!(a.games_played, 1);
+        assert_eq!(a.games_won, 1);
+        assert_eq!(a.guesses_made, 2);
+        assert_eq!(a.guesses_correct, 2);
+        assert_eq!(a.songs_guessed, 2);
+        assert_eq!(a.songs_recognised, 0);
+
+        assert_eq!(b.games_won, 0);
+        assert_eq!(b.songs_guessed, 2);
+        assert_eq!(b.songs_recognised, 1);
+    }
+
+    #[test]
+    fn compatibility_is_tracked_per_pair() {
+        let compatibility = scoreboard().compatibility();
+        let pair = |guesser_id: &str, owner_id: &str| {
+            compatibility
+                .iter()
+                .find(|c| c.guesser_id == guesser_id && c.owner_id == owner_id)
+                .map(|c| (c.rounds, c.correct))
+        };
+
+        assert_eq!(compatibility.len(), 6);
+        assert_eq!(pair("a", "b"), Some((1, 1)));
+        assert_eq!(pair("c", "b"), Some((1, 0)));
+        assert_eq!(pair("a", "a"), None);
+    }
+
+    #[test]
+    fn players_who_left_still_played() {
+        let lobby = Lobby {
+            sequence: Some("a,b".to_string()),
+            current_user_id: Some("b".to_string()),
+            ..Default::default()
+        };
+
+        let scoreboard = Scoreboard::new(&lobby, &[player("a", "a,b")]).unwrap();
+
+        assert_eq!(
+            scoreboard.player_ids,
+            vec!["a".to_string(), "b".to_string()]
+        );
+        assert_eq!(scoreboard.score("a"), 1);
+    }
+
+    #[test]
+    fn nobody_wins_without_correct_guesses() {
+        let lobby = Lobby {
+            sequence: Some("a,b".to_string()),
+            current_user_id: Some("b".to_string()),
+            ..Default::default()
+        };
+
+        let scoreboard = Scoreboard::new(&lobby, &[player("a", ""), player("b", "")]).unwrap();
+
+        assert!(scoreboard.winner_ids().is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use async_graphql::*;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db_schema::{user_compatibility, user_guess_targets, user_stats},
+    services::{blocking, stats::StatsService},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, Queryable, Selectable, Insertable)]
+#[diesel(table_name = user_stats)]
+#[diesel(primary_key(user_id))]
+#[graphql(complex)]
+pub struct UserStats {
+    #[graphql(skip)]
+    pub user_id: String,
+    pub games_played: i32,
+    pub games_won: i32,
+    pub guesses_made: i32,
+    pub guesses_correct: i32,
+    /// how often other players guessed on songs submitted by this user
+    pub songs_guessed: i32,
+    /// how often other players correctly identified songs submitted by this user
+    pub songs_recognised: i32,
+    #[graphql(skip)]
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl UserStats {
+    pub fn empty(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            games_played: 0,
+            games_won: 0,
+            guesses_made: 0,
+            guesses_correct: 0,
+            songs_guessed: 0,
+            songs_recognised: 0,
+            updated_at: chrono::Utc::now(),
+        }
+    }
+}
+
+fn ratio(part: i32, total: i32) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    part as f64 / total as f64
+}
+
+#[ComplexObject]
+impl UserStats {
+    /// share of correct guesses on other players' songs
+    async fn accuracy(&self) -> f64 {
+        ratio(self.guesses_correct, self.guesses_made)
+    }
+
+    /// share of guesses on this user's songs that identified them correctly
+    async fn recognisability(&self) -> f64 {
+        ratio(self.songs_recognised, self.songs_guessed)
+    }
+
+    /// the users this user guessed most often
+    async fn favourite_targets(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 3)] limit: i64,
+    ) -> FieldResult<Vec<GuessTarget>> {
+        let stats_service = ctx.data::<StatsService>().unwrap().clone();
+        let user_id = self.user_id.clone();
+
+        let targets = blocking(move || stats_service.favourite_targets(&user_id, limit))
+            .await
+            .extend()?;
+
+        Ok(targets)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = user_guess_targets)]
+#[diesel(primary_key(user_id, target_id))]
+pub struct UserGuessTarget {
+    pub user_id: String,
+    pub target_id: String,
+    pub guesses: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, Queryable)]
+pub struct GuessTarget {
+    pub user_id: String,
+    pub name: String,
+    pub guesses: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = user_compatibility)]
+#[diesel(primary_key(guesser_id, owner_id))]
+pub struct UserCompatibility {
+    pub guesser_id: String,
+    pub owner_id: String,
+    pub rounds: i32,
+    pub correct: i32,
+}
+
+/// How well one player predicts another player's taste.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct Compatibility {
+    pub guesser_id: String,
+    pub guesser_name: String,
+    pub owner_id: String,
+    pub owner_name: String,
+    /// rounds in which the guesser guessed on content submitted by the owner
+    pub rounds: i32,
+    /// rounds in which the guesser correctly identified the owner
+    pub correct: i32,
+    pub score: f64,
+}
+
+impl Compatibility {
+    pub fn new(compatibility: UserCompatibility, names: &HashMap<String, String>) -> Self {
+        let name = |user_id: &String| names.get(user_id).cloned().unwrap_or_default();
+
+        Self {
+            guesser_name: name(&compatibility.guesser_id),
+            owner_name: name(&compatibility.owner_id),
+            score: ratio(compatibility.correct, compatibility.rounds),
+            guesser_id: compatibility.guesser_id,
+            owner_id: compatibility.owner_id,
+            rounds: compatibility.rounds,
+            correct: compatibility.correct,
+        }
+    }
+}
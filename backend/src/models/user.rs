@@ -1,8 +1,14 @@
-use async_graphql::SimpleObject;
+use async_graphql::*;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::db_schema::users;
+use crate::{
+    auth::UserInfo,
+    db_schema::{user_identities, users},
+    services::{avatar::avatar_url, blocking, stats::StatsService, user::UserService, Error},
+};
+
+use super::{name::NamePolicy, stats::UserStats};
 
 #[derive(
     Debug,
@@ -17,9 +23,336 @@ use crate::db_schema::users;
     AsChangeset,
 )]
 #[diesel(table_name = users)]
+#[graphql(complex)]
 pub struct User {
     pub id: String,
-    pub email: String,
+    /// only visible to the user themselves and admins
+    #[graphql(skip)]
+    pub email: Option<String>,
     pub name: String,
     pub created_at: chrono::NaiveDateTime,
+    pub is_guest: bool,
+    pub is_admin: bool,
+    /// storage key of the uploaded avatar
+    #[graphql(skip)]
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub bio: Option<String>,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub genres: String,
+}
+
+pub const MAX_BIO_LENGTH: usize = 280;
+pub const MAX_GENRES: usize = 10;
+pub const MAX_GENRE_LENGTH: usize = 30;
+
+/// Lowercases a genre tag and checks it only holds letters, digits, spaces, `-` and `&`.
+fn normalize_genre(genre: &str) -> Result<String, Error> {
+    let genre = genre.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let genre = genre.to_lowercase();
+
+    if genre.is_empty() || genre.chars().count() > MAX_GENRE_LENGTH {
+        return Err(Error::InvalidInput(format!(
+            "genres must be between 1 and {MAX_GENRE_LENGTH} characters"
+        )));
+    }
+
+    if !genre
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '&')
+    {
+        return Err(Error::InvalidInput(format!(
+            "genre {genre} may only contain letters, digits, spaces, - and &"
+        )));
+    }
+
+    Ok(genre)
+}
+
+fn split_genres(genres: &str) -> Vec<String> {
+    genres
+        .split(',')
+        .filter(|genre| !genre.is_empty())
+        .map(|genre| genre.to_owned())
+        .collect()
+}
+
+impl User {
+    /// Validates and applies the given profile changes, leaving out what's `None`.
+    /// An empty bio removes it.
+    pub fn update_profile(
+        &mut self,
+        names: &NamePolicy,
+        name: Option<String>,
+        bio: Option<String>,
+        genres: Option<Vec<String>>,
+    ) -> Result<(), Error> {
+        if let Some(name) = name {
+            self.name = names.validate(&name)?;
+        }
+
+        if let Some(bio) = bio {
+            let bio = bio.trim();
+
+            if bio.chars().count() > MAX_BIO_LENGTH {
+                return Err(Error::InvalidInput(format!(
+                    "bio must be at most {MAX_BIO_LENGTH} characters"
+                )));
+            }
+
+            self.bio = Some(bio.to_owned()).filter(|bio| !bio.is_empty());
+        }
+
+        if let Some(genres) = genres {
+            let mut normalized: Vec<String> = Vec::new();
+
+            for genre in genres {
+                let genre = normalize_genre(&genre)?;
+
+                if !normalized.contains(&genre) {
+                    normalized.push(genre);
+                }
+            }
+
+            if normalized.len() > MAX_GENRES {
+                return Err(Error::InvalidInput(format!(
+                    "at most {MAX_GENRES} genres are allowed"
+                )));
+            }
+
+            self.genres = normalized.join(",");
+        }
+
+        Ok(())
+    }
+}
+
+/// Changes to your own profile, fields that are left out stay as they are.
+#[derive(InputObject)]
+pub struct ProfileInput {
+    pub name: Option<String>,
+    /// an empty bio removes it
+    pub bio: Option<String>,
+    pub genres: Option<Vec<String>>,
+    /// a PNG, JPEG, GIF or WebP image, cropped to a square
+    pub avatar: Option<Upload>,
+    #[graphql(default)]
+    pub remove_avatar: bool,
+}
+
+#[ComplexObject]
+impl User {
+    #[graphql(name = "email")]
+    async fn visible_email(&self, ctx: &Context<'_>) -> FieldResult<Option<String>> {
+        let viewer = &ctx.data::<UserInfo>()?.user;
+
+        if viewer.id == self.id {
+            return Ok(self.email.clone());
+        }
+
+        // the flag in the session only spares the lookup, demoted admins lose access right away
+        if viewer.is_admin {
+            let user_service = ctx.data::<UserService>()?.clone();
+            let viewer_id = viewer.id.clone();
+
+            if blocking(move || user_service.is_admin(&viewer_id))
+                .await
+                .extend()?
+            {
+                return Ok(self.email.clone());
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn avatar_url(&self) -> Option<String> {
+        self.avatar.as_deref().map(avatar_url)
+    }
+
+    #[graphql(name = "genres")]
+    async fn genre_list(&self) -> Vec<String> {
+        split_genres(&self.genres)
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> FieldResult<UserStats> {
+        let stats_service = ctx.data::<StatsService>().unwrap().clone();
+        let user_id = self.id.clone();
+
+        let stats = blocking(move || stats_service.find(&user_id))
+            .await
+            .extend()?;
+
+        Ok(stats)
+    }
+}
+
+/// What other players get to see of a user.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct PublicUser {
+    pub id: String,
+    pub name: String,
+    pub is_guest: bool,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub genres: Vec<String>,
+}
+
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        Self {
+            avatar_url: user.avatar.as_deref().map(avatar_url),
+            genres: split_genres(&user.genres),
+            id: user.id,
+            name: user.name,
+            is_guest: user.is_guest,
+            bio: user.bio,
+        }
+    }
+}
+
+#[ComplexObject]
+impl PublicUser {
+    async fn stats(&self, ctx: &Context<'_>) -> FieldResult<UserStats> {
+        let stats_service = ctx.data::<StatsService>().unwrap().clone();
+        let user_id = self.id.clone();
+
+        let stats = blocking(move || stats_service.find(&user_id))
+            .await
+            .extend()?;
+
+        Ok(stats)
+    }
+}
+
+/// Links the subject of an OIDC provider to a user.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = user_identities)]
+#[diesel(primary_key(provider, subject))]
+pub struct UserIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub user_id: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+
+    use crate::auth::UserInfo;
+    use crate::handler::graphql_handler::{Mutation, Query};
+
+    use super::{NamePolicy, PublicUser, User};
+
+    fn user(id: &str) -> User {
+        User {
+            id: id.to_string(),
+            email: Some(format!("{id}@example.com")),
+            name: id.to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+            is_guest: false,
+            is_admin: false,
+            avatar: None,
+            bio: None,
+            genres: String::new(),
+        }
+    }
+
+    struct UserQuery;
+
+    #[Object]
+    impl UserQuery {
+        async fn alice(&self) -> User {
+            user("alice")
+        }
+
+        async fn public_alice(&self) -> PublicUser {
+            user("alice").into()
+        }
+    }
+
+    fn email_as(viewer: &str, query: &str) -> async_graphql::Response {
+        let schema = Schema::build(UserQuery, EmptyMutation, EmptySubscription)
+            .data(UserInfo::guest(user(viewer)))
+            .finish();
+
+        futures::executor::block_on(schema.execute(query))
+    }
+
+    #[test]
+    fn email_is_only_visible_to_the_user_themselves() {
+        let own = email_as("alice", "{ alice { email } }");
+        assert_eq!(
+            own.data.into_json().unwrap()["alice"]["email"],
+            "alice@example.com"
+        );
+
+        let other = email_as("bob", "{ alice { email } }");
+        assert!(other.errors.is_empty());
+        assert!(other.data.into_json().unwrap()["alice"]["email"].is_null());
+
+        let public = email_as("bob", "{ publicAlice { email } }");
+        assert!(!public.errors.is_empty());
+    }
+
+    #[test]
+    fn profile_updates_are_validated() {
+        let mut user = user("alice");
+        let names = NamePolicy::default();
+
+        user.update_profile(
+            &names,
+            Some("  Alice ".to_string()),
+            Some(" ".to_string()),
+            Some(vec![
+                "Hip  Hop".to_string(),
+                "drum & bass".to_string(),
+                "hip hop".to_string(),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(user.name, "Alice");
+        assert_eq!(user.bio, None);
+        assert_eq!(user.genres, "hip hop,drum & bass");
+
+        assert!(user
+            .update_profile(&names, Some(" ".to_string()), None, None)
+            .is_err());
+        assert!(user
+            .update_profile(&names, None, Some("a".repeat(281)), None)
+            .is_err());
+        assert!(user
+            .update_profile(&names, None, None, Some(vec!["<script>".to_string()]))
+            .is_err());
+        assert!(user
+            .update_profile(
+                &names,
+                None,
+                None,
+                Some((0..11).map(|i| format!("genre {i}")).collect())
+            )
+            .is_err());
+        assert_eq!(user.name, "Alice");
+    }
+
+    #[test]
+    fn other_players_are_public_users() {
+        let sdl = Schema::build(Query, Mutation, EmptySubscription)
+            .finish()
+            .sdl();
+
+        let public_user = sdl
+            .split("type PublicUser {")
+            .nth(1)
+            .and_then(|rest| rest.split('}').next())
+            .unwrap();
+
+        assert!(!public_user.contains("email"));
+        assert!(sdl.contains("host: PublicUser!"));
+    }
 }
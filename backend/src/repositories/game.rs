@@ -0,0 +1,129 @@
+use diesel::prelude::*;
+
+use crate::{
+    db_schema::{game_players, game_rounds, games},
+    models::game::{Game, GamePlayer, GameRound},
+    services::Error,
+};
+
+use super::PgRepository;
+
+/// The archive of finished games.
+pub trait GameRepository: Send + Sync {
+    fn insert(
+        &self,
+        game: &Game,
+        players: &[GamePlayer],
+        rounds: &[GameRound],
+    ) -> Result<(), Error>;
+
+    fn find(&self, game_id: &str) -> Result<Option<Game>, Error>;
+
+    /// A page of the games a user played in, most recent first, and how many there are in total.
+    fn find_by_player(
+        &self,
+        player_id: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Game>, i64), Error>;
+
+    /// The players of a game, the best first.
+    fn players(&self, game_id: &str) -> Result<Vec<GamePlayer>, Error>;
+
+    fn rounds(&self, game_id: &str) -> Result<Vec<GameRound>, Error>;
+}
+
+impl GameRepository for PgRepository {
+    fn insert(
+        &self,
+        game: &Game,
+        players: &[GamePlayer],
+        rounds: &[GameRound],
+    ) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| insert(conn, game, players, rounds))
+    }
+
+    fn find(&self, game_id: &str) -> Result<Option<Game>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let game = games::table
+            .filter(games::id.eq(game_id))
+            .first::<Game>(&mut conn)
+            .optional()?;
+
+        Ok(game)
+    }
+
+    fn find_by_player(
+        &self,
+        player_id: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Game>, i64), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let game_ids = game_players::table
+            .filter(game_players::player_id.eq(player_id))
+            .select(game_players::game_id);
+
+        let total = games::table
+            .filter(games::id.eq_any(game_ids))
+            .count()
+            .get_result::<i64>(&mut conn)?;
+
+        let games = games::table
+            .filter(games::id.eq_any(game_ids))
+            .order(games::finished_at.desc())
+            .offset(offset)
+            .limit(limit)
+            .get_results::<Game>(&mut conn)?;
+
+        Ok((games, total))
+    }
+
+    fn players(&self, game_id: &str) -> Result<Vec<GamePlayer>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let players = game_players::table
+            .filter(game_players::game_id.eq(game_id))
+            .order((game_players::score.desc(), game_players::name.asc()))
+            .get_results::<GamePlayer>(&mut conn)?;
+
+        Ok(players)
+    }
+
+    fn rounds(&self, game_id: &str) -> Result<Vec<GameRound>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let rounds = game_rounds::table
+            .filter(game_rounds::game_id.eq(game_id))
+            .order(game_rounds::round_index.asc())
+            .get_results::<GameRound>(&mut conn)?;
+
+        Ok(rounds)
+    }
+}
+
+/// Archives a game on the given connection, so it can be part of a bigger transaction.
+pub(super) fn insert(
+    conn: &mut PgConnection,
+    game: &Game,
+    players: &[GamePlayer],
+    rounds: &[GameRound],
+) -> Result<(), Error> {
+    diesel::insert_into(games::table)
+        .values(game)
+        .execute(conn)?;
+
+    diesel::insert_into(game_players::table)
+        .values(players)
+        .execute(conn)?;
+
+    diesel::insert_into(game_rounds::table)
+        .values(rounds)
+        .execute(conn)?;
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use diesel::{prelude::*, upsert::on_constraint};
+
+use crate::{
+    db_schema::{contents, lobbies, lobbies_players},
+    models::{content::Contents, game::FinishedGame, lobby::Lobby, lobby::LobbyPlayers},
+    services::Error,
+};
+
+use super::PgRepository;
+
+/// Lobbies along with their players and the content they submitted.
+pub trait LobbyRepository: Send + Sync {
+    fn insert(&self, lobby: &Lobby) -> Result<(), Error>;
+
+    fn find(&self, lobby_id: &str) -> Result<Option<Lobby>, Error>;
+
+    fn update(&self, lobby: &Lobby) -> Result<(), Error>;
+
+    /// Adds the player unless they already joined.
+    fn add_player(&self, player: &LobbyPlayers) -> Result<(), Error>;
+
+    fn find_player(&self, lobby_id: &str, player_id: &str) -> Result<Option<LobbyPlayers>, Error>;
+
+    /// The players of the lobbies in the order they joined.
+    fn players(&self, lobby_ids: &[String]) -> Result<Vec<LobbyPlayers>, Error>;
+
+    fn set_ready(&self, lobby_id: &str, player_id: &str, ready: bool) -> Result<(), Error>;
+
+    fn set_guesses(&self, lobby_id: &str, player_id: &str, guesses: &str) -> Result<(), Error>;
+
+    /// Removes every player of the lobby that isn't in `player_ids`.
+    fn retain_players(&self, lobby_id: &str, player_ids: &[String]) -> Result<(), Error>;
+
+    /// Adds the content or replaces what the user submitted before.
+    fn save_content(&self, content: &Contents) -> Result<(), Error>;
+
+    /// The content of the lobbies, limited to the given users if there are any.
+    fn contents(&self, lobby_ids: &[String], user_ids: &[String]) -> Result<Vec<Contents>, Error>;
+
+    /// Adds the rematch of a lobby with its players and content in one go. Only the first of
+    /// concurrent calls does so, the others get the rematch it created.
+    fn insert_rematch(
+        &self,
+        lobby_id: &str,
+        rematch: &Lobby,
+        players: &[LobbyPlayers],
+        contents: &[Contents],
+    ) -> Result<Lobby, Error>;
+
+    /// Saves the finished lobby, archives its game and adds it to the stats of its players in one
+    /// go. Only the first of concurrent calls does so, the others return `false` and change
+    /// nothing.
+    fn finish(&self, lobby: &Lobby, finished: &FinishedGame) -> Result<bool, Error>;
+}
+
+impl LobbyRepository for PgRepository {
+    fn insert(&self, lobby: &Lobby) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::insert_into(lobbies::table)
+            .values(lobby)
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn find(&self, lobby_id: &str) -> Result<Option<Lobby>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let lobby = lobbies::table
+            .filter(lobbies::id.eq(lobby_id))
+            .get_result::<Lobby>(&mut conn)
+            .optional()?;
+
+        Ok(lobby)
+    }
+
+    fn update(&self, lobby: &Lobby) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::update(lobbies::table)
+            .filter(lobbies::id.eq(&lobby.id))
+            .set(lobby)
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn add_player(&self, player: &LobbyPlayers) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::insert_into(lobbies_players::table)
+            .values(player)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn find_player(&self, lobby_id: &str, player_id: &str) -> Result<Option<LobbyPlayers>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let player = lobbies_players::table
+            .filter(lobbies_players::lobby_id.eq(lobby_id))
+            .filter(lobbies_players::player_id.eq(player_id))
+            .first::<LobbyPlayers>(&mut conn)
+            .optional()?;
+
+        Ok(player)
+    }
+
+    fn players(&self, lobby_ids: &[String]) -> Result<Vec<LobbyPlayers>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let players = lobbies_players::table
+            .filter(lobbies_players::lobby_id.eq_any(lobby_ids))
+            .order(lobbies_players::created_at.asc())
+            .get_results::<LobbyPlayers>(&mut conn)?;
+
+        Ok(players)
+    }
+
+    fn set_ready(&self, lobby_id: &str, player_id: &str, ready: bool) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::update(lobbies_players::table)
+            .filter(lobbies_players::lobby_id.eq(lobby_id))
+            .filter(lobbies_players::player_id.eq(player_id))
+            .set(lobbies_players::is_ready.eq(ready))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn set_guesses(&self, lobby_id: &str, player_id: &str, guesses: &str) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::update(lobbies_players::table)
+            .filter(lobbies_players::lobby_id.eq(lobby_id))
+            .filter(lobbies_players::player_id.eq(player_id))
+            .set(lobbies_players::guesses.eq(guesses))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn retain_players(&self, lobby_id: &str, player_ids: &[String]) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::delete(
+            lobbies_players::table
+                .filter(lobbies_players::lobby_id.eq(lobby_id))
+                .filter(lobbies_players::player_id.ne_all(player_ids)),
+        )
+        .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn save_content(&self, content: &Contents) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::insert_into(contents::table)
+            .values(content)
+            .on_conflict(on_constraint("contents_pkey"))
+            .do_update()
+            .set((
+                contents::data.eq(&content.data),
+                contents::type_.eq(&content.type_),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn contents(&self, lobby_ids: &[String], user_ids: &[String]) -> Result<Vec<Contents>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let mut query = contents::table
+            .filter(contents::lobby_id.eq_any(lobby_ids))
+            .into_boxed();
+
+        if !user_ids.is_empty() {
+            query = query.filter(contents::user_id.eq_any(user_ids));
+        }
+
+        Ok(query.get_results::<Contents>(&mut conn)?)
+    }
+
+    fn insert_rematch(
+        &self,
+        lobby_id: &str,
+        rematch: &Lobby,
+        players: &[LobbyPlayers],
+        contents: &[Contents],
+    ) -> Result<Lobby, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            // lock the finished lobby so that concurrent requests end up in the same rematch
+            let lobby = lobbies::table
+                .filter(lobbies::id.eq(lobby_id))
+                .for_update()
+                .get_result::<Lobby>(conn)?;
+
+            if let Some(ref existing_id) = lobby.rematch_id {
+                return Ok(lobbies::table
+                    .filter(lobbies::id.eq(existing_id))
+                    .get_result::<Lobby>(conn)?);
+            }
+
+            diesel::insert_into(lobbies::table)
+                .values(rematch)
+                .execute(conn)?;
+
+            diesel::insert_into(lobbies_players::table)
+                .values(players)
+                .execute(conn)?;
+
+            diesel::insert_into(contents::table)
+                .values(contents)
+                .execute(conn)?;
+
+            diesel::update(lobbies::table)
+                .filter(lobbies::id.eq(lobby_id))
+                .set(lobbies::rematch_id.eq(&rematch.id))
+                .execute(conn)?;
+
+            Ok(rematch.clone())
+        })
+    }
+
+    fn finish(&self, lobby: &Lobby, finished: &FinishedGame) -> Result<bool, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let updated = diesel::update(lobbies::table)
+                .filter(lobbies::id.eq(&lobby.id))
+                .filter(lobbies::finished_at.is_null())
+                .set(lobby)
+                .execute(conn)?;
+
+            if updated == 0 {
+                return Ok(false);
+            }
+
+            super::game::insert(conn, &finished.game, &finished.players, &finished.rounds)?;
+            super::stats::record(
+                conn,
+                &finished.stats,
+                &finished.guess_targets,
+                &finished.compatibility,
+            )?;
+
+            Ok(true)
+        })
+    }
+}
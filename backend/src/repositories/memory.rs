@@ -0,0 +1,1062 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{
+    models::{
+        content::Contents,
+        export::UserDataExport,
+        game::{FinishedGame, Game, GamePlayer, GameRound},
+        lobby::{Lobby, LobbyPlayers},
+        stats::{GuessTarget, UserCompatibility, UserGuessTarget, UserStats},
+        user::{User, UserIdentity},
+    },
+    services::Error,
+};
+
+use super::{GameRepository, LobbyRepository, PresenceRepository, StatsRepository, UserRepository};
+
+#[derive(Default)]
+struct Tables {
+    users: Vec<User>,
+    identities: Vec<UserIdentity>,
+    lobbies: Vec<Lobby>,
+    players: Vec<LobbyPlayers>,
+    contents: Vec<Contents>,
+    games: Vec<Game>,
+    game_players: Vec<GamePlayer>,
+    game_rounds: Vec<GameRound>,
+    stats: Vec<UserStats>,
+    guess_targets: Vec<UserGuessTarget>,
+    compatibility: Vec<UserCompatibility>,
+    /// when the presence of (lobby id, player id) expires
+    presence: HashMap<(String, String), Instant>,
+}
+
+/// Keeps everything in memory for tests and local experiments, nothing survives a restart.
+/// Behaves like the Postgres and Redis backends, apart from not knowing any personal access
+/// tokens.
+#[derive(Default)]
+pub struct MemoryRepository {
+    tables: Mutex<Tables>,
+    lookups: AtomicUsize,
+}
+
+impl MemoryRepository {
+    /// How often the tables were accessed, what checking out a connection is for Postgres.
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::SeqCst)
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+
+        // a panicking test must not take the others down with it
+        self.tables.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Replaces a user id in a comma separated list of them.
+fn replace_id(list: &mut String, from: &str, to: &str) {
+    if list.contains(from) {
+        *list = list
+            .split(',')
+            .map(|id| if id == from { to } else { id })
+            .collect::<Vec<&str>>()
+            .join(",");
+    }
+}
+
+fn add_stats(stats: &mut UserStats, other: &UserStats) {
+    stats.games_played += other.games_played;
+    stats.games_won += other.games_won;
+    stats.guesses_made += other.guesses_made;
+    stats.guesses_correct += other.guesses_correct;
+    stats.songs_guessed += other.songs_guessed;
+    stats.songs_recognised += other.songs_recognised;
+    stats.updated_at = other.updated_at;
+}
+
+impl Tables {
+    fn merge_stats(&mut self, stats: UserStats) {
+        match self.stats.iter_mut().find(|s| s.user_id == stats.user_id) {
+            Some(existing) => add_stats(existing, &stats),
+            None => self.stats.push(stats),
+        }
+    }
+
+    fn merge_guess_target(&mut self, target: UserGuessTarget) {
+        match self
+            .guess_targets
+            .iter_mut()
+            .find(|t| t.user_id == target.user_id && t.target_id == target.target_id)
+        {
+            Some(existing) => existing.guesses += target.guesses,
+            None => self.guess_targets.push(target),
+        }
+    }
+
+    fn merge_compatibility(&mut self, pair: UserCompatibility) {
+        match self
+            .compatibility
+            .iter_mut()
+            .find(|c| c.guesser_id == pair.guesser_id && c.owner_id == pair.owner_id)
+        {
+            Some(existing) => {
+                existing.rounds += pair.rounds;
+                existing.correct += pair.correct;
+            }
+            None => self.compatibility.push(pair),
+        }
+    }
+
+    /// Points lobbies, guesses and archived games of one user to another. Rows the other user
+    /// already has for the same lobby or game stay with the first one.
+    fn move_history(&mut self, from: &str, to: &str) {
+        for lobby in &mut self.lobbies {
+            if lobby.host_id == from {
+                lobby.host_id = to.to_owned();
+            }
+
+            if lobby.current_user_id.as_deref() == Some(from) {
+                lobby.current_user_id = Some(to.to_owned());
+            }
+
+            if let Some(ref mut sequence) = lobby.sequence {
+                replace_id(sequence, from, to);
+            }
+        }
+
+        let joined = |rows: &[LobbyPlayers], lobby_id: &str| {
+            rows.iter()
+                .any(|p| p.lobby_id == lobby_id && p.player_id == to)
+        };
+        for index in 0..self.players.len() {
+            if self.players[index].player_id == from
+                && !joined(&self.players, &self.players[index].lobby_id)
+            {
+                self.players[index].player_id = to.to_owned();
+            }
+
+            replace_id(&mut self.players[index].guesses, from, to);
+        }
+
+        for index in 0..self.contents.len() {
+            let lobby_id = self.contents[index].lobby_id.clone();
+
+            if self.contents[index].user_id == from
+                && !self
+                    .contents
+                    .iter()
+                    .any(|c| c.lobby_id == lobby_id && c.user_id == to)
+            {
+                self.contents[index].user_id = to.to_owned();
+            }
+        }
+
+        for game in &mut self.games {
+            if game.host_id == from {
+                game.host_id = to.to_owned();
+            }
+        }
+
+        for index in 0..self.game_players.len() {
+            let game_id = self.game_players[index].game_id.clone();
+
+            if self.game_players[index].player_id == from
+                && !self
+                    .game_players
+                    .iter()
+                    .any(|p| p.game_id == game_id && p.player_id == to)
+            {
+                self.game_players[index].player_id = to.to_owned();
+            }
+
+            replace_id(&mut self.game_players[index].guesses, from, to);
+        }
+
+        for round in &mut self.game_rounds {
+            if round.owner_id == from {
+                round.owner_id = to.to_owned();
+            }
+        }
+    }
+}
+
+impl UserRepository for MemoryRepository {
+    fn register(&self, user: &User) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        match tables.users.iter_mut().find(|u| u.id == user.id) {
+            Some(existing) => existing.email = user.email.clone(),
+            None => tables.users.push(user.clone()),
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, user_id: &str) -> Result<Option<User>, Error> {
+        Ok(self
+            .tables()
+            .users
+            .iter()
+            .find(|u| u.id == user_id)
+            .cloned())
+    }
+
+    fn find_many(&self, user_ids: &[String]) -> Result<Vec<User>, Error> {
+        Ok(self
+            .tables()
+            .users
+            .iter()
+            .filter(|u| user_ids.contains(&u.id))
+            .cloned()
+            .collect())
+    }
+
+    fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+        Ok(self
+            .tables()
+            .users
+            .iter()
+            .find(|u| u.email.as_deref() == Some(email))
+            .cloned())
+    }
+
+    fn find_by_identity(&self, provider: &str, subject: &str) -> Result<Option<User>, Error> {
+        let tables = self.tables();
+
+        let Some(identity) = tables
+            .identities
+            .iter()
+            .find(|i| i.provider == provider && i.subject == subject)
+        else {
+            return Ok(None);
+        };
+
+        Ok(tables
+            .users
+            .iter()
+            .find(|u| u.id == identity.user_id)
+            .cloned())
+    }
+
+    fn insert_identity(&self, user: &User, identity: &UserIdentity) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        if !tables.users.iter().any(|u| u.id == user.id) {
+            tables.users.push(user.clone());
+        }
+
+        tables.identities.push(identity.clone());
+
+        Ok(())
+    }
+
+    fn save(&self, user: &User) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        if let Some(existing) = tables.users.iter_mut().find(|u| u.id == user.id) {
+            *existing = user.clone();
+        }
+
+        Ok(())
+    }
+
+    fn upgrade_guest(&self, guest_id: &str, user_id: &str) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        tables.move_history(guest_id, user_id);
+
+        let stats = tables
+            .stats
+            .iter()
+            .filter(|s| s.user_id == guest_id)
+            .map(|s| UserStats {
+                user_id: user_id.to_owned(),
+                ..s.clone()
+            })
+            .collect::<Vec<UserStats>>();
+        let targets = tables
+            .guess_targets
+            .iter()
+            .filter(|t| (t.user_id == guest_id) != (t.target_id == guest_id))
+            .map(|t| UserGuessTarget {
+                user_id: if t.user_id == guest_id {
+                    user_id
+                } else {
+                    &t.user_id
+                }
+                .to_owned(),
+                target_id: if t.target_id == guest_id {
+                    user_id
+                } else {
+                    &t.target_id
+                }
+                .to_owned(),
+                guesses: t.guesses,
+            })
+            .collect::<Vec<UserGuessTarget>>();
+        let pairs = tables
+            .compatibility
+            .iter()
+            .filter(|c| (c.guesser_id == guest_id) != (c.owner_id == guest_id))
+            .map(|c| UserCompatibility {
+                guesser_id: if c.guesser_id == guest_id {
+                    user_id
+                } else {
+                    &c.guesser_id
+                }
+                .to_owned(),
+                owner_id: if c.owner_id == guest_id {
+                    user_id
+                } else {
+                    &c.owner_id
+                }
+                .to_owned(),
+                ..c.clone()
+            })
+            .collect::<Vec<UserCompatibility>>();
+
+        stats.into_iter().for_each(|s| tables.merge_stats(s));
+        targets
+            .into_iter()
+            .for_each(|t| tables.merge_guess_target(t));
+        pairs
+            .into_iter()
+            .for_each(|c| tables.merge_compatibility(c));
+
+        // like the foreign keys in Postgres, whatever still refers to the guest goes with it
+        tables.users.retain(|u| !(u.id == guest_id && u.is_guest));
+        tables.identities.retain(|i| i.user_id != guest_id);
+        tables.players.retain(|p| p.player_id != guest_id);
+        tables.contents.retain(|c| c.user_id != guest_id);
+        tables.game_players.retain(|p| p.player_id != guest_id);
+        tables.stats.retain(|s| s.user_id != guest_id);
+        tables
+            .guess_targets
+            .retain(|t| t.user_id != guest_id && t.target_id != guest_id);
+        tables
+            .compatibility
+            .retain(|c| c.guesser_id != guest_id && c.owner_id != guest_id);
+
+        Ok(())
+    }
+
+    fn delete_account(&self, user_id: &str, anonymous_id: &str, name: &str) -> Result<(), Error> {
+        let mut tables = self.tables();
+        let tables = &mut *tables;
+
+        let unstarted = tables
+            .lobbies
+            .iter()
+            .filter(|l| l.started_at.is_none())
+            .map(|l| l.id.clone())
+            .collect::<Vec<String>>();
+
+        tables
+            .contents
+            .retain(|c| !(c.user_id == user_id && unstarted.contains(&c.lobby_id)));
+        tables
+            .players
+            .retain(|p| !(p.player_id == user_id && unstarted.contains(&p.lobby_id)));
+
+        for lobby in tables.lobbies.iter_mut().filter(|l| l.host_id == user_id) {
+            if let Some(player) = tables.players.iter().find(|p| {
+                p.lobby_id == lobby.id
+                    && p.player_id != user_id
+                    && !p.player_id.starts_with("deleted:")
+            }) {
+                lobby.host_id = player.player_id.clone();
+            }
+        }
+
+        tables.identities.retain(|i| i.user_id != user_id);
+        tables.stats.retain(|s| s.user_id != user_id);
+        tables.guess_targets.retain(|t| t.user_id != user_id);
+        tables.compatibility.retain(|c| c.guesser_id != user_id);
+
+        tables.move_history(user_id, anonymous_id);
+
+        for game in tables
+            .games
+            .iter_mut()
+            .filter(|g| g.host_id == anonymous_id)
+        {
+            game.host_name = name.to_owned();
+        }
+        for player in tables
+            .game_players
+            .iter_mut()
+            .filter(|p| p.player_id == anonymous_id)
+        {
+            player.name = name.to_owned();
+        }
+        for round in tables
+            .game_rounds
+            .iter_mut()
+            .filter(|r| r.owner_id == anonymous_id)
+        {
+            round.owner_name = name.to_owned();
+        }
+
+        // what's left of the user refers to the stand-in now
+        for target in tables
+            .guess_targets
+            .iter_mut()
+            .filter(|t| t.target_id == user_id)
+        {
+            target.target_id = anonymous_id.to_owned();
+        }
+        for pair in tables
+            .compatibility
+            .iter_mut()
+            .filter(|c| c.owner_id == user_id)
+        {
+            pair.owner_id = anonymous_id.to_owned();
+        }
+
+        let referenced = tables.players.iter().any(|p| p.player_id == anonymous_id)
+            || tables.contents.iter().any(|c| c.user_id == anonymous_id)
+            || tables.lobbies.iter().any(|l| l.host_id == anonymous_id);
+
+        match tables.users.iter_mut().find(|u| u.id == user_id) {
+            Some(user) if referenced => {
+                *user = User {
+                    id: anonymous_id.to_owned(),
+                    name: name.to_owned(),
+                    email: None,
+                    avatar: None,
+                    bio: None,
+                    genres: String::new(),
+                    is_admin: false,
+                    ..user.clone()
+                };
+            }
+            _ => tables.users.retain(|u| u.id != user_id),
+        }
+
+        Ok(())
+    }
+
+    fn export_data(&self, user_id: &str) -> Result<Option<UserDataExport>, Error> {
+        let tables = self.tables();
+
+        let Some(user) = tables.users.iter().find(|u| u.id == user_id).cloned() else {
+            return Ok(None);
+        };
+
+        Ok(Some(UserDataExport {
+            exported_at: chrono::Utc::now(),
+            user,
+            identities: tables
+                .identities
+                .iter()
+                .filter(|i| i.user_id == user_id)
+                .cloned()
+                .collect(),
+            api_tokens: Vec::new(),
+            lobbies: tables
+                .players
+                .iter()
+                .filter(|p| p.player_id == user_id)
+                .cloned()
+                .collect(),
+            contents: tables
+                .contents
+                .iter()
+                .filter(|c| c.user_id == user_id)
+                .cloned()
+                .collect(),
+            games: tables
+                .game_players
+                .iter()
+                .filter(|p| p.player_id == user_id)
+                .cloned()
+                .collect(),
+            stats: tables.stats.iter().find(|s| s.user_id == user_id).cloned(),
+            guess_targets: tables
+                .guess_targets
+                .iter()
+                .filter(|t| t.user_id == user_id)
+                .cloned()
+                .collect(),
+            compatibility: tables
+                .compatibility
+                .iter()
+                .filter(|c| c.guesser_id == user_id)
+                .cloned()
+                .collect(),
+        }))
+    }
+}
+
+impl LobbyRepository for MemoryRepository {
+    fn insert(&self, lobby: &Lobby) -> Result<(), Error> {
+        self.tables().lobbies.push(lobby.clone());
+
+        Ok(())
+    }
+
+    fn find(&self, lobby_id: &str) -> Result<Option<Lobby>, Error> {
+        Ok(self
+            .tables()
+            .lobbies
+            .iter()
+            .find(|l| l.id == lobby_id)
+            .cloned())
+    }
+
+    fn update(&self, lobby: &Lobby) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        if let Some(existing) = tables.lobbies.iter_mut().find(|l| l.id == lobby.id) {
+            *existing = lobby.clone();
+        }
+
+        Ok(())
+    }
+
+    fn add_player(&self, player: &LobbyPlayers) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        if !tables
+            .players
+            .iter()
+            .any(|p| p.lobby_id == player.lobby_id && p.player_id == player.player_id)
+        {
+            tables.players.push(player.clone());
+        }
+
+        Ok(())
+    }
+
+    fn find_player(&self, lobby_id: &str, player_id: &str) -> Result<Option<LobbyPlayers>, Error> {
+        Ok(self
+            .tables()
+            .players
+            .iter()
+            .find(|p| p.lobby_id == lobby_id && p.player_id == player_id)
+            .cloned())
+    }
+
+    fn players(&self, lobby_ids: &[String]) -> Result<Vec<LobbyPlayers>, Error> {
+        let mut players = self
+            .tables()
+            .players
+            .iter()
+            .filter(|p| lobby_ids.contains(&p.lobby_id))
+            .cloned()
+            .collect::<Vec<LobbyPlayers>>();
+
+        players.sort_by_key(|p| p.created_at);
+
+        Ok(players)
+    }
+
+    fn set_ready(&self, lobby_id: &str, player_id: &str, ready: bool) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        if let Some(player) = tables
+            .players
+            .iter_mut()
+            .find(|p| p.lobby_id == lobby_id && p.player_id == player_id)
+        {
+            player.is_ready = ready;
+        }
+
+        Ok(())
+    }
+
+    fn set_guesses(&self, lobby_id: &str, player_id: &str, guesses: &str) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        if let Some(player) = tables
+            .players
+            .iter_mut()
+            .find(|p| p.lobby_id == lobby_id && p.player_id == player_id)
+        {
+            player.guesses = guesses.to_owned();
+        }
+
+        Ok(())
+    }
+
+    fn retain_players(&self, lobby_id: &str, player_ids: &[String]) -> Result<(), Error> {
+        self.tables()
+            .players
+            .retain(|p| p.lobby_id != lobby_id || player_ids.contains(&p.player_id));
+
+        Ok(())
+    }
+
+    fn save_content(&self, content: &Contents) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        match tables
+            .contents
+            .iter_mut()
+            .find(|c| c.lobby_id == content.lobby_id && c.user_id == content.user_id)
+        {
+            Some(existing) => {
+                existing.data = content.data.clone();
+                existing.type_ = content.type_.clone();
+            }
+            None => tables.contents.push(content.clone()),
+        }
+
+        Ok(())
+    }
+
+    fn contents(&self, lobby_ids: &[String], user_ids: &[String]) -> Result<Vec<Contents>, Error> {
+        Ok(self
+            .tables()
+            .contents
+            .iter()
+            .filter(|c| lobby_ids.contains(&c.lobby_id))
+            .filter(|c| user_ids.is_empty() || user_ids.contains(&c.user_id))
+            .cloned()
+            .collect())
+    }
+
+    fn insert_rematch(
+        &self,
+        lobby_id: &str,
+        rematch: &Lobby,
+        players: &[LobbyPlayers],
+        contents: &[Contents],
+    ) -> Result<Lobby, Error> {
+        let mut tables = self.tables();
+
+        let lobby = tables
+            .lobbies
+            .iter_mut()
+            .find(|l| l.id == lobby_id)
+            .ok_or(Error::Db(diesel::result::Error::NotFound))?;
+
+        if let Some(existing_id) = lobby.rematch_id.clone() {
+            return tables
+                .lobbies
+                .iter()
+                .find(|l| l.id == existing_id)
+                .cloned()
+                .ok_or(Error::Db(diesel::result::Error::NotFound));
+        }
+
+        lobby.rematch_id = Some(rematch.id.clone());
+
+        tables.lobbies.push(rematch.clone());
+        tables.players.extend(players.iter().cloned());
+        tables.contents.extend(contents.iter().cloned());
+
+        Ok(rematch.clone())
+    }
+
+    fn finish(&self, lobby: &Lobby, finished: &FinishedGame) -> Result<bool, Error> {
+        let mut tables = self.tables();
+
+        match tables
+            .lobbies
+            .iter_mut()
+            .find(|l| l.id == lobby.id && l.finished_at.is_none())
+        {
+            Some(existing) => *existing = lobby.clone(),
+            None => return Ok(false),
+        }
+
+        tables.games.push(finished.game.clone());
+        tables.game_players.extend(finished.players.iter().cloned());
+        tables.game_rounds.extend(finished.rounds.iter().cloned());
+
+        finished
+            .stats
+            .iter()
+            .for_each(|s| tables.merge_stats(s.clone()));
+        finished
+            .guess_targets
+            .iter()
+            .for_each(|t| tables.merge_guess_target(t.clone()));
+        finished
+            .compatibility
+            .iter()
+            .for_each(|c| tables.merge_compatibility(c.clone()));
+
+        Ok(true)
+    }
+}
+
+impl GameRepository for MemoryRepository {
+    fn insert(
+        &self,
+        game: &Game,
+        players: &[GamePlayer],
+        rounds: &[GameRound],
+    ) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        tables.games.push(game.clone());
+        tables.game_players.extend(players.iter().cloned());
+        tables.game_rounds.extend(rounds.iter().cloned());
+
+        Ok(())
+    }
+
+    fn find(&self, game_id: &str) -> Result<Option<Game>, Error> {
+        Ok(self
+            .tables()
+            .games
+            .iter()
+            .find(|g| g.id == game_id)
+            .cloned())
+    }
+
+    fn find_by_player(
+        &self,
+        player_id: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Game>, i64), Error> {
+        let tables = self.tables();
+
+        let mut games = tables
+            .games
+            .iter()
+            .filter(|g| {
+                tables
+                    .game_players
+                    .iter()
+                    .any(|p| p.game_id == g.id && p.player_id == player_id)
+            })
+            .cloned()
+            .collect::<Vec<Game>>();
+
+        games.sort_by_key(|g| std::cmp::Reverse(g.finished_at));
+
+        let total = games.len() as i64;
+        let page = games
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+
+        Ok((page, total))
+    }
+
+    fn players(&self, game_id: &str) -> Result<Vec<GamePlayer>, Error> {
+        let mut players = self
+            .tables()
+            .game_players
+            .iter()
+            .filter(|p| p.game_id == game_id)
+            .cloned()
+            .collect::<Vec<GamePlayer>>();
+
+        players.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(players)
+    }
+
+    fn rounds(&self, game_id: &str) -> Result<Vec<GameRound>, Error> {
+        let mut rounds = self
+            .tables()
+            .game_rounds
+            .iter()
+            .filter(|r| r.game_id == game_id)
+            .cloned()
+            .collect::<Vec<GameRound>>();
+
+        rounds.sort_by_key(|r| r.round_index);
+
+        Ok(rounds)
+    }
+}
+
+impl StatsRepository for MemoryRepository {
+    fn find(&self, user_id: &str) -> Result<Option<UserStats>, Error> {
+        Ok(self
+            .tables()
+            .stats
+            .iter()
+            .find(|s| s.user_id == user_id)
+            .cloned())
+    }
+
+    fn favourite_targets(&self, user_id: &str, limit: i64) -> Result<Vec<GuessTarget>, Error> {
+        let tables = self.tables();
+
+        let mut targets = tables
+            .guess_targets
+            .iter()
+            .filter(|t| t.user_id == user_id)
+            .filter_map(|t| {
+                let target = tables.users.iter().find(|u| u.id == t.target_id)?;
+
+                Some(GuessTarget {
+                    user_id: t.target_id.clone(),
+                    name: target.name.clone(),
+                    guesses: t.guesses,
+                })
+            })
+            .collect::<Vec<GuessTarget>>();
+
+        targets.sort_by_key(|t| std::cmp::Reverse(t.guesses));
+        targets.truncate(limit.max(0) as usize);
+
+        Ok(targets)
+    }
+
+    fn record(
+        &self,
+        stats: &[UserStats],
+        guess_targets: &[UserGuessTarget],
+        compatibility: &[UserCompatibility],
+    ) -> Result<(), Error> {
+        let mut tables = self.tables();
+
+        stats.iter().for_each(|s| tables.merge_stats(s.clone()));
+        guess_targets
+            .iter()
+            .for_each(|t| tables.merge_guess_target(t.clone()));
+        compatibility
+            .iter()
+            .for_each(|c| tables.merge_compatibility(c.clone()));
+
+        Ok(())
+    }
+
+    fn compatibility(&self, user_id: &str) -> Result<Vec<UserCompatibility>, Error> {
+        let mut pairs = self
+            .tables()
+            .compatibility
+            .iter()
+            .filter(|c| c.guesser_id == user_id || c.owner_id == user_id)
+            .cloned()
+            .collect::<Vec<UserCompatibility>>();
+
+        pairs.sort_by(|a, b| (&a.guesser_id, &a.owner_id).cmp(&(&b.guesser_id, &b.owner_id)));
+
+        Ok(pairs)
+    }
+}
+
+impl PresenceRepository for MemoryRepository {
+    fn heartbeat(&self, lobby_id: &str, player_id: &str, ttl_seconds: u64) -> Result<(), Error> {
+        self.tables().presence.insert(
+            (lobby_id.to_owned(), player_id.to_owned()),
+            Instant::now() + Duration::from_secs(ttl_seconds),
+        );
+
+        Ok(())
+    }
+
+    fn present_player_ids(&self, lobby_id: &str) -> Result<Vec<String>, Error> {
+        let now = Instant::now();
+        let mut tables = self.tables();
+
+        tables.presence.retain(|_, expires_at| *expires_at > now);
+
+        Ok(tables
+            .presence
+            .keys()
+            .filter(|(id, _)| id == lobby_id)
+            .map(|(_, player_id)| player_id.clone())
+            .collect())
+    }
+
+    fn present(&self) -> Result<Vec<(String, String)>, Error> {
+        let now = Instant::now();
+        let mut tables = self.tables();
+
+        tables.presence.retain(|_, expires_at| *expires_at > now);
+
+        Ok(tables.presence.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_graphql::{Request, Variables};
+    use serde_json::{json, Value};
+
+    use crate::auth::UserInfo;
+    use crate::config::GameConfig;
+    use crate::metrics::Metrics;
+    use crate::models::game::{FinishedGame, Game};
+    use crate::models::lobby::Lobby;
+    use crate::models::stats::UserStats;
+    use crate::models::user::User;
+    use crate::repositories::{LobbyRepository, MemoryRepository, Repositories};
+    use crate::services::Services;
+    use crate::{schema_builder, NamePolicy, ProjectSchema};
+
+    fn user(name: &str) -> User {
+        User {
+            id: format!("guest:{name}"),
+            name: name.to_owned(),
+            email: None,
+            created_at: chrono::Utc::now().naive_utc(),
+            is_guest: true,
+            is_admin: false,
+            avatar: None,
+            bio: None,
+            genres: String::new(),
+        }
+    }
+
+    async fn execute(schema: &ProjectSchema, user: &User, query: &str, variables: Value) -> Value {
+        let response = schema
+            .execute(
+                Request::new(query)
+                    .variables(Variables::from_json(variables))
+                    .data(UserInfo::guest(user.clone())),
+            )
+            .await;
+
+        assert!(response.errors.is_empty(), "{query}: {:?}", response.errors);
+
+        response.data.into_json().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn plays_a_game_without_postgres_or_redis() {
+        let repositories = Repositories::in_memory();
+        let metrics = Metrics::new();
+        let services = Services::new(
+            &repositories,
+            Arc::new(NamePolicy::default()),
+            &GameConfig::default(),
+            metrics.clone(),
+        );
+        let schema =
+            schema_builder(&repositories, &services, GameConfig::default(), metrics).finish();
+
+        let (alice, bob) = (user("alice"), user("bob"));
+        repositories.users.register(&alice).unwrap();
+        repositories.users.register(&bob).unwrap();
+
+        let created = execute(
+            &schema,
+            &alice,
+            "mutation { createLobby { id } }",
+            json!({}),
+        )
+        .await;
+        let id = created["createLobby"]["id"].clone();
+
+        execute(
+            &schema,
+            &bob,
+            "mutation($id: String!) { joinLobby(id: $id) { id } }",
+            json!({ "id": id }),
+        )
+        .await;
+
+        for player in [&alice, &bob] {
+            execute(
+                &schema,
+                player,
+                "mutation($id: String!, $url: String!) { setContent(id: $id, url: $url) { id } }",
+                json!({ "id": id, "url": format!("https://example.com/{}", player.name) }),
+            )
+            .await;
+        }
+
+        let mut lobby = execute(
+            &schema,
+            &alice,
+            "mutation($id: String!) { startGame(id: $id) { currentUserId players { name } } }",
+            json!({ "id": id }),
+        )
+        .await["startGame"]
+            .clone();
+        assert_eq!(lobby["players"].as_array().unwrap().len(), 2);
+
+        for round_index in 0..2 {
+            for player in [&alice, &bob] {
+                execute(
+                    &schema,
+                    player,
+                    "mutation($id: String!, $round: Int!, $guessed: String!) { \
+                    guess(id: $id, roundIndex: $round, guessedUserId: $guessed) { id } }",
+                    json!({ "id": id, "round": round_index, "guessed": lobby["currentUserId"] }),
+                )
+                .await;
+            }
+
+            lobby = execute(
+                &schema,
+                &alice,
+                "mutation($id: String!) { forward(id: $id) { currentUserId finishedAt } }",
+                json!({ "id": id }),
+            )
+            .await["forward"]
+                .clone();
+        }
+
+        assert!(!lobby["finishedAt"].is_null());
+
+        let data = execute(
+            &schema,
+            &bob,
+            "{ profile { stats { gamesPlayed } } myGames { total games { players { name } } } }",
+            json!({}),
+        )
+        .await;
+
+        assert_eq!(data["profile"]["stats"]["gamesPlayed"], 1);
+        assert_eq!(data["myGames"]["total"], 1);
+        assert_eq!(
+            data["myGames"]["games"][0]["players"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn finishes_a_lobby_only_once() {
+        let repository = MemoryRepository::default();
+        let now = chrono::Utc::now();
+        let lobby = Lobby {
+            host_id: "guest:alice".to_owned(),
+            started_at: Some(now.naive_utc()),
+            ..Lobby::default()
+        };
+        repository.insert(&lobby).unwrap();
+
+        let finished = FinishedGame {
+            game: Game {
+                id: lobby.id.clone(),
+                host_id: lobby.host_id.clone(),
+                host_name: "alice".to_owned(),
+                guessing_time: lobby.guessing_time,
+                started_at: now,
+                finished_at: now,
+            },
+            players: Vec::new(),
+            rounds: Vec::new(),
+            stats: vec![UserStats {
+                games_played: 1,
+                ..UserStats::empty("guest:alice")
+            }],
+            guess_targets: Vec::new(),
+            compatibility: Vec::new(),
+        };
+        let lobby = Lobby {
+            finished_at: Some(now.naive_utc()),
+            ..lobby
+        };
+
+        // two forwards past the last round racing each other
+        assert!(repository.finish(&lobby, &finished).unwrap());
+        assert!(!repository.finish(&lobby, &finished).unwrap());
+
+        let tables = repository.tables();
+        assert_eq!(tables.games.len(), 1);
+        assert_eq!(tables.stats[0].games_played, 1);
+    }
+}
@@ -0,0 +1,69 @@
+//! Where the services keep their data. Postgres and Redis back them in production, while
+//! [`MemoryRepository`] holds everything in memory, so the schema can be played through in tests
+//! without any infrastructure.
+
+use std::sync::Arc;
+
+use crate::DbPool;
+
+pub mod game;
+pub mod lobby;
+pub mod memory;
+pub mod presence;
+pub mod stats;
+pub mod user;
+
+pub use game::GameRepository;
+pub use lobby::LobbyRepository;
+pub use memory::MemoryRepository;
+pub use presence::{PresenceRepository, RedisPresence};
+pub use stats::StatsRepository;
+pub use user::UserRepository;
+
+/// Keeps users, lobbies, games and stats in Postgres.
+#[derive(Clone)]
+pub struct PgRepository {
+    db_pool: DbPool,
+}
+
+impl PgRepository {
+    pub fn new(db_pool: DbPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+/// Everything the services need to be built from.
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub lobbies: Arc<dyn LobbyRepository>,
+    pub games: Arc<dyn GameRepository>,
+    pub stats: Arc<dyn StatsRepository>,
+    pub presence: Arc<dyn PresenceRepository>,
+}
+
+impl Repositories {
+    pub fn postgres(db_pool: DbPool, redis: redis::Client) -> Self {
+        let repository = Arc::new(PgRepository::new(db_pool));
+
+        Self {
+            users: repository.clone(),
+            lobbies: repository.clone(),
+            games: repository.clone(),
+            stats: repository,
+            presence: Arc::new(RedisPresence::new(redis)),
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        let repository = Arc::new(MemoryRepository::default());
+
+        Self {
+            users: repository.clone(),
+            lobbies: repository.clone(),
+            games: repository.clone(),
+            stats: repository.clone(),
+            presence: repository,
+        }
+    }
+}
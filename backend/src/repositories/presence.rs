@@ -0,0 +1,109 @@
+use redis::Commands;
+
+use crate::services::Error;
+
+/// Who is currently looking at which lobby, entries expire unless they are renewed.
+pub trait PresenceRepository: Send + Sync {
+    fn heartbeat(&self, lobby_id: &str, player_id: &str, ttl_seconds: u64) -> Result<(), Error>;
+
+    fn present_player_ids(&self, lobby_id: &str) -> Result<Vec<String>, Error>;
+
+    /// Everyone present in any lobby, as (lobby id, player id).
+    fn present(&self) -> Result<Vec<(String, String)>, Error>;
+}
+
+fn presence_key(lobby_id: &str, player_id: &str) -> String {
+    format!("lobby:{}|player-id:{}", lobby_id, player_id)
+}
+
+/// The (lobby id, player id) of a presence key. Player ids may contain `:` themselves, like
+/// `guest:<uuid>`, so the key is split at the separators rather than at the last `:`.
+fn parse_presence_key(key: &str) -> Option<(&str, &str)> {
+    key.strip_prefix("lobby:")?.split_once("|player-id:")
+}
+
+/// Keeps presence in Redis keys that expire on their own.
+#[derive(Clone)]
+pub struct RedisPresence {
+    redis: redis::Client,
+}
+
+impl RedisPresence {
+    pub fn new(redis: redis::Client) -> Self {
+        Self { redis }
+    }
+
+    /// The presence keys matching the pattern. Walks the keyspace with `SCAN` rather than `KEYS`,
+    /// so Redis keeps serving everyone else meanwhile. `SCAN` may return a key more than once.
+    fn scan(&self, pattern: &str) -> Result<Vec<String>, Error> {
+        let mut redis = self
+            .redis
+            .get_connection()
+            .map_err(Error::RedisConnection)?;
+
+        let mut keys = redis
+            .scan_match::<_, String>(pattern)
+            .map_err(Error::RedisConnection)?
+            .collect::<Vec<String>>();
+
+        keys.sort();
+        keys.dedup();
+
+        Ok(keys)
+    }
+}
+
+impl PresenceRepository for RedisPresence {
+    fn heartbeat(&self, lobby_id: &str, player_id: &str, ttl_seconds: u64) -> Result<(), Error> {
+        let mut redis = self
+            .redis
+            .get_connection()
+            .map_err(Error::RedisConnection)?;
+
+        redis
+            .set_ex::<_, _, ()>(presence_key(lobby_id, player_id), 42, ttl_seconds)
+            .map_err(Error::RedisConnection)?;
+
+        Ok(())
+    }
+
+    fn present_player_ids(&self, lobby_id: &str) -> Result<Vec<String>, Error> {
+        let keys = self.scan(&presence_key(lobby_id, "*"))?;
+
+        Ok(keys
+            .iter()
+            .filter_map(|key| parse_presence_key(key))
+            .map(|(_, player_id)| player_id.to_owned())
+            .collect())
+    }
+
+    fn present(&self) -> Result<Vec<(String, String)>, Error> {
+        let keys = self.scan(&presence_key("*", "*"))?;
+
+        Ok(keys
+            .iter()
+            .filter_map(|key| parse_presence_key(key))
+            .map(|(lobby_id, player_id)| (lobby_id.to_owned(), player_id.to_owned()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_presence_key, presence_key};
+
+    #[test]
+    fn player_ids_with_colons_survive_the_key() {
+        for player_id in [
+            "guest:8f14e45f-ceea-467f-a0e6-0c5e8c1b7e3a",
+            "dev:alice",
+            "plain",
+        ] {
+            let key = presence_key("AbC123", player_id);
+
+            assert_eq!(parse_presence_key(&key), Some(("AbC123", player_id)));
+        }
+
+        assert_eq!(parse_presence_key("session:abc"), None);
+    }
+}
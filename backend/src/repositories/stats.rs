@@ -0,0 +1,138 @@
+use diesel::{pg::upsert::excluded, prelude::*};
+
+use crate::{
+    db_schema::{user_compatibility, user_guess_targets, user_stats, users},
+    models::stats::{GuessTarget, UserCompatibility, UserGuessTarget, UserStats},
+    services::Error,
+};
+
+use super::PgRepository;
+
+/// The lifetime stats of users, added up game by game.
+pub trait StatsRepository: Send + Sync {
+    fn find(&self, user_id: &str) -> Result<Option<UserStats>, Error>;
+
+    /// The users a user guessed most often, along with their current names.
+    fn favourite_targets(&self, user_id: &str, limit: i64) -> Result<Vec<GuessTarget>, Error>;
+
+    /// Adds the increments of a game to what is stored so far.
+    fn record(
+        &self,
+        stats: &[UserStats],
+        guess_targets: &[UserGuessTarget],
+        compatibility: &[UserCompatibility],
+    ) -> Result<(), Error>;
+
+    /// The pairs the user is part of, as guesser or owner.
+    fn compatibility(&self, user_id: &str) -> Result<Vec<UserCompatibility>, Error>;
+}
+
+impl StatsRepository for PgRepository {
+    fn find(&self, user_id: &str) -> Result<Option<UserStats>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let stats = user_stats::table
+            .filter(user_stats::user_id.eq(user_id))
+            .first::<UserStats>(&mut conn)
+            .optional()?;
+
+        Ok(stats)
+    }
+
+    fn favourite_targets(&self, user_id: &str, limit: i64) -> Result<Vec<GuessTarget>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let targets = user_guess_targets::table
+            .inner_join(users::table.on(users::id.eq(user_guess_targets::target_id)))
+            .filter(user_guess_targets::user_id.eq(user_id))
+            .order(user_guess_targets::guesses.desc())
+            .limit(limit)
+            .select((
+                user_guess_targets::target_id,
+                users::name,
+                user_guess_targets::guesses,
+            ))
+            .get_results::<GuessTarget>(&mut conn)?;
+
+        Ok(targets)
+    }
+
+    fn record(
+        &self,
+        stats: &[UserStats],
+        guess_targets: &[UserGuessTarget],
+        compatibility: &[UserCompatibility],
+    ) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| record(conn, stats, guess_targets, compatibility))
+    }
+
+    fn compatibility(&self, user_id: &str) -> Result<Vec<UserCompatibility>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let pairs = user_compatibility::table
+            .filter(
+                user_compatibility::guesser_id
+                    .eq(user_id)
+                    .or(user_compatibility::owner_id.eq(user_id)),
+            )
+            .order((user_compatibility::guesser_id, user_compatibility::owner_id))
+            .get_results::<UserCompatibility>(&mut conn)?;
+
+        Ok(pairs)
+    }
+}
+
+/// Adds the increments of a game on the given connection, so it can be part of a bigger
+/// transaction.
+pub(super) fn record(
+    conn: &mut PgConnection,
+    stats: &[UserStats],
+    guess_targets: &[UserGuessTarget],
+    compatibility: &[UserCompatibility],
+) -> Result<(), Error> {
+    diesel::insert_into(user_stats::table)
+        .values(stats)
+        .on_conflict(user_stats::user_id)
+        .do_update()
+        .set((
+            user_stats::games_played
+                .eq(user_stats::games_played + excluded(user_stats::games_played)),
+            user_stats::games_won.eq(user_stats::games_won + excluded(user_stats::games_won)),
+            user_stats::guesses_made
+                .eq(user_stats::guesses_made + excluded(user_stats::guesses_made)),
+            user_stats::guesses_correct
+                .eq(user_stats::guesses_correct + excluded(user_stats::guesses_correct)),
+            user_stats::songs_guessed
+                .eq(user_stats::songs_guessed + excluded(user_stats::songs_guessed)),
+            user_stats::songs_recognised
+                .eq(user_stats::songs_recognised + excluded(user_stats::songs_recognised)),
+            user_stats::updated_at.eq(excluded(user_stats::updated_at)),
+        ))
+        .execute(conn)?;
+
+    diesel::insert_into(user_guess_targets::table)
+        .values(guess_targets)
+        .on_conflict((user_guess_targets::user_id, user_guess_targets::target_id))
+        .do_update()
+        .set(
+            user_guess_targets::guesses
+                .eq(user_guess_targets::guesses + excluded(user_guess_targets::guesses)),
+        )
+        .execute(conn)?;
+
+    diesel::insert_into(user_compatibility::table)
+        .values(compatibility)
+        .on_conflict((user_compatibility::guesser_id, user_compatibility::owner_id))
+        .do_update()
+        .set((
+            user_compatibility::rounds
+                .eq(user_compatibility::rounds + excluded(user_compatibility::rounds)),
+            user_compatibility::correct
+                .eq(user_compatibility::correct + excluded(user_compatibility::correct)),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
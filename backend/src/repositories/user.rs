@@ -0,0 +1,311 @@
+use diesel::{prelude::*, sql_types::Text, upsert::on_constraint};
+
+use crate::{
+    db_schema::{
+        api_tokens, contents, game_players, lobbies_players, user_compatibility,
+        user_guess_targets, user_identities, user_stats, users,
+    },
+    models::{
+        api_token::ApiToken,
+        content::Contents,
+        export::UserDataExport,
+        game::GamePlayer,
+        lobby::LobbyPlayers,
+        stats::{UserCompatibility, UserGuessTarget, UserStats},
+        user::{User, UserIdentity},
+    },
+    services::Error,
+};
+
+use super::PgRepository;
+
+pub trait UserRepository: Send + Sync {
+    /// Adds the user, or updates the email of an existing one.
+    fn register(&self, user: &User) -> Result<(), Error>;
+
+    fn find(&self, user_id: &str) -> Result<Option<User>, Error>;
+
+    fn find_many(&self, user_ids: &[String]) -> Result<Vec<User>, Error>;
+
+    fn find_by_email(&self, email: &str) -> Result<Option<User>, Error>;
+
+    fn find_by_identity(&self, provider: &str, subject: &str) -> Result<Option<User>, Error>;
+
+    /// Adds the identity along with its user, unless the user already exists.
+    fn insert_identity(&self, user: &User, identity: &UserIdentity) -> Result<(), Error>;
+
+    fn save(&self, user: &User) -> Result<(), Error>;
+
+    /// Moves everything the guest did over to the user and removes the guest.
+    fn upgrade_guest(&self, guest_id: &str, user_id: &str) -> Result<(), Error>;
+
+    /// Removes what belongs to the user alone and hands everything shared with other players
+    /// to an anonymous stand-in with the given id and name.
+    fn delete_account(&self, user_id: &str, anonymous_id: &str, name: &str) -> Result<(), Error>;
+
+    fn export_data(&self, user_id: &str) -> Result<Option<UserDataExport>, Error>;
+}
+
+/// Statements moving everything a guest did over to a real account, `$1` being the guest's
+/// id and `$2` the account's id. Where the account already has a row for the same lobby or
+/// game, the guest's row is left untouched. Comma separated lists of ids are replaced element
+/// by element, an id may be part of another one.
+const UPGRADE_GUEST_STATEMENTS: [&str; 16] = [
+    "UPDATE lobbies SET host_id = $2 WHERE host_id = $1",
+    "UPDATE lobbies SET current_user_id = $2 WHERE current_user_id = $1",
+    "UPDATE lobbies SET sequence = \
+        array_to_string(array_replace(string_to_array(sequence, ','), $1, $2), ',') \
+        WHERE $1 = ANY(string_to_array(sequence, ','))",
+    "UPDATE lobbies_players SET player_id = $2 WHERE player_id = $1 \
+        AND lobby_id NOT IN (SELECT lobby_id FROM lobbies_players WHERE player_id = $2)",
+    "UPDATE lobbies_players SET guesses = \
+        array_to_string(array_replace(string_to_array(guesses, ','), $1, $2), ',') \
+        WHERE $1 = ANY(string_to_array(guesses, ','))",
+    "UPDATE contents SET user_id = $2 WHERE user_id = $1 \
+        AND lobby_id NOT IN (SELECT lobby_id FROM contents WHERE user_id = $2)",
+    "UPDATE games SET host_id = $2 WHERE host_id = $1",
+    "UPDATE game_players SET player_id = $2 WHERE player_id = $1 \
+        AND game_id NOT IN (SELECT game_id FROM game_players WHERE player_id = $2)",
+    "UPDATE game_players SET guesses = \
+        array_to_string(array_replace(string_to_array(guesses, ','), $1, $2), ',') \
+        WHERE $1 = ANY(string_to_array(guesses, ','))",
+    "UPDATE game_rounds SET owner_id = $2 WHERE owner_id = $1",
+    "INSERT INTO user_stats (user_id, games_played, games_won, guesses_made, guesses_correct, \
+        songs_guessed, songs_recognised) \
+        SELECT $2, games_played, games_won, guesses_made, guesses_correct, songs_guessed, \
+        songs_recognised FROM user_stats WHERE user_id = $1 \
+        ON CONFLICT (user_id) DO UPDATE SET \
+        games_played = user_stats.games_played + EXCLUDED.games_played, \
+        games_won = user_stats.games_won + EXCLUDED.games_won, \
+        guesses_made = user_stats.guesses_made + EXCLUDED.guesses_made, \
+        guesses_correct = user_stats.guesses_correct + EXCLUDED.guesses_correct, \
+        songs_guessed = user_stats.songs_guessed + EXCLUDED.songs_guessed, \
+        songs_recognised = user_stats.songs_recognised + EXCLUDED.songs_recognised",
+    "INSERT INTO user_guess_targets (user_id, target_id, guesses) \
+        SELECT $2, target_id, guesses FROM user_guess_targets WHERE user_id = $1 AND target_id <> $1 \
+        ON CONFLICT (user_id, target_id) DO UPDATE \
+        SET guesses = user_guess_targets.guesses + EXCLUDED.guesses",
+    "INSERT INTO user_guess_targets (user_id, target_id, guesses) \
+        SELECT user_id, $2, guesses FROM user_guess_targets WHERE target_id = $1 AND user_id <> $1 \
+        ON CONFLICT (user_id, target_id) DO UPDATE \
+        SET guesses = user_guess_targets.guesses + EXCLUDED.guesses",
+    "INSERT INTO user_compatibility (guesser_id, owner_id, rounds, correct) \
+        SELECT $2, owner_id, rounds, correct FROM user_compatibility \
+        WHERE guesser_id = $1 AND owner_id <> $1 \
+        ON CONFLICT (guesser_id, owner_id) DO UPDATE SET \
+        rounds = user_compatibility.rounds + EXCLUDED.rounds, \
+        correct = user_compatibility.correct + EXCLUDED.correct",
+    "INSERT INTO user_compatibility (guesser_id, owner_id, rounds, correct) \
+        SELECT guesser_id, $2, rounds, correct FROM user_compatibility \
+        WHERE owner_id = $1 AND guesser_id <> $1 \
+        ON CONFLICT (guesser_id, owner_id) DO UPDATE SET \
+        rounds = user_compatibility.rounds + EXCLUDED.rounds, \
+        correct = user_compatibility.correct + EXCLUDED.correct",
+    "DELETE FROM users WHERE id = $1 AND is_guest",
+];
+
+/// Statements anonymising a user, `$1` being the user's id, `$2` the id of the anonymous
+/// stand-in and `$3` its name. The user leaves lobbies that haven't started, hosted lobbies go
+/// to the player who joined first or keep the stand-in as their host if nobody else is in them.
+/// Started and finished games keep the user's rounds so they stay playable and add up for
+/// everyone else. Lists of ids are replaced element by element, an id may be part of another.
+const DELETE_ACCOUNT_STATEMENTS: [&str; 17] = [
+    "DELETE FROM contents WHERE user_id = $1 \
+        AND lobby_id IN (SELECT id FROM lobbies WHERE started_at IS NULL)",
+    "DELETE FROM lobbies_players WHERE player_id = $1 \
+        AND lobby_id IN (SELECT id FROM lobbies WHERE started_at IS NULL)",
+    "UPDATE lobbies SET host_id = (SELECT player_id FROM lobbies_players \
+        WHERE lobby_id = lobbies.id AND player_id <> $1 AND player_id NOT LIKE 'deleted:%' \
+        ORDER BY created_at LIMIT 1) \
+        WHERE host_id = $1 AND EXISTS (SELECT 1 FROM lobbies_players \
+        WHERE lobby_id = lobbies.id AND player_id <> $1 AND player_id NOT LIKE 'deleted:%')",
+    "DELETE FROM user_identities WHERE user_id = $1",
+    "DELETE FROM api_tokens WHERE user_id = $1",
+    "DELETE FROM user_stats WHERE user_id = $1",
+    "DELETE FROM user_guess_targets WHERE user_id = $1",
+    "DELETE FROM user_compatibility WHERE guesser_id = $1",
+    "UPDATE lobbies SET current_user_id = $2 WHERE current_user_id = $1",
+    "UPDATE lobbies SET sequence = \
+        array_to_string(array_replace(string_to_array(sequence, ','), $1, $2), ',') \
+        WHERE $1 = ANY(string_to_array(sequence, ','))",
+    "UPDATE lobbies_players SET guesses = \
+        array_to_string(array_replace(string_to_array(guesses, ','), $1, $2), ',') \
+        WHERE $1 = ANY(string_to_array(guesses, ','))",
+    "UPDATE games SET host_id = $2, host_name = $3 WHERE host_id = $1",
+    "UPDATE game_players SET player_id = $2, name = $3 WHERE player_id = $1",
+    "UPDATE game_players SET guesses = \
+        array_to_string(array_replace(string_to_array(guesses, ','), $1, $2), ',') \
+        WHERE $1 = ANY(string_to_array(guesses, ','))",
+    "UPDATE game_rounds SET owner_id = $2, owner_name = $3 WHERE owner_id = $1",
+    // the new id cascades to everything still referring to the user
+    "UPDATE users SET id = $2, name = $3, email = NULL, avatar = NULL, bio = NULL, genres = '', \
+        is_admin = FALSE WHERE id = $1",
+    "DELETE FROM users WHERE id = $2 \
+        AND NOT EXISTS (SELECT 1 FROM lobbies_players WHERE player_id = $2) \
+        AND NOT EXISTS (SELECT 1 FROM contents WHERE user_id = $2) \
+        AND NOT EXISTS (SELECT 1 FROM lobbies WHERE host_id = $2)",
+];
+
+impl UserRepository for PgRepository {
+    fn register(&self, user: &User) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::insert_into(users::table)
+            .values(user)
+            .on_conflict(on_constraint("users_pkey"))
+            .do_update()
+            .set(users::email.eq(user.email.clone()))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn find(&self, user_id: &str) -> Result<Option<User>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let user = users::table
+            .filter(users::id.eq(user_id))
+            .get_result::<User>(&mut conn)
+            .optional()?;
+
+        Ok(user)
+    }
+
+    fn find_many(&self, user_ids: &[String]) -> Result<Vec<User>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let users = users::table
+            .filter(users::id.eq_any(user_ids))
+            .get_results::<User>(&mut conn)?;
+
+        Ok(users)
+    }
+
+    fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let user = users::table
+            .filter(users::email.eq(email))
+            .first::<User>(&mut conn)
+            .optional()?;
+
+        Ok(user)
+    }
+
+    fn find_by_identity(&self, provider: &str, subject: &str) -> Result<Option<User>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let user = user_identities::table
+            .inner_join(users::table)
+            .filter(user_identities::provider.eq(provider))
+            .filter(user_identities::subject.eq(subject))
+            .select(User::as_select())
+            .first::<User>(&mut conn)
+            .optional()?;
+
+        Ok(user)
+    }
+
+    fn insert_identity(&self, user: &User, identity: &UserIdentity) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            diesel::insert_into(users::table)
+                .values(user)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            diesel::insert_into(user_identities::table)
+                .values(identity)
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    fn save(&self, user: &User) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::update(users::table)
+            .set(user)
+            .filter(users::id.eq(&user.id))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn upgrade_guest(&self, guest_id: &str, user_id: &str) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            for statement in UPGRADE_GUEST_STATEMENTS {
+                diesel::sql_query(statement)
+                    .bind::<Text, _>(guest_id)
+                    .bind::<Text, _>(user_id)
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_account(&self, user_id: &str, anonymous_id: &str, name: &str) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            for statement in DELETE_ACCOUNT_STATEMENTS {
+                diesel::sql_query(statement)
+                    .bind::<Text, _>(user_id)
+                    .bind::<Text, _>(anonymous_id)
+                    .bind::<Text, _>(name)
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn export_data(&self, user_id: &str) -> Result<Option<UserDataExport>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, Error, _>(|conn| {
+            let Some(user) = users::table
+                .filter(users::id.eq(user_id))
+                .get_result::<User>(conn)
+                .optional()?
+            else {
+                return Ok(None);
+            };
+
+            Ok(Some(UserDataExport {
+                exported_at: chrono::Utc::now(),
+                user,
+                identities: user_identities::table
+                    .filter(user_identities::user_id.eq(user_id))
+                    .get_results::<UserIdentity>(conn)?,
+                api_tokens: api_tokens::table
+                    .filter(api_tokens::user_id.eq(user_id))
+                    .get_results::<ApiToken>(conn)?,
+                lobbies: lobbies_players::table
+                    .filter(lobbies_players::player_id.eq(user_id))
+                    .get_results::<LobbyPlayers>(conn)?,
+                contents: contents::table
+                    .filter(contents::user_id.eq(user_id))
+                    .get_results::<Contents>(conn)?,
+                games: game_players::table
+                    .filter(game_players::player_id.eq(user_id))
+                    .get_results::<GamePlayer>(conn)?,
+                stats: user_stats::table
+                    .filter(user_stats::user_id.eq(user_id))
+                    .get_result::<UserStats>(conn)
+                    .optional()?,
+                guess_targets: user_guess_targets::table
+                    .filter(user_guess_targets::user_id.eq(user_id))
+                    .get_results::<UserGuessTarget>(conn)?,
+                compatibility: user_compatibility::table
+                    .filter(user_compatibility::guesser_id.eq(user_id))
+                    .get_results::<UserCompatibility>(conn)?,
+            }))
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db_schema::{api_tokens, users},
+    models::{
+        api_token::{ApiToken, CreatedApiToken, TokenScope},
+        user::User,
+    },
+    DbPool,
+};
+
+use super::{Error, Resource};
+
+/// Every token starts with this, so leaked ones are easy to spot.
+const TOKEN_PREFIX: &str = "gg_";
+
+#[derive(Clone)]
+pub struct ApiTokenService {
+    db_pool: DbPool,
+}
+
+fn hash(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+fn generate_secret() -> String {
+    let random = OsRng
+        .sample_iter(Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect::<String>();
+
+    format!("{TOKEN_PREFIX}{random}")
+}
+
+impl ApiTokenService {
+    pub fn new(db_pool: DbPool) -> Self {
+        Self { db_pool }
+    }
+
+    pub fn create(
+        &self,
+        user: &User,
+        name: String,
+        scopes: &[TokenScope],
+    ) -> Result<CreatedApiToken, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let name = name.trim().to_owned();
+
+        if name.is_empty() || name.chars().count() > 70 {
+            return Err(Error::InvalidInput(
+                "token name must be between 1 and 70 characters".to_string(),
+            ));
+        }
+
+        if scopes.is_empty() {
+            return Err(Error::InvalidInput(
+                "token needs at least one scope".to_string(),
+            ));
+        }
+
+        let secret = generate_secret();
+
+        let token = ApiToken {
+            id: uuid::Uuid::new_v4(),
+            user_id: user.id.clone(),
+            name,
+            token_hash: hash(&secret),
+            scopes: ApiToken::join_scopes(scopes),
+            created_at: chrono::Utc::now(),
+            last_used_at: None,
+        };
+
+        diesel::insert_into(api_tokens::table)
+            .values(&token)
+            .execute(&mut conn)
+            .map_err(Error::Db)?;
+
+        Ok(CreatedApiToken { token, secret })
+    }
+
+    pub fn find_by_user(&self, user_id: &str) -> Result<Vec<ApiToken>, Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let tokens = api_tokens::table
+            .filter(api_tokens::user_id.eq(user_id))
+            .order(api_tokens::created_at.desc())
+            .get_results::<ApiToken>(&mut conn)
+            .map_err(Error::Db)?;
+
+        Ok(tokens)
+    }
+
+    pub fn revoke(&self, user_id: &str, token_id: uuid::Uuid) -> Result<(), Error> {
+        let mut conn = self.db_pool.get()?;
+
+        let deleted = diesel::delete(
+            api_tokens::table
+                .filter(api_tokens::id.eq(token_id))
+                .filter(api_tokens::user_id.eq(user_id)),
+        )
+        .execute(&mut conn)
+        .map_err(Error::Db)?;
+
+        if deleted == 0 {
+            return Err(Error::NotFound(Resource::ApiToken));
+        }
+
+        Ok(())
+    }
+
+    /// Finds the token and its owner for a secret sent by a client and marks it as used.
+    pub fn authenticate(&self, secret: &str) -> Result<Option<(ApiToken, User)>, Error> {
+        if !secret.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+
+        let mut conn = self.db_pool.get()?;
+
+        let token = diesel::update(api_tokens::table)
+            .filter(api_tokens::token_hash.eq(hash(secret)))
+            .set(api_tokens::last_used_at.eq(chrono::Utc::now()))
+            .get_result::<ApiToken>(&mut conn)
+            .optional()
+            .map_err(Error::Db)?;
+
+        let Some(token) = token else {
+            return Ok(None);
+        };
+
+        let user = users::table
+            .filter(users::id.eq(&token.user_id))
+            .get_result::<User>(&mut conn)
+            .map_err(Error::Db)?;
+
+        Ok(Some((token, user)))
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use image::imageops::FilterType;
+use image::io::{Limits, Reader};
+use image::ImageOutputFormat;
+
+use crate::storage::Storage;
+
+use super::Error;
+
+/// Avatars are stored as square PNGs of this size, whatever was uploaded.
+pub const AVATAR_SIZE: u32 = 256;
+
+/// Uploads larger than this are refused before they are decoded.
+pub const MAX_AVATAR_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Keeps images with absurd dimensions from being decoded into memory.
+const MAX_AVATAR_DIMENSION: u32 = 8192;
+
+#[derive(Clone)]
+pub struct AvatarService {
+    storage: Arc<dyn Storage>,
+}
+
+/// Decodes an uploaded image and crops it to a square PNG of [`AVATAR_SIZE`].
+pub fn resize_avatar(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::InvalidInput("avatar has to be a PNG, JPEG, GIF or WebP image".into());
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_AVATAR_DIMENSION);
+    limits.max_image_height = Some(MAX_AVATAR_DIMENSION);
+
+    let mut reader = Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| invalid())?;
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|_| invalid())?;
+    let avatar = image.resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3);
+
+    let mut png = Vec::new();
+    avatar
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|_| invalid())?;
+
+    Ok(png)
+}
+
+impl AvatarService {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// Resizes and stores an uploaded avatar, returns the key to find it by.
+    pub fn store(&self, data: &[u8]) -> Result<String, Error> {
+        let png = resize_avatar(data)?;
+        let key = format!("{}.png", uuid::Uuid::new_v4());
+
+        self.storage.put(&key, &png)?;
+
+        Ok(key)
+    }
+
+    pub fn load(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.storage.get(key)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<(), Error> {
+        self.storage.delete(key)
+    }
+}
+
+pub fn avatar_url(key: &str) -> String {
+    format!("/avatars/{}", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageOutputFormat, RgbImage};
+
+    use super::{resize_avatar, AVATAR_SIZE};
+
+    #[test]
+    fn avatars_are_cropped_to_squares() {
+        let mut jpeg = Vec::new();
+        RgbImage::new(640, 360)
+            .write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(80))
+            .unwrap();
+
+        let avatar = image::load_from_memory(&resize_avatar(&jpeg).unwrap()).unwrap();
+
+        assert_eq!(avatar.width(), AVATAR_SIZE);
+        assert_eq!(avatar.height(), AVATAR_SIZE);
+        assert!(resize_avatar(b"not an image").is_err());
+    }
+}
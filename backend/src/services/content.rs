@@ -1,49 +1,58 @@
+use std::sync::Arc;
+
 use crate::{
-    db_schema::contents::dsl::*,
-    models::{content::Contents, lobby::Lobby, user::User},
-    DbPool,
+    models::{
+        lobby::Lobby,
+        playlist::{Playlist, PlaylistTrack},
+    },
+    repositories::LobbyRepository,
 };
 
-use diesel::prelude::*;
-
-use super::Error;
+use super::{game::GameService, Error};
 
+#[derive(Clone)]
 pub struct ContentService {
-    db_pool: DbPool,
+    lobbies: Arc<dyn LobbyRepository>,
+    game_service: GameService,
 }
 
 impl ContentService {
-    pub fn new(db_pool: DbPool) -> Self {
-        Self { db_pool }
-    }
-
-    pub fn find(&self, lobby: &Lobby, user: &User) -> Result<Option<Contents>, Error> {
-        let mut conn = self.db_pool.get()?;
-
-        let content = contents
-            .filter(lobby_id.eq(lobby.id.clone()))
-            .filter(user_id.eq(user.id.clone()))
-            .first::<Contents>(&mut conn)
-            .optional()
-            .map_err(Error::Db)?;
-
-        Ok(content)
+    pub fn new(lobbies: Arc<dyn LobbyRepository>, game_service: GameService) -> Self {
+        Self {
+            lobbies,
+            game_service,
+        }
     }
 
-    pub fn current(&self, lobby: &Lobby) -> Result<Option<Contents>, Error> {
-        let mut conn = self.db_pool.get()?;
-
-        if lobby.current_user_id.is_none() {
-            return Ok(None);
+    /// Collects the content of a finished lobby in the order it was played, as it was archived
+    /// with the game. Only the players of the lobby get to see it.
+    pub fn playlist(&self, lobby: &Lobby, user_id: &str) -> Result<Playlist, Error> {
+        if lobby.host_id != user_id && self.lobbies.find_player(&lobby.id, user_id)?.is_none() {
+            return Err(Error::NotAPlayer);
         }
 
-        let content = contents
-            .filter(lobby_id.eq(lobby.id.clone()))
-            .filter(user_id.eq(lobby.current_user_id.clone().unwrap()))
-            .first::<Contents>(&mut conn)
-            .optional()
-            .map_err(Error::Db)?;
+        if lobby.finished_at.is_none() {
+            return Err(Error::GameNotFinished);
+        }
 
-        Ok(content)
+        let game = self.game_service.find(&lobby.id)?;
+
+        let tracks = self
+            .game_service
+            .rounds(&game)?
+            .into_iter()
+            .filter_map(|round| {
+                Some(PlaylistTrack {
+                    url: round.content_data?,
+                    submitter_id: round.owner_id,
+                    submitter_name: round.owner_name,
+                })
+            })
+            .collect();
+
+        Ok(Playlist {
+            title: format!("grooveguessr {}", lobby.id),
+            tracks,
+        })
     }
 }
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{
+    models::{
+        game::{self, FinishedGame, Game, GamePlayer, GameRound},
+        lobby::Lobby,
+        scoreboard::Scoreboard,
+    },
+    repositories::{GameRepository, LobbyRepository, UserRepository},
+};
+
+use super::{Error, Resource};
+
+#[derive(Clone)]
+pub struct GameService {
+    games: Arc<dyn GameRepository>,
+    users: Arc<dyn UserRepository>,
+    lobbies: Arc<dyn LobbyRepository>,
+}
+
+impl GameService {
+    pub fn new(
+        games: Arc<dyn GameRepository>,
+        users: Arc<dyn UserRepository>,
+        lobbies: Arc<dyn LobbyRepository>,
+    ) -> Self {
+        Self {
+            games,
+            users,
+            lobbies,
+        }
+    }
+
+    /// Takes a snapshot of a finished lobby to archive, including the names and content as they
+    /// were at the time and what the game adds to the stats of its players.
+    pub fn snapshot(&self, lobby: &Lobby, scoreboard: &Scoreboard) -> Result<FinishedGame, Error> {
+        let started_at = lobby.started_at.ok_or(Error::GameNotStarted)?;
+        let finished_at = lobby.finished_at.ok_or(Error::GameNotFinished)?;
+
+        let mut user_ids = scoreboard.player_ids.clone();
+        user_ids.push(lobby.host_id.clone());
+
+        let names = self
+            .users
+            .find_many(&user_ids)?
+            .into_iter()
+            .map(|user| (user.id, user.name))
+            .collect::<HashMap<String, String>>();
+
+        let name = |user_id: &String| names.get(user_id).cloned().unwrap_or_default();
+
+        let contents = self
+            .lobbies
+            .contents(std::slice::from_ref(&lobby.id), &[])?;
+
+        let game = Game {
+            id: lobby.id.clone(),
+            host_id: lobby.host_id.clone(),
+            host_name: name(&lobby.host_id),
+            guessing_time: lobby.guessing_time,
+            started_at: started_at.and_utc(),
+            finished_at: finished_at.and_utc(),
+        };
+
+        let winner_ids = scoreboard.winner_ids();
+
+        let players = scoreboard
+            .player_ids
+            .iter()
+            .map(|player_id| GamePlayer {
+                game_id: game.id.clone(),
+                player_id: player_id.clone(),
+                name: name(player_id),
+                score: scoreboard.score(player_id) as i32,
+                is_winner: winner_ids.contains(player_id),
+                guesses: scoreboard
+                    .guesses(player_id)
+                    .join(",")
+                    .trim_end_matches(',')
+                    .to_owned(),
+            })
+            .collect::<Vec<GamePlayer>>();
+
+        let rounds = scoreboard
+            .rounds
+            .iter()
+            .enumerate()
+            .map(|(index, round)| {
+                let content = contents.iter().find(|c| c.user_id == round.owner_id);
+
+                GameRound {
+                    game_id: game.id.clone(),
+                    round_index: index as i16,
+                    owner_id: round.owner_id.clone(),
+                    owner_name: name(&round.owner_id),
+                    content_type: content.map(|c| c.type_.clone()),
+                    content_data: content.map(|c| c.data.clone()),
+                }
+            })
+            .collect::<Vec<GameRound>>();
+
+        Ok(FinishedGame {
+            game,
+            players,
+            rounds,
+            stats: scoreboard.user_stats(),
+            guess_targets: scoreboard.guess_targets(),
+            compatibility: scoreboard.compatibility(),
+        })
+    }
+
+    pub fn find(&self, game_id: &str) -> Result<Game, Error> {
+        self.games
+            .find(game_id)?
+            .ok_or(Error::NotFound(Resource::Game))
+    }
+
+    /// Finds the games a user played in, most recent first.
+    pub fn find_by_player(
+        &self,
+        player_id: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Game>, i64), Error> {
+        self.games.find_by_player(player_id, offset, limit)
+    }
+
+    pub fn players(&self, game: &Game) -> Result<Vec<GamePlayer>, Error> {
+        self.games.players(&game.id)
+    }
+
+    pub fn rounds(&self, game: &Game) -> Result<Vec<GameRound>, Error> {
+        self.games.rounds(&game.id)
+    }
+
+    pub fn is_player(&self, game: &Game, player_id: &str) -> Result<bool, Error> {
+        Ok(self.players(game)?.iter().any(|p| p.player_id == player_id))
+    }
+
+    pub fn scoreboard(&self, game: &Game) -> Result<Scoreboard, Error> {
+        Ok(game::scoreboard(&self.rounds(game)?, &self.players(game)?))
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::{Duration, Instant};
+
+use diesel::prelude::*;
+use diesel_migrations::MigrationHarness;
+use serde::Serialize;
+
+use crate::{DbPool, MIGRATIONS};
+
+/// How long a dependency may take to answer before it counts as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the server can serve requests, along with what each dependency reported.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub database: DatabaseCheck,
+    pub redis: RedisCheck,
+    pub migrations: MigrationsCheck,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseCheck {
+    pub ok: bool,
+    pub latency_ms: u64,
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedisCheck {
+    pub ok: bool,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationsCheck {
+    pub ok: bool,
+    /// versions of the migrations that haven't been run yet
+    pub pending: Vec<String>,
+}
+
+/// Checks the dependencies of the server. Failures are logged, clients only learn which check
+/// failed, since the endpoints using this don't require a login.
+#[derive(Clone)]
+pub struct HealthService {
+    db_pool: DbPool,
+    redis: redis::Client,
+}
+
+impl HealthService {
+    pub fn new(db_pool: DbPool, redis: redis::Client) -> Self {
+        Self { db_pool, redis }
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let started = Instant::now();
+        let connection = self
+            .db_pool
+            .get_timeout(CHECK_TIMEOUT)
+            .map_err(|err| err.to_string())
+            .and_then(|mut conn| {
+                diesel::sql_query("SELECT 1")
+                    .execute(&mut conn)
+                    .map(|_| conn)
+                    .map_err(|err| err.to_string())
+            });
+        let database_latency = started.elapsed();
+
+        let (database_ok, migrations) = match connection {
+            // the migrations can only be checked with a working connection
+            Ok(mut conn) => (true, Self::migrations(&mut conn)),
+            Err(err) => {
+                warn!("Readiness check of the database failed: {err}");
+
+                let migrations = MigrationsCheck {
+                    ok: false,
+                    pending: Vec::new(),
+                };
+
+                (false, migrations)
+            }
+        };
+
+        let state = self.db_pool.state();
+        let database = DatabaseCheck {
+            ok: database_ok,
+            latency_ms: database_latency.as_millis() as u64,
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            max_connections: self.db_pool.max_size(),
+        };
+
+        let started = Instant::now();
+        let redis_result = self
+            .redis
+            .get_connection_with_timeout(CHECK_TIMEOUT)
+            .and_then(|mut conn| redis::cmd("PING").query::<String>(&mut conn));
+
+        if let Err(ref err) = redis_result {
+            warn!("Readiness check of Redis failed: {err}");
+        }
+
+        let redis = RedisCheck {
+            ok: redis_result.is_ok(),
+            latency_ms: started.elapsed().as_millis() as u64,
+        };
+
+        Readiness {
+            ready: database.ok && redis.ok && migrations.ok,
+            database,
+            redis,
+            migrations,
+        }
+    }
+
+    fn migrations(conn: &mut PgConnection) -> MigrationsCheck {
+        match conn.pending_migrations(MIGRATIONS) {
+            Ok(pending) => MigrationsCheck {
+                ok: pending.is_empty(),
+                pending: pending
+                    .iter()
+                    .map(|migration| migration.name().version().to_string())
+                    .collect(),
+            },
+            Err(err) => {
+                warn!("Readiness check of migrations failed: {err}");
+
+                MigrationsCheck {
+                    ok: false,
+                    pending: Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use diesel::PgConnection;
+
+    use super::HealthService;
+
+    #[test]
+    fn unreachable_dependencies_are_not_ready() {
+        let db_pool =
+            Pool::builder()
+                .max_size(1)
+                .build_unchecked(ConnectionManager::<PgConnection>::new(
+                    "postgres://127.0.0.1:1/grooveguessr",
+                ));
+        let redis = redis::Client::open("redis://127.0.0.1:1").unwrap();
+
+        let readiness = HealthService::new(db_pool, redis).readiness();
+
+        assert!(!readiness.ready);
+        assert!(!readiness.database.ok);
+        assert!(!readiness.redis.ok);
+        assert!(!readiness.migrations.ok);
+        assert_eq!(readiness.database.max_connections, 1);
+    }
+
+    /// Needs a migrated database in `TEST_DATABASE_URL`, skipped without one.
+    #[test]
+    fn a_migrated_database_is_ready() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return;
+        };
+
+        let db_pool = Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::<PgConnection>::new(database_url))
+            .unwrap();
+        let redis = redis::Client::open("redis://127.0.0.1:1").unwrap();
+
+        let readiness = HealthService::new(db_pool, redis).readiness();
+
+        assert!(readiness.database.ok);
+        assert!(readiness.migrations.ok, "{:?}", readiness.migrations);
+        assert!(readiness.migrations.pending.is_empty());
+        assert_eq!(readiness.database.connections, 1);
+    }
+}
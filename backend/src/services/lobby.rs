@@ -1,37 +1,43 @@
+use std::sync::Arc;
+
+use crate::metrics::Metrics;
+use crate::models::lobby::Lobby;
+use crate::telemetry::record_lobby_id;
 use crate::{
-    db_schema::{
-        contents::{self},
-        lobbies::dsl::*,
-        lobbies_players,
-    },
-    models::{content::Contents, lobby::LobbyPlayers, user::User},
+    models::{content::Contents, lobby::LobbyPlayers, scoreboard::Scoreboard, user::User},
+    repositories::LobbyRepository,
 };
-use crate::{models::lobby::Lobby, DbPool};
-use diesel::{prelude::*, upsert::on_constraint};
 use rand::seq::SliceRandom;
 
-use super::{presence::PresenceService, Error};
+use super::{game::GameService, presence::PresenceService, Error, Resource};
 
+#[derive(Clone)]
 pub struct LobbyService {
-    db_pool: DbPool,
+    lobbies: Arc<dyn LobbyRepository>,
     presence_service: PresenceService,
+    game_service: GameService,
+    metrics: Metrics,
 }
 
 impl LobbyService {
-    pub fn new(db_pool: DbPool, presence_service: PresenceService) -> Self {
+    pub fn new(
+        lobbies: Arc<dyn LobbyRepository>,
+        presence_service: PresenceService,
+        game_service: GameService,
+        metrics: Metrics,
+    ) -> Self {
         Self {
-            db_pool,
+            lobbies,
             presence_service,
+            game_service,
+            metrics,
         }
     }
 
     pub fn create(&self, lobby: Lobby, user: &User) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get().map_err(Error::DbConnection)?;
+        record_lobby_id(&lobby.id);
 
-        diesel::insert_into(lobbies)
-            .values(&lobby)
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.lobbies.insert(&lobby)?;
 
         self.join(&lobby, user)?;
 
@@ -39,13 +45,9 @@ impl LobbyService {
     }
 
     pub fn find(&self, by_lobby_id: String, user: &User) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
+        record_lobby_id(&by_lobby_id);
 
-        let lobby = lobbies
-            .filter(id.eq(by_lobby_id))
-            .limit(1)
-            .get_result::<Lobby>(&mut conn)
-            .map_err(Error::Db)?;
+        let lobby = self.find_by_id(&by_lobby_id)?;
 
         // TODO: seperate it into it's own heartbeat mechanism
         self.presence_service.heartbeat(&lobby, user)?;
@@ -55,9 +57,14 @@ impl LobbyService {
         Ok(lobby)
     }
 
-    pub fn join(&self, lobby: &Lobby, user: &User) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
+    /// Looks up a lobby without marking anyone as present.
+    pub fn find_by_id(&self, by_lobby_id: &str) -> Result<Lobby, Error> {
+        self.lobbies
+            .find(by_lobby_id)?
+            .ok_or(Error::NotFound(Resource::Lobby))
+    }
 
+    pub fn join(&self, lobby: &Lobby, user: &User) -> Result<Lobby, Error> {
         let lobby_player = LobbyPlayers {
             lobby_id: lobby.id.clone(),
             player_id: user.id.clone(),
@@ -66,11 +73,7 @@ impl LobbyService {
             created_at: chrono::Utc::now(),
         };
 
-        diesel::insert_into(lobbies_players::table)
-            .values(lobby_player)
-            .on_conflict_do_nothing()
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.lobbies.add_player(&lobby_player)?;
 
         self.presence_service.heartbeat(lobby, user)?;
 
@@ -78,46 +81,34 @@ impl LobbyService {
     }
 
     pub fn configure(&self, lobby: Lobby, _user: &User) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
-
         if lobby.host_id != _user.id {
-            return Err(Error::Unauthorized);
+            return Err(Error::NotHost);
         }
 
-        diesel::update(lobbies)
-            .filter(id.eq(lobby.id.clone()))
-            .set(&lobby)
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.lobbies.update(&lobby)?;
 
         Ok(lobby)
     }
 
-    pub fn start_game(&self, lobby: Lobby, user: &User) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
-
+    pub fn start_game(&self, mut lobby: Lobby, user: &User) -> Result<Lobby, Error> {
         if lobby.host_id != user.id {
-            return Err(Error::Unauthorized);
+            return Err(Error::NotHost);
         }
 
         if lobby.started_at.is_some() {
             return Err(Error::GameAlreadyStarted);
         }
 
-        let players = lobbies_players::table
-            .filter(lobbies_players::lobby_id.eq(&lobby.id))
-            .get_results::<LobbyPlayers>(&mut conn)
-            .map_err(Error::Db)?;
+        let players = self.find_players(&lobby)?;
 
         // TODO: validate it
         /*if players.len() < 3 {
             return Err(Error::NotEnoughPlayers);
         }*/
 
-        let player_contents = contents::table
-            .filter(contents::lobby_id.eq(&lobby.id))
-            .get_results::<Contents>(&mut conn)
-            .map_err(Error::Db)?;
+        let player_contents = self
+            .lobbies
+            .contents(std::slice::from_ref(&lobby.id), &[])?;
 
         if players.len() != player_contents.len() {
             return Err(Error::NotEveryoneHasContent);
@@ -131,123 +122,156 @@ impl LobbyService {
 
         shuffled_ids.shuffle(&mut rand::thread_rng());
 
-        diesel::update(lobbies)
-            .filter(id.eq(lobby.id.clone()))
-            .set((
-                started_at.eq(chrono::Utc::now().naive_utc()),
-                sequence.eq(shuffled_ids.join(",")),
-                current_user_id.eq(shuffled_ids[0].clone()),
-            ))
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        lobby.started_at = Some(chrono::Utc::now().naive_utc());
+        lobby.sequence = Some(shuffled_ids.join(","));
+        lobby.current_user_id = Some(shuffled_ids[0].clone());
+
+        self.lobbies.update(&lobby)?;
+
+        self.metrics.games_started.inc();
 
         Ok(lobby)
     }
 
     pub fn set_ready(&self, lobby: Lobby, user: &User, ready: bool) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
-
-        diesel::update(lobbies_players::table)
-            .filter(
-                lobbies_players::lobby_id
-                    .eq(&lobby.id)
-                    .and(lobbies_players::player_id.eq(&user.id)),
-            )
-            .set(lobbies_players::is_ready.eq(&ready))
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.lobbies.set_ready(&lobby.id, &user.id, ready)?;
 
         Ok(lobby)
     }
 
     pub fn set_content(&self, lobby: Lobby, user: &User, url: String) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
-
-        diesel::insert_into(contents::table)
-            .values((
-                contents::user_id.eq(user.id.clone()),
-                contents::data.eq(url.clone()),
-                contents::type_.eq("url"),
-                contents::lobby_id.eq(lobby.id.clone()),
-                contents::created_at.eq(chrono::Utc::now().naive_utc()),
-            ))
-            .on_conflict(on_constraint("contents_pkey"))
-            .do_update()
-            .set((contents::data.eq(url), contents::type_.eq("url")))
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.lobbies.save_content(&Contents {
+            lobby_id: lobby.id.clone(),
+            user_id: user.id.clone(),
+            type_: "url".to_owned(),
+            data: url,
+            created_at: chrono::Utc::now().naive_utc(),
+        })?;
 
         Ok(lobby)
     }
 
     pub fn forward(&self, lobby: Lobby, user: &User) -> Result<Lobby, Error> {
-        let mut conn = self.db_pool.get()?;
-
         if lobby.host_id != user.id {
-            return Err(Error::Unauthorized);
+            return Err(Error::NotHost);
+        }
+
+        // forwarding past the last round ends the game
+        let lobby = match lobby.clone().forward() {
+            Err(Error::GameAlreadyFinished) => lobby.finish()?,
+            result => result?,
+        };
+
+        if lobby.finished_at.is_none() {
+            self.lobbies.update(&lobby)?;
+
+            return Ok(lobby);
         }
 
-        let lobby = lobby.forward()?;
+        let players = self.find_players(&lobby)?;
+        let scoreboard = Scoreboard::new(&lobby, &players)?;
+        let finished = self.game_service.snapshot(&lobby, &scoreboard)?;
+
+        // only the request that actually finished the game archives and counts it
+        if !self.lobbies.finish(&lobby, &finished)? {
+            return self.find_by_id(&lobby.id);
+        }
 
-        diesel::update(lobbies)
-            .filter(id.eq(lobby.id.clone()))
-            .set(lobby.clone())
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.metrics.games_finished.inc();
 
         Ok(lobby)
     }
 
-    pub fn clear_inactive_players(&self, lobby: &Lobby) -> Result<(), Error> {
-        let mut conn = self.db_pool.get()?;
+    pub fn rematch(&self, lobby: Lobby, user: &User, keep_content: bool) -> Result<Lobby, Error> {
+        let present_user_ids = self.presence_service.present_user_ids(&lobby)?;
 
-        let present_user_ids = self.presence_service.present_user_ids(lobby)?;
+        // the lobby might have been finished or rematched since it was loaded
+        let lobby = self.find_by_id(&lobby.id)?;
 
-        diesel::delete(
-            lobbies_players::table.filter(
-                lobbies_players::lobby_id
-                    .eq(&lobby.id)
-                    .and(lobbies_players::player_id.ne_all(&present_user_ids)),
-            ),
-        )
-        .execute(&mut conn)
-        .map_err(Error::Db)?;
+        if lobby.finished_at.is_none() {
+            return Err(Error::GameNotFinished);
+        }
 
-        if !present_user_ids.contains(&lobby.host_id) {
-            // TODO: don't delete lobby if host is gone, set new host (if there are players left)
-            /*diesel::delete(
-                lobbies::table().filter(id.eq(&lobby.id).and(host_id.eq(&lobby.host_id))),
-            )
-            .execute(&mut conn)
-            .map_err(Error::Db)?;*/
+        let players = self.find_players(&lobby)?;
+
+        if lobby.host_id != user.id && !players.iter().any(|p| p.player_id == user.id) {
+            return Err(Error::NotAPlayer);
         }
 
-        Ok(())
-    }
+        if let Some(ref existing_id) = lobby.rematch_id {
+            return self.find_by_id(existing_id);
+        }
 
-    pub fn guesses(&self, lobby: &Lobby, user: &User) -> Result<Vec<String>, Error> {
-        let mut conn = self.db_pool.get()?;
+        let rematch = lobby.rematch();
+
+        let mut player_ids = vec![lobby.host_id.clone()];
+
+        player_ids.extend(
+            players
+                .into_iter()
+                .map(|p| p.player_id)
+                .filter(|p| *p != lobby.host_id && present_user_ids.contains(p)),
+        );
+
+        let new_players = player_ids
+            .iter()
+            .map(|player_id| LobbyPlayers {
+                lobby_id: rematch.id.clone(),
+                player_id: player_id.clone(),
+                is_ready: false,
+                guesses: "".to_owned(),
+                created_at: chrono::Utc::now(),
+            })
+            .collect::<Vec<LobbyPlayers>>();
+
+        let carried_contents = if keep_content {
+            self.lobbies
+                .contents(std::slice::from_ref(&lobby.id), &player_ids)?
+                .into_iter()
+                .map(|c| Contents {
+                    lobby_id: rematch.id.clone(),
+                    created_at: chrono::Utc::now().naive_utc(),
+                    ..c
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
 
-        let player = lobbies_players::table
-            .filter(lobbies_players::lobby_id.eq(&lobby.id))
-            .filter(lobbies_players::player_id.eq(&user.id))
-            .first::<LobbyPlayers>(&mut conn)
-            .optional()
-            .map_err(Error::Db)?;
+        let inserted =
+            self.lobbies
+                .insert_rematch(&lobby.id, &rematch, &new_players, &carried_contents)?;
 
-        let guesses = match player {
-            Some(player) => {
-                if player.guesses.is_empty() {
-                    return Ok(Vec::new());
-                }
+        // someone else was quicker, their rematch already has its players
+        if inserted.id != rematch.id {
+            return Ok(inserted);
+        }
 
-                player.guesses.split(',').map(|s| s.to_owned()).collect()
-            }
+        // keep the carried over players present until their clients have been redirected
+        for player_id in player_ids {
+            self.presence_service
+                .heartbeat_player(&rematch, &player_id)?;
+        }
 
-            None => Vec::new(),
-        };
+        Ok(rematch)
+    }
 
-        Ok(guesses)
+    /// Removes the players who left a lobby before its game started. Once it runs, everyone stays,
+    /// their content is a round of the game and their guesses count.
+    pub fn clear_inactive_players(&self, lobby: &Lobby) -> Result<(), Error> {
+        if lobby.started_at.is_some() {
+            return Ok(());
+        }
+
+        let present_user_ids = self.presence_service.present_user_ids(lobby)?;
+
+        self.lobbies.retain_players(&lobby.id, &present_user_ids)?;
+
+        if !present_user_ids.contains(&lobby.host_id) {
+            // TODO: don't delete lobby if host is gone, set new host (if there are players left)
+        }
+
+        Ok(())
     }
 
     pub fn guess(
@@ -257,14 +281,7 @@ impl LobbyService {
         user: &User,
         guessed_user: &User,
     ) -> Result<(), Error> {
-        let mut conn = self.db_pool.get()?;
-
-        let player = lobbies_players::table
-            .filter(lobbies_players::lobby_id.eq(&lobby.id))
-            .filter(lobbies_players::player_id.eq(&user.id))
-            .first::<LobbyPlayers>(&mut conn)
-            .optional()
-            .unwrap();
+        let player = self.lobbies.find_player(&lobby.id, &user.id)?;
 
         let mut guesses = match player {
             Some(player) => {
@@ -278,34 +295,22 @@ impl LobbyService {
             None => Vec::new(),
         };
 
+        // skipped rounds stay empty, so every guess keeps the position of its round
         if guesses.len() <= round_index {
-            guesses.push(guessed_user.id.clone());
-        } else {
-            guesses[round_index] = guessed_user.id.clone();
+            guesses.resize(round_index + 1, String::new());
         }
 
-        diesel::update(lobbies_players::table)
-            .filter(
-                lobbies_players::lobby_id
-                    .eq(&lobby.id)
-                    .and(lobbies_players::player_id.eq(&user.id)),
-            )
-            .set(lobbies_players::guesses.eq(guesses.join(",")))
-            .execute(&mut conn)
-            .unwrap();
+        guesses[round_index] = guessed_user.id.clone();
+
+        self.lobbies
+            .set_guesses(&lobby.id, &user.id, &guesses.join(","))?;
+
+        self.metrics.guesses_submitted.inc();
 
         Ok(())
     }
 
     pub fn find_players(&self, lobby: &Lobby) -> Result<Vec<LobbyPlayers>, Error> {
-        let mut conn = self.db_pool.get()?;
-
-        let players = lobbies_players::table
-            .filter(lobbies_players::lobby_id.eq(&lobby.id))
-            .order(lobbies_players::created_at.asc())
-            .get_results::<LobbyPlayers>(&mut conn)
-            .map_err(Error::Db)?;
-
-        Ok(players)
+        self.lobbies.players(std::slice::from_ref(&lobby.id))
     }
 }
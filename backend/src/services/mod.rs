@@ -1,21 +1,111 @@
 use std::fmt::{Display, Formatter};
 
+use std::sync::Arc;
+
+use async_graphql::ErrorExtensions;
+
+use crate::config::GameConfig;
+use crate::metrics::Metrics;
+use crate::models::{api_token::TokenScope, name::NamePolicy};
+use crate::repositories::Repositories;
+
+pub mod api_token;
+pub mod avatar;
 pub mod content;
+pub mod game;
+pub mod health;
 pub mod lobby;
 pub mod presence;
+pub mod session;
+pub mod stats;
 pub mod user;
 
+/// The game services on top of one set of repositories. Built once and shared by the schema and
+/// the HTTP handlers, clones use the same repositories and metrics.
+#[derive(Clone)]
+pub struct Services {
+    pub users: user::UserService,
+    pub lobbies: lobby::LobbyService,
+    pub games: game::GameService,
+    pub stats: stats::StatsService,
+    pub contents: content::ContentService,
+    pub presence: presence::PresenceService,
+}
+
+impl Services {
+    pub fn new(
+        repositories: &Repositories,
+        name_policy: Arc<NamePolicy>,
+        game_config: &GameConfig,
+        metrics: Metrics,
+    ) -> Self {
+        let presence = presence::PresenceService::new(
+            repositories.presence.clone(),
+            game_config.presence_ttl_seconds,
+            metrics.clone(),
+        );
+        let games = game::GameService::new(
+            repositories.games.clone(),
+            repositories.users.clone(),
+            repositories.lobbies.clone(),
+        );
+
+        Self {
+            users: user::UserService::new(repositories.users.clone(), name_policy),
+            lobbies: lobby::LobbyService::new(
+                repositories.lobbies.clone(),
+                presence.clone(),
+                games.clone(),
+                metrics,
+            ),
+            stats: stats::StatsService::new(repositories.stats.clone(), repositories.users.clone()),
+            contents: content::ContentService::new(repositories.lobbies.clone(), games.clone()),
+            games,
+            presence,
+        }
+    }
+}
+
+/// What a lookup was for, so clients can tell which id was wrong.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Resource {
+    Lobby,
+    User,
+    Game,
+    ApiToken,
+}
+
+impl Resource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Lobby => "Lobby",
+            Resource::User => "User",
+            Resource::Game => "Game",
+            Resource::ApiToken => "API token",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Db(diesel::result::Error),
     DbConnection(r2d2::Error),
     RedisConnection(redis::RedisError),
+    Storage(std::io::Error),
+    Blocking(actix_web::error::BlockingError),
+    NotFound(Resource),
     GameAlreadyStarted,
     Unauthorized,
+    NotHost,
+    NotAPlayer,
+    SessionRequired,
     NotEveryoneHasContent,
     NotEnoughPlayers,
     GameNotStarted,
     GameAlreadyFinished,
+    GameNotFinished,
+    InvalidInput(String),
+    MissingScope(TokenScope),
 }
 
 impl Display for Error {
@@ -24,12 +114,23 @@ impl Display for Error {
             Error::Db(e) => write!(f, "Database Error: {}", e),
             Error::DbConnection(e) => write!(f, "Database Connection Error: {}", e),
             Error::RedisConnection(e) => write!(f, "Redis Connection Error: {}", e),
+            Error::Storage(e) => write!(f, "Storage Error: {}", e),
+            Error::Blocking(e) => write!(f, "Blocking Task Error: {}", e),
+            Error::NotFound(resource) => write!(f, "{} not found", resource.as_str()),
             Error::GameAlreadyStarted => write!(f, "Game already started"),
             Error::Unauthorized => write!(f, "Unauthorized"),
+            Error::NotHost => write!(f, "Only the host can do that"),
+            Error::NotAPlayer => write!(f, "Only players of the lobby can do that"),
+            Error::SessionRequired => write!(f, "Personal access tokens can't do that"),
             Error::NotEveryoneHasContent => write!(f, "Not everyone has content"),
             Error::NotEnoughPlayers => write!(f, "Not enough players (min of 3)"),
             Error::GameNotStarted => write!(f, "Game not started"),
             Error::GameAlreadyFinished => write!(f, "Game already finished"),
+            Error::GameNotFinished => write!(f, "Game not finished yet"),
+            Error::InvalidInput(reason) => write!(f, "Invalid input: {}", reason),
+            Error::MissingScope(scope) => {
+                write!(f, "Token is missing the {} scope", scope.as_str())
+            }
         }
     }
 }
@@ -45,3 +146,128 @@ impl From<r2d2::Error> for Error {
         Error::DbConnection(e)
     }
 }
+
+impl Error {
+    /// The stable, machine-readable `code` extension clients get to see.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Db(diesel::result::Error::NotFound) => "NOT_FOUND",
+            Error::Db(_)
+            | Error::DbConnection(_)
+            | Error::RedisConnection(_)
+            | Error::Storage(_)
+            | Error::Blocking(_) => "INTERNAL_SERVER_ERROR",
+            Error::NotFound(Resource::Lobby) => "LOBBY_NOT_FOUND",
+            Error::NotFound(Resource::User) => "USER_NOT_FOUND",
+            Error::NotFound(Resource::Game) => "GAME_NOT_FOUND",
+            Error::NotFound(Resource::ApiToken) => "API_TOKEN_NOT_FOUND",
+            Error::GameAlreadyStarted => "GAME_ALREADY_STARTED",
+            Error::Unauthorized => "FORBIDDEN",
+            Error::NotHost => "NOT_HOST",
+            Error::NotAPlayer => "NOT_A_PLAYER",
+            Error::SessionRequired => "SESSION_REQUIRED",
+            Error::NotEveryoneHasContent => "NOT_EVERYONE_HAS_CONTENT",
+            Error::NotEnoughPlayers => "NOT_ENOUGH_PLAYERS",
+            Error::GameNotStarted => "GAME_NOT_STARTED",
+            Error::GameAlreadyFinished => "GAME_ALREADY_FINISHED",
+            Error::GameNotFinished => "GAME_NOT_FINISHED",
+            Error::InvalidInput(_) => "INVALID_INPUT",
+            Error::MissingScope(_) => "MISSING_SCOPE",
+        }
+    }
+}
+
+/// Runs the synchronous database, Redis or file system work of the services on the blocking
+/// thread pool, so a slow query doesn't hold up every other request served by the same worker.
+/// The work stays in the caller's span, so its logs can be told apart by request.
+pub async fn blocking<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let span = tracing::Span::current();
+
+    actix_web::web::block(move || span.in_scope(f))
+        .await
+        .map_err(Error::Blocking)?
+}
+
+/// Turns service errors into GraphQL errors with a `code` extension. Failures of the
+/// infrastructure are logged and reach clients without any details.
+impl ErrorExtensions for Error {
+    fn extend(&self) -> async_graphql::Error {
+        let code = self.code();
+
+        let message = match self {
+            // nothing says what was looked up, and the database is none of the client's business
+            Error::Db(diesel::result::Error::NotFound) => "Not found".to_string(),
+            _ if code == "INTERNAL_SERVER_ERROR" => {
+                error!("{}", self);
+
+                "Internal server error".to_string()
+            }
+            _ => self.to_string(),
+        };
+
+        async_graphql::Error::new(message).extend_with(|_, e| {
+            e.set("code", code);
+
+            if let Error::MissingScope(scope) = self {
+                e.set("scope", scope.as_str());
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::ErrorExtensions;
+
+    use super::{Error, Resource};
+
+    fn code(err: &async_graphql::Error) -> String {
+        err.extensions
+            .as_ref()
+            .unwrap()
+            .get("code")
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn errors_have_stable_codes() {
+        let not_found = Error::NotFound(Resource::Lobby).extend();
+        assert_eq!(code(&not_found), "\"LOBBY_NOT_FOUND\"");
+        assert_eq!(not_found.message, "Lobby not found");
+
+        assert_eq!(code(&Error::NotHost.extend()), "\"NOT_HOST\"");
+        assert_eq!(
+            code(&Error::GameAlreadyStarted.extend()),
+            "\"GAME_ALREADY_STARTED\""
+        );
+    }
+
+    #[test]
+    fn database_errors_are_hidden() {
+        let err = Error::Db(diesel::result::Error::BrokenTransactionManager).extend();
+
+        assert_eq!(code(&err), "\"INTERNAL_SERVER_ERROR\"");
+        assert_eq!(err.message, "Internal server error");
+    }
+
+    #[test]
+    fn missing_rows_are_not_found() {
+        let err = Error::Db(diesel::result::Error::NotFound).extend();
+
+        assert_eq!(code(&err), "\"NOT_FOUND\"");
+        assert_eq!(err.message, "Not found");
+    }
+
+    #[test]
+    fn storage_errors_are_hidden() {
+        let err = Error::Storage(std::io::Error::other("/var/lib/avatars is full")).extend();
+
+        assert_eq!(code(&err), "\"INTERNAL_SERVER_ERROR\"");
+        assert_eq!(err.message, "Internal server error");
+    }
+}
@@ -1,50 +1,100 @@
-use redis::Commands;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
 
+use crate::metrics::Metrics;
 use crate::models::{lobby::Lobby, user::User};
+use crate::repositories::PresenceRepository;
 
 use super::Error;
 
 #[derive(Clone)]
 pub struct PresenceService {
-    pub(crate) redis: redis::Client,
+    presence: Arc<dyn PresenceRepository>,
+    ttl_seconds: u64,
+    metrics: Metrics,
 }
 
 impl PresenceService {
-    pub fn new(redis: redis::Client) -> Self {
-        Self { redis }
+    /// Players count as present for `ttl_seconds` after their last request to the lobby.
+    pub fn new(presence: Arc<dyn PresenceRepository>, ttl_seconds: u64, metrics: Metrics) -> Self {
+        Self {
+            presence,
+            ttl_seconds,
+            metrics,
+        }
     }
 
     pub fn heartbeat(&self, lobby: &Lobby, user: &User) -> Result<(), Error> {
-        let mut redis = self
-            .redis
-            .get_connection()
-            .map_err(Error::RedisConnection)?;
+        self.heartbeat_player(lobby, &user.id)
+    }
+
+    pub fn heartbeat_player(&self, lobby: &Lobby, player_id: &str) -> Result<(), Error> {
+        self.timed("heartbeat", || {
+            self.presence
+                .heartbeat(&lobby.id, player_id, self.ttl_seconds)
+        })
+    }
+
+    pub fn present_user_ids(&self, lobby: &Lobby) -> Result<Vec<String>, Error> {
+        self.timed("present_player_ids", || {
+            self.presence.present_player_ids(&lobby.id)
+        })
+    }
+
+    /// Updates the gauges of active lobbies and players online.
+    pub fn record_online(&self) -> Result<(), Error> {
+        let present = self.timed("present", || self.presence.present())?;
 
-        redis
-            .set_ex(format!("lobby:{}|player-id:{}", lobby.id, user.id), 42, 5)
-            .map_err(Error::RedisConnection)?;
+        let lobbies = present.iter().map(|(lobby_id, _)| lobby_id);
+        let players = present.iter().map(|(_, player_id)| player_id);
+
+        self.metrics
+            .active_lobbies
+            .set(lobbies.collect::<HashSet<_>>().len() as i64);
+        self.metrics
+            .players_online
+            .set(players.collect::<HashSet<_>>().len() as i64);
 
         Ok(())
     }
 
-    pub fn present_user_ids(&self, lobby: &Lobby) -> Result<Vec<String>, Error> {
-        let mut redis = self
-            .redis
-            .get_connection()
-            .map_err(Error::RedisConnection)?;
+    fn timed<T>(&self, operation: &str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+
+        self.metrics
+            .redis_duration
+            .with_label_values(&[operation])
+            .observe(started.elapsed().as_secs_f64());
+
+        result
+    }
+}
 
-        let keys: Vec<String> = redis
-            .keys(format!("lobby:{}|player-id:*", lobby.id))
-            .map_err(Error::RedisConnection)?;
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-        let mut users = Vec::new();
+    use super::PresenceService;
+    use crate::metrics::Metrics;
+    use crate::repositories::{MemoryRepository, PresenceRepository};
 
-        for key in keys {
-            let user_id = key.split(':').last().unwrap().to_owned();
+    #[test]
+    fn counts_lobbies_and_players_online() {
+        let presence = Arc::new(MemoryRepository::default());
+        let metrics = Metrics::new();
+        let service = PresenceService::new(presence.clone(), 5, metrics.clone());
 
-            users.push(user_id);
-        }
+        presence.heartbeat("lobby-1", "alice", 5).unwrap();
+        presence.heartbeat("lobby-1", "bob", 5).unwrap();
+        // bob has a second lobby open
+        presence.heartbeat("lobby-2", "bob", 5).unwrap();
+        presence.heartbeat("lobby-3", "carol", 0).unwrap();
+
+        service.record_online().unwrap();
 
-        Ok(users)
+        assert_eq!(metrics.active_lobbies.get(), 2);
+        assert_eq!(metrics.players_online.get(), 2);
     }
 }
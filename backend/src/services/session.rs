@@ -0,0 +1,143 @@
+use redis::{Commands, ExistenceCheck, SetExpiry, SetOptions};
+
+use super::Error;
+
+/// Server-side sessions in Redis, indexed by user so that they can be revoked.
+#[derive(Clone)]
+pub struct SessionService {
+    redis: redis::Client,
+}
+
+impl SessionService {
+    pub fn new(redis: redis::Client) -> Self {
+        Self { redis }
+    }
+
+    fn session_key(session_key: &str) -> String {
+        format!("session:{}", session_key)
+    }
+
+    fn user_key(user_id: &str) -> String {
+        format!("user-sessions:{}", user_id)
+    }
+
+    fn connection(&self) -> Result<redis::Connection, Error> {
+        self.redis.get_connection().map_err(Error::RedisConnection)
+    }
+
+    pub fn load(&self, session_key: &str) -> Result<Option<String>, Error> {
+        let mut redis = self.connection()?;
+
+        redis
+            .get(Self::session_key(session_key))
+            .map_err(Error::RedisConnection)
+    }
+
+    pub fn save(
+        &self,
+        session_key: &str,
+        state: &str,
+        user_id: Option<&str>,
+        ttl: u64,
+    ) -> Result<(), Error> {
+        let mut redis = self.connection()?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .set_ex(Self::session_key(session_key), state, ttl)
+            .ignore();
+
+        if let Some(user_id) = user_id {
+            pipe.sadd(Self::user_key(user_id), session_key)
+                .ignore()
+                .expire(Self::user_key(user_id), ttl as i64)
+                .ignore();
+        }
+
+        pipe.query::<()>(&mut redis).map_err(Error::RedisConnection)
+    }
+
+    /// Overwrites an existing session, returns `false` if it expired or was revoked in the meantime.
+    pub fn update(
+        &self,
+        session_key: &str,
+        state: &str,
+        user_id: Option<&str>,
+        ttl: u64,
+    ) -> Result<bool, Error> {
+        let mut redis = self.connection()?;
+
+        let updated: Option<String> = redis
+            .set_options(
+                Self::session_key(session_key),
+                state,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::XX)
+                    .with_expiration(SetExpiry::EX(ttl as usize)),
+            )
+            .map_err(Error::RedisConnection)?;
+
+        if updated.is_none() {
+            return Ok(false);
+        }
+
+        if let Some(user_id) = user_id {
+            redis::pipe()
+                .sadd(Self::user_key(user_id), session_key)
+                .ignore()
+                .expire(Self::user_key(user_id), ttl as i64)
+                .ignore()
+                .query::<()>(&mut redis)
+                .map_err(Error::RedisConnection)?;
+        }
+
+        Ok(true)
+    }
+
+    pub fn update_ttl(&self, session_key: &str, ttl: u64) -> Result<(), Error> {
+        let mut redis = self.connection()?;
+
+        redis
+            .expire::<_, ()>(Self::session_key(session_key), ttl as i64)
+            .map_err(Error::RedisConnection)
+    }
+
+    pub fn delete(&self, session_key: &str, user_id: Option<&str>) -> Result<(), Error> {
+        let mut redis = self.connection()?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(Self::session_key(session_key)).ignore();
+
+        if let Some(user_id) = user_id {
+            pipe.srem(Self::user_key(user_id), session_key).ignore();
+        }
+
+        pipe.query::<()>(&mut redis).map_err(Error::RedisConnection)
+    }
+
+    /// Ends every session of a user, returns how many were still active.
+    pub fn revoke_all(&self, user_id: &str) -> Result<usize, Error> {
+        let mut redis = self.connection()?;
+
+        let session_keys: Vec<String> = redis
+            .smembers(Self::user_key(user_id))
+            .map_err(Error::RedisConnection)?;
+
+        let keys = session_keys
+            .iter()
+            .map(|session_key| Self::session_key(session_key))
+            .collect::<Vec<String>>();
+
+        let revoked: usize = if keys.is_empty() {
+            0
+        } else {
+            redis.del(&keys).map_err(Error::RedisConnection)?
+        };
+
+        redis
+            .del::<_, ()>(Self::user_key(user_id))
+            .map_err(Error::RedisConnection)?;
+
+        Ok(revoked)
+    }
+}
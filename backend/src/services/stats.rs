@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{
+    models::{
+        scoreboard::Scoreboard,
+        stats::{Compatibility, GuessTarget, UserCompatibility, UserStats},
+    },
+    repositories::{StatsRepository, UserRepository},
+};
+
+use super::Error;
+
+#[derive(Clone)]
+pub struct StatsService {
+    stats: Arc<dyn StatsRepository>,
+    users: Arc<dyn UserRepository>,
+}
+
+impl StatsService {
+    pub fn new(stats: Arc<dyn StatsRepository>, users: Arc<dyn UserRepository>) -> Self {
+        Self { stats, users }
+    }
+
+    pub fn find(&self, user_id: &str) -> Result<UserStats, Error> {
+        let stats = self.stats.find(user_id)?;
+
+        Ok(stats.unwrap_or_else(|| UserStats::empty(user_id)))
+    }
+
+    pub fn favourite_targets(&self, user_id: &str, limit: i64) -> Result<Vec<GuessTarget>, Error> {
+        self.stats.favourite_targets(user_id, limit)
+    }
+
+    /// The compatibility of a user with everyone they played with, in both directions.
+    pub fn compatibility(&self, user_id: &str) -> Result<Vec<Compatibility>, Error> {
+        let pairs = self.stats.compatibility(user_id)?;
+
+        self.with_names(pairs)
+    }
+
+    /// The compatibility between the players of a single game.
+    pub fn game_compatibility(&self, scoreboard: &Scoreboard) -> Result<Vec<Compatibility>, Error> {
+        self.with_names(scoreboard.compatibility())
+    }
+
+    fn with_names(&self, pairs: Vec<UserCompatibility>) -> Result<Vec<Compatibility>, Error> {
+        let user_ids = pairs
+            .iter()
+            .flat_map(|c| [c.guesser_id.clone(), c.owner_id.clone()])
+            .collect::<Vec<String>>();
+
+        let names = self
+            .users
+            .find_many(&user_ids)?
+            .into_iter()
+            .map(|user| (user.id, user.name))
+            .collect::<HashMap<String, String>>();
+
+        Ok(pairs
+            .into_iter()
+            .map(|c| Compatibility::new(c, &names))
+            .collect())
+    }
+}
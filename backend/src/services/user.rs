@@ -1,56 +1,123 @@
-use crate::db_schema::users;
-use diesel::{prelude::*, upsert::on_constraint};
 use rand::seq::SliceRandom;
+use std::sync::Arc;
 
-use crate::{models::user::User, DbPool};
+use crate::{
+    models::{
+        export::UserDataExport,
+        name::NamePolicy,
+        user::{User, UserIdentity},
+    },
+    repositories::UserRepository,
+};
 
-use super::Error;
+use super::{Error, Resource};
 
+/// What deleted users are called where other players still see them.
+pub const DELETED_USER_NAME: &str = "deleted player";
+
+#[derive(Clone)]
 pub struct UserService {
-    db_pool: DbPool,
+    users: Arc<dyn UserRepository>,
+    name_policy: Arc<NamePolicy>,
 }
 
 impl UserService {
-    pub fn new(db_pool: DbPool) -> Self {
-        Self { db_pool }
+    pub fn new(users: Arc<dyn UserRepository>, name_policy: Arc<NamePolicy>) -> Self {
+        Self { users, name_policy }
+    }
+
+    pub fn name_policy(&self) -> &NamePolicy {
+        &self.name_policy
     }
 
     pub fn register(&self, user: User) -> Result<User, Error> {
-        let mut conn = self.db_pool.get().map_err(Error::DbConnection)?;
+        self.users.register(&user)?;
 
-        diesel::insert_into(users::table)
-            .values(&user)
-            .on_conflict(on_constraint("users_pkey"))
-            .do_update()
-            .set(users::email.eq(user.email.clone()))
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        Ok(user)
+    }
+
+    /// Finds the user behind a provider's subject or registers a new one.
+    ///
+    /// Identities are never linked by email, as providers vouch for their emails to varying
+    /// degrees. The same person logging in through two providers ends up with two accounts.
+    pub fn register_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+        user: User,
+    ) -> Result<User, Error> {
+        if let Some(user) = self.users.find_by_identity(provider, subject)? {
+            return Ok(user);
+        }
+
+        let email_taken = match user.email {
+            Some(ref email) => self.users.find_by_email(email)?.is_some(),
+            None => false,
+        };
+
+        // don't hand out someone else's email, the new account just goes without
+        let user = if email_taken {
+            User {
+                email: None,
+                ..user
+            }
+        } else {
+            user
+        };
+
+        self.users.insert_identity(
+            &user,
+            &UserIdentity {
+                provider: provider.to_owned(),
+                subject: subject.to_owned(),
+                user_id: user.id.clone(),
+                created_at: chrono::Utc::now(),
+            },
+        )?;
 
         Ok(user)
     }
 
     pub fn save(&self, user: User) -> Result<User, Error> {
-        let mut conn = self.db_pool.get().map_err(Error::DbConnection)?;
-
-        diesel::update(users::table)
-            .set(&user)
-            .filter(users::id.eq(user.id.clone()))
-            .execute(&mut conn)
-            .map_err(Error::Db)?;
+        self.users.save(&user)?;
 
         Ok(user)
     }
 
+    /// Moves the history of a guest over to the account they logged in with and removes the guest.
+    pub fn upgrade_guest(&self, guest: &User, user: &User) -> Result<(), Error> {
+        if !guest.is_guest || guest.id == user.id {
+            return Err(Error::Unauthorized);
+        }
+
+        self.users.upgrade_guest(&guest.id, &user.id)
+    }
+
+    pub fn is_admin(&self, user_id: &str) -> Result<bool, Error> {
+        let user = self.users.find(user_id)?;
+
+        Ok(user.is_some_and(|user| user.is_admin))
+    }
+
     pub fn find(&self, user_id: &str) -> Result<User, Error> {
-        let mut conn = self.db_pool.get()?;
+        self.users
+            .find(user_id)?
+            .ok_or(Error::NotFound(Resource::User))
+    }
 
-        let user = users::table
-            .filter(users::id.eq(user_id))
-            .limit(1)
-            .get_result::<User>(&mut conn)
-            .map_err(Error::Db)?;
+    /// Removes or anonymises everything stored about a user, the user leaves lobbies that haven't
+    /// started and hosted lobbies go to the player who joined first, if there is one.
+    pub fn delete_account(&self, user_id: &str) -> Result<(), Error> {
+        let anonymous_id = format!("deleted:{}", uuid::Uuid::new_v4());
 
-        Ok(user)
+        self.users
+            .delete_account(user_id, &anonymous_id, DELETED_USER_NAME)
+    }
+
+    pub fn export_data(&self, user_id: &str) -> Result<UserDataExport, Error> {
+        self.users
+            .export_data(user_id)?
+            .ok_or(Error::NotFound(Resource::User))
     }
 }
 
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use serde::Deserialize;
+
+use crate::services::{blocking, session::SessionService};
+
+type SessionState = HashMap<String, String>;
+
+/// Keeps the session state in Redis, the cookie only holds the session key.
+///
+/// Sessions of logged in users are indexed by their user id, which is what makes
+/// [`SessionService::revoke_all`] possible.
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    sessions: SessionService,
+}
+
+impl RedisSessionStore {
+    pub fn new(sessions: SessionService) -> Self {
+        Self { sessions }
+    }
+}
+
+/// The part of [`crate::auth::UserInfo`] needed to index a session.
+#[derive(Deserialize)]
+struct SessionUser {
+    user: SessionUserId,
+}
+
+#[derive(Deserialize)]
+struct SessionUserId {
+    id: String,
+}
+
+fn user_id(state: &SessionState) -> Option<String> {
+    let user_info = state.get("user_info")?;
+
+    serde_json::from_str::<SessionUser>(user_info)
+        .ok()
+        .map(|user_info| user_info.user.id)
+}
+
+fn generate_session_key() -> SessionKey {
+    let session_key = OsRng
+        .sample_iter(Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect::<String>();
+
+    session_key.try_into().unwrap()
+}
+
+fn ttl_seconds(ttl: &Duration) -> u64 {
+    ttl.whole_seconds().max(1) as u64
+}
+
+impl SessionStore for RedisSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let sessions = self.sessions.clone();
+        let session_key = session_key.as_ref().to_owned();
+
+        let state = blocking(move || sessions.load(&session_key))
+            .await
+            .map_err(|err| LoadError::Other(anyhow::anyhow!("{err}")))?;
+
+        match state {
+            Some(state) => serde_json::from_str(&state)
+                .map(Some)
+                .map_err(|err| LoadError::Deserialization(err.into())),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_string(&session_state)
+            .map_err(|err| SaveError::Serialization(err.into()))?;
+        let session_key = generate_session_key();
+        let (sessions, key) = (self.sessions.clone(), session_key.as_ref().to_owned());
+        let (user_id, ttl) = (user_id(&session_state), ttl_seconds(ttl));
+
+        blocking(move || sessions.save(&key, &state, user_id.as_deref(), ttl))
+            .await
+            .map_err(|err| SaveError::Other(anyhow::anyhow!("{err}")))?;
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let state = serde_json::to_string(&session_state)
+            .map_err(|err| UpdateError::Serialization(err.into()))?;
+
+        let (sessions, key) = (self.sessions.clone(), session_key.as_ref().to_owned());
+        let (user_id, ttl_secs) = (user_id(&session_state), ttl_seconds(ttl));
+
+        let updated = blocking(move || sessions.update(&key, &state, user_id.as_deref(), ttl_secs))
+            .await
+            .map_err(|err| UpdateError::Other(anyhow::anyhow!("{err}")))?;
+
+        if updated {
+            return Ok(session_key);
+        }
+
+        // the session expired or was revoked while the request was handled, so it starts over
+        // under a new key - without the login, a revoked session must not come back to life
+        let mut session_state = session_state;
+        session_state.remove("user_info");
+
+        self.save(session_state, ttl)
+            .await
+            .map_err(|err| match err {
+                SaveError::Serialization(err) => UpdateError::Serialization(err),
+                SaveError::Other(err) => UpdateError::Other(err),
+            })
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        let (sessions, key) = (self.sessions.clone(), session_key.as_ref().to_owned());
+        let ttl = ttl_seconds(ttl);
+
+        blocking(move || sessions.update_ttl(&key, ttl))
+            .await
+            .map_err(|err| anyhow::anyhow!("{err}"))
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        let user_id = self
+            .load(session_key)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|state| user_id(&state));
+
+        let (sessions, key) = (self.sessions.clone(), session_key.as_ref().to_owned());
+
+        blocking(move || sessions.delete(&key, user_id.as_deref()))
+            .await
+            .map_err(|err| anyhow::anyhow!("{err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::user_id;
+
+    #[test]
+    fn indexes_sessions_by_logged_in_user() {
+        let mut state = HashMap::new();
+        state.insert("oidc-request".to_string(), "{}".to_string());
+
+        assert_eq!(user_id(&state), None);
+
+        state.insert(
+            "user_info".to_string(),
+            r#"{"provider":null,"claims":null,"token":null,"user":{"id":"guest:1","name":"lazy dog"}}"#
+                .to_string(),
+        );
+
+        assert_eq!(user_id(&state), Some("guest:1".to_string()));
+    }
+}
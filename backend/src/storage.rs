@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use crate::services::Error;
+
+/// Where uploaded files such as avatars are kept, addressed by a flat key like `<uuid>.png`.
+pub trait Storage: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error>;
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    fn delete(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Keys end up in paths and urls, so only a conservative set of characters is allowed.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 100
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Stores files in a directory of the local filesystem.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+
+        std::fs::create_dir_all(&root)?;
+
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        if !is_valid_key(key) {
+            return Err(Error::InvalidInput(format!("invalid storage key {key}")));
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        std::fs::write(self.path(key)?, data).map_err(Error::Storage)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match std::fs::read(self.path(key)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::Storage(err)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Error> {
+        match std::fs::remove_file(self.path(key)?) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::Storage(err)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Logs as structured tracing events, printed as text or JSON lines. `RUST_LOG` picks what is
+//! logged, e.g. `info` or `info,grooveguessr_backend=debug` to also see spans of nested fields.
+//!
+//! Every HTTP request gets a span with a `request_id`, every GraphQL operation one with its name
+//! and the `user_id`, and every field one of its own. Top-level fields also record the
+//! `lobby_id` they work on and log a warning when they fail, so a player's failing `guess` shows
+//! up with the request, user and lobby it belongs to. `log` records, e.g. of actix or the
+//! services, are logged within the same spans.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_graphql::async_trait::async_trait;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextResolve, ResolveInfo,
+};
+use async_graphql::{Response, ServerResult, Value};
+use tracing::field::Empty;
+use tracing::{debug_span, info_span, Instrument, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::auth::UserInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// human-readable lines with the fields of all enclosing spans
+    Text,
+    /// one JSON object per event, for log aggregation
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("expected text or json, got {other}")),
+        }
+    }
+}
+
+/// Sets up the global subscriber, logging `info` and above unless `RUST_LOG` says otherwise.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+}
+
+/// Adds the lobby to the span of the top-level field being resolved, does nothing outside of one.
+pub fn record_lobby_id(lobby_id: &str) {
+    Span::current().record("lobby_id", lobby_id);
+}
+
+/// Spans for GraphQL operations and their fields.
+pub struct GraphQLTracing;
+
+impl ExtensionFactory for GraphQLTracing {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLTracingExtension)
+    }
+}
+
+struct GraphQLTracingExtension;
+
+#[async_trait]
+impl Extension for GraphQLTracingExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let user_id = ctx.data_opt::<UserInfo>().map(|info| info.user.id.as_str());
+        let span = info_span!(
+            "graphql",
+            operation = operation_name.unwrap_or("anonymous"),
+            user_id
+        );
+
+        next.run(ctx, operation_name).instrument(span).await
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if info.is_for_introspection {
+            return next.run(ctx, info).await;
+        }
+
+        if !matches!(info.parent_type, "Query" | "Mutation") {
+            let span = debug_span!("resolve", field = %info.path_node);
+
+            return next.run(ctx, info).instrument(span).await;
+        }
+
+        let field = info.name;
+        let span = info_span!("resolve", field, lobby_id = Empty);
+        let result = next.run(ctx, info).instrument(span.clone()).await;
+
+        if let Err(ref err) = result {
+            let code = match err.extensions.as_ref().and_then(|e| e.get("code")) {
+                Some(Value::String(code)) => code.as_str(),
+                _ => "UNKNOWN",
+            };
+
+            span.in_scope(|| tracing::warn!(code, "{field} failed: {}", err.message));
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use async_graphql::{Request, Variables};
+    use serde_json::json;
+
+    use super::LogFormat;
+    use crate::auth::UserInfo;
+    use crate::config::GameConfig;
+    use crate::metrics::Metrics;
+    use crate::models::user::User;
+    use crate::repositories::Repositories;
+    use crate::services::Services;
+    use crate::{schema_builder, NamePolicy};
+
+    /// Collects everything logged.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn user(name: &str) -> User {
+        User {
+            id: format!("guest:{name}"),
+            name: name.to_owned(),
+            email: None,
+            created_at: chrono::Utc::now().naive_utc(),
+            is_guest: true,
+            is_admin: false,
+            avatar: None,
+            bio: None,
+            genres: String::new(),
+        }
+    }
+
+    #[test]
+    fn parses_log_formats() {
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("Text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert_eq!(
+            "yaml".parse::<LogFormat>(),
+            Err("expected text or json, got yaml".to_owned())
+        );
+    }
+
+    /// The lobby id is recorded on the blocking thread pool, which only sees the global
+    /// subscriber, so this is the only test setting one.
+    #[actix_web::test]
+    async fn failing_mutations_are_logged_with_user_and_lobby() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        tracing::subscriber::set_global_default(
+            tracing_subscriber::fmt()
+                .with_ansi(false)
+                .with_writer(move || writer.clone())
+                .finish(),
+        )
+        .unwrap();
+
+        let repositories = Repositories::in_memory();
+        let metrics = Metrics::new();
+        let services = Services::new(
+            &repositories,
+            Arc::new(NamePolicy::default()),
+            &GameConfig::default(),
+            metrics.clone(),
+        );
+        let schema =
+            schema_builder(&repositories, &services, GameConfig::default(), metrics).finish();
+
+        let (alice, bob) = (user("alice"), user("bob"));
+        repositories.users.register(&alice).unwrap();
+        repositories.users.register(&bob).unwrap();
+
+        let created = schema
+            .execute(Request::new("mutation { createLobby { id } }").data(UserInfo::guest(alice)))
+            .await
+            .data
+            .into_json()
+            .unwrap();
+        let id = created["createLobby"]["id"].as_str().unwrap();
+
+        let response = schema
+            .execute(
+                Request::new("mutation Start($id: String!) { startGame(id: $id) { id } }")
+                    .variables(Variables::from_json(json!({ "id": id })))
+                    .data(UserInfo::guest(bob)),
+            )
+            .await;
+        assert!(response.is_err());
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let line = logs
+            .lines()
+            .find(|line| line.contains("startGame failed"))
+            .unwrap_or_else(|| panic!("no failure logged:\n{logs}"));
+
+        assert!(line.contains("operation=\"Start\""), "{line}");
+        assert!(line.contains("user_id=\"guest:bob\""), "{line}");
+        assert!(line.contains(&format!("lobby_id=\"{id}\"")), "{line}");
+        assert!(line.contains("code=\"NOT_HOST\""), "{line}");
+    }
+}
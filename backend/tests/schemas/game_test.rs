@@ -0,0 +1,153 @@
+use serde_json::json;
+
+use grooveguessr_backend::config::GameConfig;
+
+use crate::harness::{TestApp, MY_GAMES};
+
+const GAME: &str = "query($id: String!) { game(id: $id) { id players { playerId } } }";
+
+const COMPATIBILITY: &str = "query($id: String!) { compatibility(lobbyId: $id) { score } }";
+
+#[actix_web::test]
+async fn finished_games_are_archived_for_their_players_only() {
+    for app in TestApp::all() {
+        let alice = app.player("alice");
+        let bob = app.player("bob");
+        let carol = app.player("carol");
+
+        let id = alice.create_lobby().await["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.join_lobby(&id).await;
+        alice.set_content(&id, "https://example.com/alice").await;
+        bob.set_content(&id, "https://example.com/bob").await;
+        alice.start_game(&id).await;
+        alice.forward(&id).await;
+
+        // nothing is archived before the last round is over
+        assert_eq!(alice.ok(MY_GAMES, json!({})).await["myGames"]["total"], 0);
+
+        let finished = alice.forward(&id).await;
+        assert!(!finished["finishedAt"].is_null());
+
+        for player in [&alice, &bob] {
+            let games = player.ok(MY_GAMES, json!({})).await;
+            assert_eq!(games["myGames"]["total"], 1, "{}", app.backend);
+            assert_eq!(games["myGames"]["games"][0]["id"], id.as_str());
+            assert_eq!(
+                games["myGames"]["games"][0]["players"]
+                    .as_array()
+                    .unwrap()
+                    .len(),
+                2
+            );
+
+            let game = player.ok(GAME, json!({ "id": id })).await;
+            assert_eq!(game["game"]["id"], id.as_str());
+
+            let compatibility = player.ok(COMPATIBILITY, json!({ "id": id })).await;
+            assert!(compatibility["compatibility"].is_array());
+        }
+
+        assert_eq!(carol.ok(MY_GAMES, json!({})).await["myGames"]["total"], 0);
+        assert_eq!(
+            carol.error_code(GAME, json!({ "id": id })).await,
+            "GAME_NOT_FOUND"
+        );
+        assert_eq!(
+            carol.error_code(COMPATIBILITY, json!({ "id": id })).await,
+            "GAME_NOT_FOUND"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn guesses_after_a_skipped_round_count_for_their_own_round() {
+    for app in TestApp::all() {
+        let alice = app.player("alice");
+        let bob = app.player("bob");
+
+        let id = alice.create_lobby().await["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.join_lobby(&id).await;
+        alice.set_content(&id, "https://example.com/alice").await;
+        bob.set_content(&id, "https://example.com/bob").await;
+
+        let first_owner = alice.start_game(&id).await["currentUserId"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        alice.guess(&id, 0, &first_owner).await;
+        alice.forward(&id).await;
+
+        // bob sits out the first round and only guesses the second
+        let second_owner = alice.lobby(&id).await["currentUserId"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.guess(&id, 1, &second_owner).await;
+        alice.forward(&id).await;
+
+        let games = bob
+            .ok(
+                "{ myGames { games { players { playerId score guesses } } } }",
+                json!({}),
+            )
+            .await;
+        let players = games["myGames"]["games"][0]["players"].as_array().unwrap();
+        let bobs = players.iter().find(|p| p["playerId"] == bob.id()).unwrap();
+
+        assert_eq!(
+            bobs["guesses"],
+            json!(["", second_owner]),
+            "{}",
+            app.backend
+        );
+        // own rounds don't score
+        let score = if second_owner == bob.id() { 0 } else { 1 };
+        assert_eq!(bobs["score"], score, "{}", app.backend);
+    }
+}
+
+#[actix_web::test]
+async fn players_who_leave_a_running_game_still_count() {
+    let game_config = GameConfig {
+        presence_ttl_seconds: 1,
+        ..GameConfig::default()
+    };
+
+    for app in TestApp::all_with(game_config) {
+        let alice = app.player("alice");
+        let bob = app.player("bob");
+
+        let id = alice.create_lobby().await["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.join_lobby(&id).await;
+        alice.set_content(&id, "https://example.com/alice").await;
+        bob.set_content(&id, "https://example.com/bob").await;
+
+        let first_owner = alice.start_game(&id).await["currentUserId"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.guess(&id, 0, &first_owner).await;
+
+        // bob closes the tab while alice plays on
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        alice.forward(&id).await;
+        let finished = alice.forward(&id).await;
+        assert!(!finished["finishedAt"].is_null());
+
+        let games = bob.ok(MY_GAMES, json!({})).await;
+        assert_eq!(games["myGames"]["total"], 1, "{}", app.backend);
+
+        let players = games["myGames"]["games"][0]["players"].as_array().unwrap();
+        let bobs = players.iter().find(|p| p["playerId"] == bob.id()).unwrap();
+        assert_eq!(bobs["guesses"], json!([first_owner]), "{}", app.backend);
+    }
+}
@@ -0,0 +1,342 @@
+use std::sync::Arc;
+
+use async_graphql::{Request, ServerError, Variables};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel_migrations::MigrationHarness;
+use serde_json::{json, Value};
+
+use grooveguessr_backend::auth::UserInfo;
+use grooveguessr_backend::config::GameConfig;
+use grooveguessr_backend::metrics::Metrics;
+use grooveguessr_backend::repositories::{MemoryRepository, PgRepository, Repositories};
+use grooveguessr_backend::services::Services;
+use grooveguessr_backend::{schema_builder, NamePolicy, ProjectSchema, User, MIGRATIONS};
+
+/// A database created for a single test, dropped again along with the app using it.
+struct ThrowawayDatabase {
+    server_url: String,
+    name: String,
+}
+
+impl ThrowawayDatabase {
+    fn create(server_url: &str) -> (Self, Pool<ConnectionManager<PgConnection>>) {
+        let name = format!("grooveguessr_test_{}", uuid::Uuid::new_v4().simple());
+
+        let mut conn = PgConnection::establish(server_url)
+            .unwrap_or_else(|err| panic!("Error connecting to {server_url}: {err}"));
+        diesel::sql_query(format!("CREATE DATABASE {name}"))
+            .execute(&mut conn)
+            .unwrap();
+
+        let mut database_url = url::Url::parse(server_url).unwrap();
+        database_url.set_path(&name);
+
+        let db_pool = Pool::builder()
+            .max_size(4)
+            .build(ConnectionManager::<PgConnection>::new(
+                database_url.as_str(),
+            ))
+            .unwrap();
+        db_pool
+            .get()
+            .unwrap()
+            .run_pending_migrations(MIGRATIONS)
+            .unwrap();
+
+        let database = Self {
+            server_url: server_url.to_owned(),
+            name,
+        };
+
+        (database, db_pool)
+    }
+}
+
+impl Drop for ThrowawayDatabase {
+    fn drop(&mut self) {
+        let dropped = PgConnection::establish(&self.server_url).and_then(|mut conn| {
+            diesel::sql_query(format!(
+                "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+                self.name
+            ))
+            .execute(&mut conn)
+            .map_err(|err| ConnectionError::BadConnection(err.to_string()))
+        });
+
+        if let Err(err) = dropped {
+            eprintln!("Error dropping test database {}: {err}", self.name);
+        }
+    }
+}
+
+/// The schema on top of one set of repositories, along with the users playing on it.
+pub struct TestApp {
+    pub backend: &'static str,
+    pub metrics: Metrics,
+    pub repositories: Repositories,
+    schema: ProjectSchema,
+    // declared last, so the schema lets go of its connections before the database is dropped
+    _database: Option<ThrowawayDatabase>,
+}
+
+impl TestApp {
+    pub fn in_memory(game_config: GameConfig) -> Self {
+        Self::new("memory", Repositories::in_memory(), game_config, None)
+    }
+
+    /// Users, lobbies, games and stats in a fresh database on the server, presence in memory.
+    pub fn postgres(server_url: &str, game_config: GameConfig) -> Self {
+        let (database, db_pool) = ThrowawayDatabase::create(server_url);
+        let postgres = Arc::new(PgRepository::new(db_pool));
+
+        let repositories = Repositories {
+            users: postgres.clone(),
+            lobbies: postgres.clone(),
+            games: postgres.clone(),
+            stats: postgres,
+            presence: Arc::new(MemoryRepository::default()),
+        };
+
+        Self::new("postgres", repositories, game_config, Some(database))
+    }
+
+    /// The in-memory app, plus one on Postgres if `TEST_DATABASE_URL` is set.
+    pub fn all() -> Vec<Self> {
+        Self::all_with(GameConfig::default())
+    }
+
+    /// Like [`TestApp::all`], with games played by other rules.
+    pub fn all_with(game_config: GameConfig) -> Vec<Self> {
+        let mut apps = vec![Self::in_memory(game_config.clone())];
+
+        match std::env::var("TEST_DATABASE_URL") {
+            Ok(server_url) => apps.push(Self::postgres(&server_url, game_config)),
+            Err(_) => eprintln!("TEST_DATABASE_URL is not set, skipping Postgres"),
+        }
+
+        apps
+    }
+
+    fn new(
+        backend: &'static str,
+        repositories: Repositories,
+        game_config: GameConfig,
+        database: Option<ThrowawayDatabase>,
+    ) -> Self {
+        let metrics = Metrics::new();
+        let services = Services::new(
+            &repositories,
+            Arc::new(NamePolicy::default()),
+            &game_config,
+            metrics.clone(),
+        );
+        let schema =
+            schema_builder(&repositories, &services, game_config, metrics.clone()).finish();
+
+        Self {
+            backend,
+            metrics,
+            schema,
+            repositories,
+            _database: database,
+        }
+    }
+
+    /// Registers a guest called `name` to send requests as.
+    pub fn player(&self, name: &str) -> Client<'_> {
+        self.login(user(&format!("guest:{}", uuid::Uuid::new_v4()), name, true))
+    }
+
+    /// Registers the user to send requests as.
+    pub fn login(&self, user: User) -> Client<'_> {
+        self.repositories.users.register(&user).unwrap();
+
+        Client { app: self, user }
+    }
+}
+
+pub fn user(id: &str, name: &str, is_guest: bool) -> User {
+    User {
+        id: id.to_owned(),
+        name: name.to_owned(),
+        email: None,
+        created_at: chrono::Utc::now().naive_utc(),
+        is_guest,
+        is_admin: false,
+        avatar: None,
+        bio: None,
+        genres: String::new(),
+    }
+}
+
+/// Plays a whole game hosted by the first player, everyone guesses every round right. Returns
+/// the id of the lobby.
+pub async fn play_game(players: &[&Client<'_>]) -> String {
+    let host = players[0];
+    let id = host.create_lobby().await["id"].as_str().unwrap().to_owned();
+
+    for player in &players[1..] {
+        player.join_lobby(&id).await;
+    }
+
+    for player in players {
+        let url = format!("https://example.com/{}", player.user.name);
+        player.set_content(&id, &url).await;
+    }
+
+    let mut lobby = host.start_game(&id).await;
+
+    for round_index in 0..players.len() {
+        let owner_id = lobby["currentUserId"].as_str().unwrap().to_owned();
+
+        for player in players {
+            player.guess(&id, round_index, &owner_id).await;
+        }
+
+        lobby = host.forward(&id).await;
+    }
+
+    assert!(!lobby["finishedAt"].is_null());
+
+    id
+}
+
+/// Sends requests to the schema as one user, the way a logged in browser would.
+pub struct Client<'a> {
+    app: &'a TestApp,
+    pub user: User,
+}
+
+impl Client<'_> {
+    pub fn id(&self) -> &str {
+        &self.user.id
+    }
+
+    pub async fn execute(&self, query: &str, variables: Value) -> Result<Value, Vec<ServerError>> {
+        let response = self
+            .app
+            .schema
+            .execute(
+                Request::new(query)
+                    .variables(Variables::from_json(variables))
+                    .data(UserInfo::guest(self.user.clone())),
+            )
+            .await;
+
+        if response.errors.is_empty() {
+            Ok(response.data.into_json().unwrap())
+        } else {
+            Err(response.errors)
+        }
+    }
+
+    /// Runs a request that has to succeed.
+    pub async fn ok(&self, query: &str, variables: Value) -> Value {
+        self.execute(query, variables)
+            .await
+            .unwrap_or_else(|errors| {
+                panic!(
+                    "{} on {}: {query} failed with {errors:?}",
+                    self.user.name, self.app.backend
+                )
+            })
+    }
+
+    /// Runs a request that has to fail and returns the code of its error.
+    pub async fn error_code(&self, query: &str, variables: Value) -> String {
+        match self.execute(query, variables).await {
+            Ok(data) => panic!(
+                "{} on {}: {query} succeeded with {data}",
+                self.user.name, self.app.backend
+            ),
+            Err(errors) => errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("code"))
+                .map(|code| code.to_string().trim_matches('"').to_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub async fn create_lobby(&self) -> Value {
+        self.ok(
+            &format!("mutation {{ createLobby {{ {LOBBY_FIELDS} }} }}"),
+            json!({}),
+        )
+        .await["createLobby"]
+            .clone()
+    }
+
+    pub async fn lobby(&self, id: &str) -> Value {
+        self.ok(
+            &format!("query($id: String!) {{ lobby(id: $id) {{ {LOBBY_FIELDS} }} }}"),
+            json!({ "id": id }),
+        )
+        .await["lobby"]
+            .clone()
+    }
+
+    pub async fn join_lobby(&self, id: &str) -> Value {
+        self.lobby_mutation("joinLobby(id: $id)", "$id: String!", json!({ "id": id }))
+            .await
+    }
+
+    pub async fn set_content(&self, id: &str, url: &str) -> Value {
+        self.lobby_mutation(
+            "setContent(id: $id, url: $url)",
+            "$id: String!, $url: String!",
+            json!({ "id": id, "url": url }),
+        )
+        .await
+    }
+
+    pub async fn start_game(&self, id: &str) -> Value {
+        self.lobby_mutation("startGame(id: $id)", "$id: String!", json!({ "id": id }))
+            .await
+    }
+
+    pub async fn guess(&self, id: &str, round_index: usize, guessed_user_id: &str) -> Value {
+        self.lobby_mutation(
+            "guess(id: $id, roundIndex: $roundIndex, guessedUserId: $guessedUserId)",
+            "$id: String!, $roundIndex: Int!, $guessedUserId: String!",
+            json!({ "id": id, "roundIndex": round_index, "guessedUserId": guessed_user_id }),
+        )
+        .await
+    }
+
+    pub async fn forward(&self, id: &str) -> Value {
+        self.lobby_mutation("forward(id: $id)", "$id: String!", json!({ "id": id }))
+            .await
+    }
+
+    pub async fn rematch(&self, id: &str, keep_content: bool) -> Value {
+        self.lobby_mutation(
+            "rematch(id: $id, keepContent: $keepContent)",
+            "$id: String!, $keepContent: Boolean!",
+            json!({ "id": id, "keepContent": keep_content }),
+        )
+        .await
+    }
+
+    /// Runs a mutation returning a lobby, e.g. `forward(id: $id)`, and returns that lobby.
+    async fn lobby_mutation(&self, call: &str, parameters: &str, variables: Value) -> Value {
+        let name = call.split('(').next().unwrap();
+
+        self.ok(
+            &format!("mutation({parameters}) {{ {call} {{ {LOBBY_FIELDS} }} }}"),
+            variables,
+        )
+        .await[name]
+            .clone()
+    }
+}
+
+/// What the lobby page asks for.
+pub const LOBBY_FIELDS: &str = "id hostId startedAt finishedAt currentUserId roundIndex \
+    rematchId guesses host { id name } players { id name isReady } content { data } \
+    currentContent { userId data }";
+
+/// The games of the player, along with who played them.
+pub const MY_GAMES: &str = "{ myGames { total games { id hostId \
+    players { playerId guesses } rounds { ownerId } } } }";
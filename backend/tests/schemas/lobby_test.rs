@@ -0,0 +1,334 @@
+use serde_json::{json, Value};
+
+use crate::harness::{Client, TestApp};
+
+fn ids(players: &Value) -> Vec<String> {
+    players
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|player| player["id"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+fn url_of(client: &Client) -> String {
+    format!("https://www.youtube.com/watch?v={}", client.user.name)
+}
+
+/// Plays a game of three where alice always guesses right, bob always guesses alice and carol
+/// doesn't guess at all.
+async fn play_full_game(app: &TestApp) {
+    let alice = app.player("alice");
+    let bob = app.player("bob");
+    let carol = app.player("carol");
+
+    // create
+    let lobby = alice.create_lobby().await;
+    let id = lobby["id"].as_str().unwrap().to_owned();
+
+    assert_eq!(lobby["hostId"], alice.id());
+    assert_eq!(lobby["host"]["name"], "alice");
+    assert_eq!(ids(&lobby["players"]), vec![alice.id()]);
+    assert!(lobby["startedAt"].is_null());
+    assert!(lobby["roundIndex"].is_null());
+    assert!(lobby["content"].is_null());
+
+    // join
+    let lobby = bob.join_lobby(&id).await;
+    assert_eq!(ids(&lobby["players"]), vec![alice.id(), bob.id()]);
+
+    let lobby = carol.join_lobby(&id).await;
+    assert_eq!(
+        ids(&lobby["players"]),
+        vec![alice.id(), bob.id(), carol.id()]
+    );
+    assert_eq!(lobby["hostId"], alice.id());
+    assert!(lobby["players"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|player| player["isReady"] == false));
+
+    // joining twice changes nothing
+    let lobby = bob.join_lobby(&id).await;
+    assert_eq!(lobby["players"].as_array().unwrap().len(), 3);
+
+    // set content
+    for player in [&alice, &bob] {
+        let lobby = player.set_content(&id, &url_of(player)).await;
+        assert_eq!(lobby["content"]["data"], url_of(player));
+    }
+
+    let start = "mutation($id: String!) { startGame(id: $id) { id } }";
+    assert_eq!(
+        alice.error_code(start, json!({ "id": id })).await,
+        "NOT_EVERYONE_HAS_CONTENT"
+    );
+
+    // content can be changed until the game starts, only the latest counts
+    carol.set_content(&id, "https://example.com/draft").await;
+    let lobby = carol.set_content(&id, &url_of(&carol)).await;
+    assert_eq!(lobby["content"]["data"], url_of(&carol));
+    assert_eq!(alice.lobby(&id).await["content"]["data"], url_of(&alice));
+
+    // start
+    assert_eq!(bob.error_code(start, json!({ "id": id })).await, "NOT_HOST");
+
+    let lobby = alice.start_game(&id).await;
+    assert!(!lobby["startedAt"].is_null());
+    assert!(lobby["finishedAt"].is_null());
+    assert_eq!(lobby["roundIndex"], 0);
+
+    assert_eq!(
+        alice.error_code(start, json!({ "id": id })).await,
+        "GAME_ALREADY_STARTED"
+    );
+
+    // only players get to see the playlist, and only once the game is over
+    let playlist_query =
+        "query($id: String!) { lobby(id: $id) { playlist { tracks { submitterId url } } } }";
+    let dave = app.player("dave");
+    assert_eq!(
+        dave.error_code(playlist_query, json!({ "id": id })).await,
+        "NOT_A_PLAYER"
+    );
+    assert_eq!(
+        bob.error_code(playlist_query, json!({ "id": id })).await,
+        "GAME_NOT_FINISHED"
+    );
+
+    let players = [&alice, &bob, &carol];
+    let mut owners = Vec::new();
+    let mut lobby = lobby;
+
+    // guess and forward, round by round
+    for round_index in 0..3 {
+        let owner_id = lobby["currentUserId"].as_str().unwrap().to_owned();
+        let owner = players.iter().find(|p| p.id() == owner_id).unwrap();
+
+        assert_eq!(lobby["roundIndex"], round_index);
+        assert!(!owners.contains(&owner_id), "{owner_id} played twice");
+        assert_eq!(lobby["currentContent"]["userId"], owner_id.as_str());
+        assert_eq!(lobby["currentContent"]["data"], url_of(owner));
+
+        let guessed = alice.guess(&id, round_index, &owner_id).await;
+        assert_eq!(
+            guessed["guesses"].as_array().unwrap().len(),
+            round_index + 1
+        );
+        assert_eq!(guessed["guesses"][round_index], owner_id.as_str());
+
+        // a changed mind replaces the guess of the round
+        bob.guess(&id, round_index, carol.id()).await;
+        let guessed = bob.guess(&id, round_index, alice.id()).await;
+        assert_eq!(
+            guessed["guesses"].as_array().unwrap().len(),
+            round_index + 1
+        );
+        assert_eq!(guessed["guesses"][round_index], alice.id());
+
+        assert_eq!(carol.lobby(&id).await["guesses"], json!([]));
+
+        let forward = "mutation($id: String!) { forward(id: $id) { id } }";
+        assert_eq!(
+            carol.error_code(forward, json!({ "id": id })).await,
+            "NOT_HOST"
+        );
+
+        owners.push(owner_id);
+        lobby = alice.forward(&id).await;
+
+        if round_index < 2 {
+            assert!(lobby["finishedAt"].is_null());
+            assert_eq!(lobby["roundIndex"], round_index + 1);
+        }
+    }
+
+    // the last forward ends the game
+    assert!(!lobby["finishedAt"].is_null());
+    assert_eq!(lobby["currentUserId"], owners[2].as_str());
+    assert_eq!(
+        alice
+            .error_code(
+                "mutation($id: String!) { forward(id: $id) { id } }",
+                json!({ "id": id })
+            )
+            .await,
+        "GAME_ALREADY_FINISHED"
+    );
+
+    assert_eq!(
+        dave.error_code(playlist_query, json!({ "id": id })).await,
+        "NOT_A_PLAYER"
+    );
+
+    let playlist = bob.ok(playlist_query, json!({ "id": id })).await;
+    let tracks = playlist["lobby"]["playlist"]["tracks"].as_array().unwrap();
+    assert_eq!(
+        tracks
+            .iter()
+            .map(|track| track["submitterId"].as_str().unwrap())
+            .collect::<Vec<&str>>(),
+        owners
+    );
+
+    // the archived game
+    let games = carol
+        .ok(
+            "{ myGames { total hasMore games { id hostName \
+            players { playerId name score isWinner guesses } \
+            winners { playerId } rounds { roundIndex ownerId contentData } } } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(games["myGames"]["total"], 1);
+    assert_eq!(games["myGames"]["hasMore"], false);
+
+    let game = &games["myGames"]["games"][0];
+    assert_eq!(game["hostName"], "alice");
+    assert_eq!(game["winners"], json!([{ "playerId": alice.id() }]));
+    assert_eq!(
+        game["players"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| (p["name"].as_str().unwrap(), p["score"].as_i64().unwrap()))
+            .collect::<Vec<(&str, i64)>>(),
+        vec![("alice", 2), ("bob", 1), ("carol", 0)]
+    );
+    assert_eq!(game["players"][0]["guesses"], json!(owners));
+    assert_eq!(game["players"][2]["guesses"], json!([]));
+
+    let rounds = game["rounds"].as_array().unwrap();
+    assert_eq!(rounds.len(), 3);
+    for (round_index, round) in rounds.iter().enumerate() {
+        let owner = players
+            .iter()
+            .find(|p| p.id() == owners[round_index])
+            .unwrap();
+
+        assert_eq!(round["roundIndex"], round_index);
+        assert_eq!(round["ownerId"], owner.id());
+        assert_eq!(round["contentData"], url_of(owner));
+    }
+
+    // lifetime stats
+    let stats = "{ profile { stats { gamesPlayed gamesWon guessesMade guessesCorrect \
+        songsGuessed songsRecognised } } }";
+
+    assert_eq!(
+        alice.ok(stats, json!({})).await["profile"]["stats"],
+        json!({ "gamesPlayed": 1, "gamesWon": 1, "guessesMade": 2, "guessesCorrect": 2,
+            "songsGuessed": 1, "songsRecognised": 1 })
+    );
+    assert_eq!(
+        bob.ok(stats, json!({})).await["profile"]["stats"],
+        json!({ "gamesPlayed": 1, "gamesWon": 0, "guessesMade": 2, "guessesCorrect": 1,
+            "songsGuessed": 1, "songsRecognised": 1 })
+    );
+    assert_eq!(
+        carol.ok(stats, json!({})).await["profile"]["stats"],
+        json!({ "gamesPlayed": 1, "gamesWon": 0, "guessesMade": 0, "guessesCorrect": 0,
+            "songsGuessed": 2, "songsRecognised": 1 })
+    );
+
+    // alice and bob guessed every round, bob twice
+    assert_eq!(app.metrics.games_started.get(), 1);
+    assert_eq!(app.metrics.games_finished.get(), 1);
+    assert_eq!(app.metrics.guesses_submitted.get(), 9);
+}
+
+#[actix_web::test]
+async fn three_players_play_a_full_game() {
+    for app in TestApp::all() {
+        play_full_game(&app).await;
+    }
+}
+
+#[actix_web::test]
+async fn games_cant_be_played_out_of_order() {
+    for app in TestApp::all() {
+        let alice = app.player("alice");
+        let bob = app.player("bob");
+
+        assert_eq!(
+            alice
+                .error_code("query { lobby(id: \"does-not-exist\") { id } }", json!({}))
+                .await,
+            "LOBBY_NOT_FOUND"
+        );
+
+        let id = alice.create_lobby().await["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.join_lobby(&id).await;
+
+        assert_eq!(
+            alice
+                .error_code(
+                    "mutation($id: String!) { forward(id: $id) { id } }",
+                    json!({ "id": id })
+                )
+                .await,
+            "GAME_NOT_STARTED"
+        );
+        assert_eq!(
+            bob.error_code(
+                "mutation($id: String!) { rematch(id: $id) { id } }",
+                json!({ "id": id })
+            )
+            .await,
+            "GAME_NOT_FINISHED"
+        );
+        assert_eq!(
+            bob.error_code(
+                "mutation($id: String!) { guess(id: $id, roundIndex: 0, guessedUserId: \"nobody\") { id } }",
+                json!({ "id": id })
+            )
+            .await,
+            "USER_NOT_FOUND"
+        );
+
+        let lobby = alice.lobby(&id).await;
+        assert!(lobby["startedAt"].is_null());
+        assert_eq!(lobby["guesses"], json!([]));
+    }
+}
+
+#[actix_web::test]
+async fn finished_games_can_be_rematched() {
+    for app in TestApp::all() {
+        let alice = app.player("alice");
+        let bob = app.player("bob");
+
+        let id = alice.create_lobby().await["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        bob.join_lobby(&id).await;
+        alice.set_content(&id, &url_of(&alice)).await;
+        bob.set_content(&id, &url_of(&bob)).await;
+        alice.start_game(&id).await;
+        alice.forward(&id).await;
+        let finished = alice.forward(&id).await;
+        assert!(!finished["finishedAt"].is_null());
+
+        let rematch = bob.rematch(&id, true).await;
+        let rematch_id = rematch["id"].as_str().unwrap();
+
+        assert_ne!(rematch_id, id);
+        assert_eq!(rematch["hostId"], alice.id());
+        assert!(rematch["startedAt"].is_null());
+        assert_eq!(ids(&rematch["players"]), vec![alice.id(), bob.id()]);
+        assert_eq!(rematch["content"]["data"], url_of(&bob));
+        assert_eq!(alice.lobby(&id).await["rematchId"], rematch_id);
+
+        // everyone asking for a rematch ends up in the same one
+        assert_eq!(alice.rematch(&id, false).await["id"], rematch_id);
+
+        // carried content is enough to start right away
+        let started = alice.start_game(rematch_id).await;
+        assert_eq!(started["roundIndex"], 0);
+    }
+}
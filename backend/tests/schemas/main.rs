@@ -0,0 +1,8 @@
+//! Plays games through the GraphQL schema the way clients do, with every mutation going through
+//! the real services. Runs against the in-memory repositories, and additionally against a
+//! throwaway Postgres database if `TEST_DATABASE_URL` points to a server to create it on.
+
+mod game_test;
+mod harness;
+mod lobby_test;
+mod user_test;
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use serde_json::json;
+
+use grooveguessr_backend::services::user::UserService;
+use grooveguessr_backend::NamePolicy;
+
+use crate::harness::{play_game, user, TestApp, MY_GAMES};
+
+fn user_service(app: &TestApp) -> UserService {
+    UserService::new(
+        app.repositories.users.clone(),
+        Arc::new(NamePolicy::default()),
+    )
+}
+
+#[actix_web::test]
+async fn upgrading_a_guest_moves_its_history() {
+    for app in TestApp::all() {
+        let guest = app.player("alice");
+        // an id the guest's id is part of must not be touched
+        let bob = app.login(user(&format!("{}0", guest.id()), "bob", true));
+
+        let id = play_game(&[&guest, &bob]).await;
+
+        let account = app.login(user(
+            &format!("gitlab:{}", uuid::Uuid::new_v4()),
+            "alice",
+            false,
+        ));
+        user_service(&app)
+            .upgrade_guest(&guest.user, &account.user)
+            .unwrap();
+
+        let lobby = bob.lobby(&id).await;
+        assert_eq!(lobby["hostId"], account.id());
+
+        let games = account.ok(MY_GAMES, json!({})).await;
+        assert_eq!(games["myGames"]["total"], 1, "{}", app.backend);
+
+        let game = &games["myGames"]["games"][0];
+        assert_eq!(game["id"], id.as_str());
+        assert_eq!(game["hostId"], account.id());
+
+        let mut owners = game["rounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["ownerId"].as_str().unwrap().to_owned())
+            .collect::<Vec<String>>();
+        owners.sort();
+        let mut expected = vec![account.id().to_owned(), bob.id().to_owned()];
+        expected.sort();
+        assert_eq!(owners, expected, "{}", app.backend);
+
+        // everyone guessed right, so the guesses follow the order of the rounds
+        let rounds = game["rounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["ownerId"].clone())
+            .collect::<Vec<_>>();
+        for player in game["players"].as_array().unwrap() {
+            assert_eq!(player["guesses"], json!(rounds), "{}", app.backend);
+        }
+
+        assert_eq!(guest.ok(MY_GAMES, json!({})).await["myGames"]["total"], 0);
+    }
+}
+
+#[actix_web::test]
+async fn deleting_an_account_keeps_everyone_elses_history() {
+    for app in TestApp::all() {
+        let alice = app.player("alice");
+        // an id the deleted id is part of must not be touched
+        let bob = app.login(user(&format!("{}0", alice.id()), "bob", true));
+
+        let id = play_game(&[&alice, &bob]).await;
+        let alone = play_game(&[&alice]).await;
+
+        user_service(&app).delete_account(alice.id()).unwrap();
+
+        let games = bob.ok(MY_GAMES, json!({})).await;
+        let game = &games["myGames"]["games"][0];
+        assert_eq!(game["id"], id.as_str());
+
+        let anonymous_id = game["hostId"].as_str().unwrap();
+        assert!(anonymous_id.starts_with("deleted:"), "{anonymous_id}");
+
+        let mut owners = game["rounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["ownerId"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        owners.sort();
+        let mut expected = vec![anonymous_id, bob.id()];
+        expected.sort();
+        assert_eq!(owners, expected, "{}", app.backend);
+
+        let rounds = game["rounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["ownerId"].clone())
+            .collect::<Vec<_>>();
+        for player in game["players"].as_array().unwrap() {
+            assert_eq!(player["guesses"], json!(rounds), "{}", app.backend);
+        }
+
+        // nobody was left to take over, the lobby stays with an anonymous host
+        let lobby = app.repositories.lobbies.find(&alone).unwrap();
+        assert!(
+            lobby.is_some_and(|lobby| lobby.host_id.starts_with("deleted:")),
+            "{}",
+            app.backend
+        );
+    }
+}
+
+#[actix_web::test]
+async fn identities_belong_to_their_provider() {
+    for app in TestApp::all() {
+        let user_service = user_service(&app);
+        let login = |provider: &str, subject: &str| {
+            let mut new_user = user(&uuid::Uuid::new_v4().to_string(), "alice", false);
+            new_user.email = Some("alice@example.com".to_owned());
+
+            user_service
+                .register_identity(provider, subject, new_user)
+                .unwrap()
+        };
+
+        let gitlab = login("gitlab", "1234");
+        let github = login("github", "1234");
+
+        assert_ne!(gitlab.id, github.id, "{}", app.backend);
+        assert_eq!(login("gitlab", "1234").id, gitlab.id);
+
+        // the email already belongs to the first account, it doesn't link the second one to it
+        let other = login("google", "5678");
+        assert_ne!(other.id, gitlab.id);
+        assert_eq!(gitlab.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(github.email, None);
+        assert_eq!(other.email, None);
+    }
+}
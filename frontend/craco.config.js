@@ -2,7 +2,7 @@ module.exports = {
   devServer: {
     proxy: [
       {
-        context: ["/graphql", "/login", "/logout", "/auth_callback"],
+        context: ["/graphql", "/login", "/logout", "/auth_callback", "/avatars"],
         target: "http://127.0.0.1:8080",
       },
     ],
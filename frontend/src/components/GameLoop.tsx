@@ -172,7 +172,7 @@ export default function GameLoop(props: GameLoopProps) {
                     handleGuess(player.id);
                   }}
                 >
-                  <UserAvatar name={player.name} />
+                  <UserAvatar name={player.name} avatarUrl={player.avatarUrl} />
                   {guessed_id === player.id && (
                     <Chip label="Guessed" color="success" />
                   )}
@@ -5,9 +5,9 @@ import { gql, useMutation } from "@apollo/client";
 import { useState } from "react";
 import UserAvatar from "./UserAvatar";
 
-export const SET_NAME = gql`
-  mutation setName($name: String!) {
-    setName(name: $name) {
+export const UPDATE_PROFILE = gql`
+  mutation updateProfile($name: String!) {
+    updateProfile(input: { name: $name }) {
       name
     }
   }
@@ -19,7 +19,7 @@ type PlayersProps = {
 };
 
 function PlayerName(props: { player: Player; editable: boolean }) {
-  const [setName] = useMutation(SET_NAME);
+  const [setName] = useMutation(UPDATE_PROFILE);
   const [editName, setEditName] = useState<string | null>(null);
   const [editError, setEditError] = useState<string | null>(null);
 
@@ -121,7 +121,10 @@ function PlayerName(props: { player: Player; editable: boolean }) {
             alignItems="center"
             onClick={startEditing}
           >
-            <UserAvatar name={props.player.name} />
+            <UserAvatar
+              name={props.player.name}
+              avatarUrl={props.player.avatarUrl}
+            />
           </Stack>
         )}
       </Stack>
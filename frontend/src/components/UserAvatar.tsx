@@ -3,12 +3,16 @@ import { stringToColor } from "../utils";
 
 type UserAvatarProps = {
   name: string;
+  avatarUrl?: string | null;
 };
 
 export default function UserAvatar(props: UserAvatarProps) {
   return (
     <Stack direction="row" alignItems="center">
-      <Avatar sx={{ backgroundColor: stringToColor(props.name) }}>
+      <Avatar
+        src={props.avatarUrl ?? undefined}
+        sx={{ backgroundColor: stringToColor(props.name) }}
+      >
         {props.name[0].toUpperCase()}
       </Avatar>
       {props.name}
@@ -1,5 +1,6 @@
 export type Player = {
   id: string;
   name: string;
+  avatarUrl?: string | null;
   isReady: boolean;
 };
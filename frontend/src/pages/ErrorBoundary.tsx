@@ -10,14 +10,17 @@ export default function ErrorBoundary({ error }: { error: any }) {
     if (error.extraInfo) {
       return <h1>Network error.</h1>;
     } else if (
-      error.graphQLErrors.map((e) => e.extensions.code === 404).length > 0
+      error.graphQLErrors.some((e) =>
+        String(e.extensions.code).endsWith("NOT_FOUND"),
+      )
     ) {
       return <h1>Not Found.</h1>;
-    } else if (
-      error.graphQLErrors.map((e) => e.extensions.code === 401).length > 0
-    ) {
+    } else if ((error.networkError as any)?.statusCode === 401) {
+      // the session ended, the same as the redirect of the Apollo link
       navigate("/login");
-    } else if (error.extraInfo) return <h1>{error.message}</h1>;
+    } else {
+      return <h1>{error.message}</h1>;
+    }
   } else if (error instanceof Error) {
     return <h1>{error.message}</h1>;
   }
@@ -14,6 +14,7 @@ export const GET_LOBBY = gql`
       players {
         id
         name
+        avatarUrl
         isReady
       }
       content {